@@ -22,7 +22,13 @@
 /// The `ForeignKey` is optional.
 ///
 /// There is no value when the `join()` method is not called.
+/// A table referring to itself uses a boxed related object: `ForeignKey<Box<Table>>`.
 pub type ForeignKey<T> = Option<T>;
 
+/// A `ManyToMany` relation is stored in a junction table.
+///
+/// It is empty when the `join()` or `prefetch()` method is not called.
+pub type ManyToMany<T> = Vec<T>;
+
 /// A `PrimaryKey` is a 4-byte integer.
 pub type PrimaryKey = i32;
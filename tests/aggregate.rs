@@ -21,7 +21,7 @@
 extern crate postgres;
 extern crate tql;
 
-use tql::PrimaryKey;
+use tql::{ForeignKey, PrimaryKey};
 
 #[SqlTable]
 #[allow(dead_code)]
@@ -29,36 +29,157 @@ struct Table {
     id: PrimaryKey,
     field1: String,
     field2: i32,
+    field3: bool,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct Author {
+    id: PrimaryKey,
+    name: String,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct Post {
+    id: PrimaryKey,
+    author: ForeignKey<Author>,
+    title: String,
+    views: i32,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct TreeTable {
+    id: PrimaryKey,
+    field1: String,
+    parent: Option<ForeignKey<Box<TreeTable>>>,
 }
 
 #[test]
 fn test_aggregate() {
     assert_eq!(
-        "SELECT CAST(AVG(field2) AS INT) FROM Table",
+        "SELECT CAST(AVG(field2) AS DOUBLE PRECISION) FROM Table",
         to_sql!(Table.aggregate(avg(field2)))
     );
     assert_eq!(
-        "SELECT CAST(AVG(field2) AS INT) FROM Table GROUP BY field1",
+        "SELECT field1, CAST(AVG(field2) AS DOUBLE PRECISION) FROM Table GROUP BY field1",
         to_sql!(Table.values(field1).aggregate(avg(field2)))
     );
     assert_eq!(
-        "SELECT CAST(AVG(field2) AS INT) FROM Table",
+        "SELECT CAST(AVG(field2) AS DOUBLE PRECISION) FROM Table",
         to_sql!(Table.aggregate(average = avg(field2)))
     );
     assert_eq!(
-        "SELECT CAST(AVG(field2) AS INT) FROM Table GROUP BY field1 HAVING CAST(AVG(field2) AS INT) < 20",
+        "SELECT field1, CAST(AVG(field2) AS DOUBLE PRECISION) FROM Table GROUP BY field1 HAVING AVG(field2) < 20",
         to_sql!(Table.values(field1).aggregate(average = avg(field2)).filter(average < 20))
     );
     assert_eq!(
-        "SELECT CAST(AVG(field2) AS INT) FROM Table GROUP BY field1 HAVING CAST(AVG(field2) AS INT) < 20",
+        "SELECT field1, CAST(AVG(field2) AS DOUBLE PRECISION) FROM Table GROUP BY field1 HAVING AVG(field2) < 20",
         to_sql!(Table.values(field1).aggregate(avg(field2)).filter(field2_avg < 20))
     );
     assert_eq!(
-        "SELECT CAST(AVG(field2) AS INT) FROM Table WHERE field2 > 10 GROUP BY field1 HAVING CAST(AVG(field2) AS INT) < 20",
+        "SELECT field1, CAST(AVG(field2) AS DOUBLE PRECISION) FROM Table WHERE field2 > 10 GROUP BY field1 HAVING AVG(field2) < 20",
         to_sql!(Table.filter(field2 > 10).values(field1).aggregate(avg(field2)).filter(field2_avg < 20))
     );
     assert_eq!(
-        "SELECT CAST(AVG(field2) AS INT) FROM Table WHERE field2 > 10 GROUP BY field1 HAVING CAST(AVG(field2) AS INT) < 20",
+        "SELECT field1, CAST(AVG(field2) AS DOUBLE PRECISION) FROM Table WHERE field2 > 10 GROUP BY field1 HAVING AVG(field2) < 20",
         to_sql!(Table.filter(field2 > 10).values(field1).aggregate(average = avg(field2)).filter(average < 20))
     );
+    assert_eq!(
+        "SELECT CAST(COUNT(*) AS BIGINT) FROM Table",
+        to_sql!(Table.aggregate(count()))
+    );
+    assert_eq!(
+        "SELECT CAST(COUNT(field1) AS BIGINT), CAST(COUNT(DISTINCT field1) AS BIGINT) FROM Table",
+        to_sql!(Table.aggregate(count(field1), count_distinct(field1)))
+    );
+    assert_eq!(
+        "SELECT CAST(SUM(field2) AS BIGINT), CAST(MIN(field2) AS INTEGER), CAST(MAX(field2) AS INTEGER) FROM Table",
+        to_sql!(Table.aggregate(sum(field2), min(field2), max(field2)))
+    );
+    assert_eq!(
+        "SELECT CAST(BOOL_AND(field3) AS BOOLEAN), CAST(BOOL_OR(field3) AS BOOLEAN) FROM Table",
+        to_sql!(Table.aggregate(bool_and(field3), bool_or(field3)))
+    );
+    assert_eq!(
+        "SELECT field3, CAST(STRING_AGG(field1, ', ') AS CHARACTER VARYING), CAST(ARRAY_AGG(field2) AS INTEGER[]) FROM Table GROUP BY field3",
+        to_sql!(Table.values(field3).aggregate(string_agg(field1, ", "), array_agg(field2)))
+    );
+    assert_eq!(
+        "SELECT field1, CAST(COUNT(*) AS BIGINT) FROM Table GROUP BY field1 HAVING COUNT(*) > 1",
+        to_sql!(Table.values(field1).aggregate(total = count()).filter(total > 1))
+    );
+    assert_eq!(
+        "SELECT field1, field3, CAST(COUNT(*) AS BIGINT) FROM Table GROUP BY field1, field3",
+        to_sql!(Table.values(field1, field3).aggregate(count()))
+    );
+    assert_eq!(
+        "SELECT CAST(AVG(field2 / id) AS DOUBLE PRECISION) FROM Table",
+        to_sql!(Table.aggregate(avg(field2 / id)))
+    );
+    assert_eq!(
+        "SELECT CAST(SUM((field2 + 1) * field2) AS BIGINT) FROM Table",
+        to_sql!(Table.aggregate(sum((field2 + 1) * field2)))
+    );
+    assert_eq!(
+        "SELECT CAST(SUM(field2 - (id % 2)) AS BIGINT) FROM Table",
+        to_sql!(Table.aggregate(total = sum(field2 - id % 2)))
+    );
+    assert_eq!(
+        "SELECT field1, CAST(AVG(field2 / id) AS DOUBLE PRECISION) FROM Table GROUP BY field1 HAVING AVG(field2 / id) > 2.5",
+        to_sql!(Table.values(field1).aggregate(ratio = avg(field2 / id)).filter(ratio > 2.5))
+    );
+}
+
+#[test]
+fn test_annotate() {
+    assert_eq!(
+        "SELECT Author.id, Author.name, CAST(COUNT(Post.author) AS BIGINT) FROM Author LEFT JOIN Post ON Author.id = Post.author GROUP BY Author.id",
+        to_sql!(Author.annotate(post_count = count(Post.author)))
+    );
+    assert_eq!(
+        "SELECT Author.id, Author.name, CAST(SUM(Post.views) AS BIGINT), CAST(AVG(Post.views) AS DOUBLE PRECISION) FROM Author LEFT JOIN Post ON Author.id = Post.author GROUP BY Author.id",
+        to_sql!(Author.annotate(total_views = sum(Post.views), average_views = avg(Post.views)))
+    );
+    assert_eq!(
+        "SELECT Author.id, Author.name, CAST(SUM(Post.views * 2) AS BIGINT) FROM Author LEFT JOIN Post ON Author.id = Post.author GROUP BY Author.id",
+        to_sql!(Author.annotate(sum(Post.views * 2)))
+    );
+    assert_eq!(
+        "SELECT Author.id, Author.name, CAST(COUNT(Post.author) AS BIGINT) FROM Author LEFT JOIN Post ON Author.id = Post.author WHERE Author.name = 'me' GROUP BY Author.id HAVING COUNT(Post.author) > 1",
+        to_sql!(Author.filter(name == "me").annotate(post_count = count(Post.author)).filter(post_count > 1))
+    );
+    assert_eq!(
+        "SELECT TreeTable.field1, TreeTable.id, CAST(COUNT(TreeTable_parent.parent) AS BIGINT) FROM TreeTable LEFT JOIN TreeTable AS TreeTable_parent ON TreeTable.id = TreeTable_parent.parent GROUP BY TreeTable.id",
+        to_sql!(TreeTable.annotate(child_count = count(TreeTable.parent)))
+    );
+}
+
+#[test]
+fn test_window() {
+    assert_eq!(
+        "SELECT Table.field1, Table.field2, Table.field3, Table.id, CAST(RANK() OVER (PARTITION BY Table.field1 ORDER BY Table.field2 DESC) AS BIGINT) FROM Table",
+        to_sql!(Table.annotate(rank = rank().over(partition(field1).sort(-field2))))
+    );
+    assert_eq!(
+        "SELECT Table.field1, Table.field2, Table.field3, Table.id, CAST(ROW_NUMBER() OVER (ORDER BY Table.field2) AS BIGINT) FROM Table",
+        to_sql!(Table.annotate(row_number().over(sort(field2))))
+    );
+    assert_eq!(
+        "SELECT Table.field1, Table.field2, Table.field3, Table.id, CAST(SUM(Table.field2) OVER (PARTITION BY Table.field1 ORDER BY Table.id) AS BIGINT) FROM Table",
+        to_sql!(Table.annotate(running_total = sum(field2).over(partition(field1).sort(id))))
+    );
+    assert_eq!(
+        "SELECT Table.field1, Table.field2, Table.field3, Table.id, CAST(LAG(Table.field2) OVER (ORDER BY Table.id) AS INTEGER), CAST(LEAD(Table.field2, 2) OVER (PARTITION BY Table.field1, Table.field3) AS INTEGER) FROM Table",
+        to_sql!(Table.annotate(previous = lag(field2).over(sort(id)), next = lead(field2, 2).over(partition(field1, field3))))
+    );
+    assert_eq!(
+        "SELECT Table.field1, Table.field2, Table.field3, Table.id, CAST(DENSE_RANK() OVER (ORDER BY Table.field2 DESC) AS BIGINT) FROM Table WHERE field3 = TRUE",
+        to_sql!(Table.filter(field3 == true).annotate(dense_rank().over(sort(-field2))))
+    );
+    assert_eq!(
+        "SELECT Author.id, Author.name, CAST(COUNT(Post.author) AS BIGINT), CAST(RANK() OVER (ORDER BY Author.name) AS BIGINT) FROM Author LEFT JOIN Post ON Author.id = Post.author GROUP BY Author.id",
+        to_sql!(Author.annotate(post_count = count(Post.author), rank = rank().over(sort(name))))
+    );
 }
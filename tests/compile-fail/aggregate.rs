@@ -46,6 +46,46 @@ fn main() {
     sql!(Table.aggregate(avg(i32_field, field1)));
     //~^ ERROR this function takes 1 parameter but 2 parameters were supplied [E0061]
 
+    sql!(Table.aggregate(count(i32_field, field1)));
+    //~^ ERROR this function takes 0 or 1 parameter but 2 parameters were supplied [E0061]
+
+    sql!(Table.aggregate(string_agg(field1)));
+    //~^ ERROR this function takes 2 parameters but 1 parameter was supplied [E0061]
+
+    sql!(Table.aggregate(bool_and(i32_field)));
+    //~^ ERROR mismatched types:
+    //~| expected `bool`,
+    //~| found `i32` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
+
+    sql!(Table.aggregate(avg(field1)));
+    //~^ ERROR no aggregate function named `avg` found for type `String`
+
+    sql!(Table.aggregate(sum(field1 * field1)));
+    //~^ ERROR binary operation `*` cannot be applied to type `String` [E0369]
+    //~| HELP run `rustc --explain E0369` to see a detailed explanation
+
+    sql!(Table.aggregate(avg(i32_field / 2.5)));
+    //~^ ERROR mismatched types:
+    //~| expected `i32`,
+    //~| found `floating-point variable` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
+
+    sql!(Table.aggregate(avg(i32_field && i32_field)));
+    //~^ ERROR Expected arithmetic operation
+
+    sql!(Table.aggregate(avg(field1.len())));
+    //~^ ERROR Expected field or arithmetic operation
+
+    sql!(Table.aggregate(string_agg(field1, 42)));
+    //~^ ERROR mismatched types:
+    //~| expected `String`,
+    //~| found `integral variable` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
+
     sql!(Table.values(i32_field).aggregate(average = avg(i32_field)).filter(avg < 20));
     //~^ ERROR no aggregate field named `avg` found
 
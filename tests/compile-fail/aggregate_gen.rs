@@ -46,6 +46,6 @@ fn main() {
     }
 
     if let Some(aggregate) = sql!(Table.aggregate(average = avg(field2))) {
-        println!("{}", aggregate.average);
+        println!("{:?}", aggregate.average);
     }
 }
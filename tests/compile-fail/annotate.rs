@@ -0,0 +1,82 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Tests of the annotate() method.
+
+#![feature(plugin)]
+#![plugin(tql_macros)]
+
+extern crate tql;
+
+use tql::{ForeignKey, PrimaryKey};
+
+#[SqlTable]
+struct Author {
+    id: PrimaryKey,
+    name: String,
+}
+
+#[SqlTable]
+struct Post {
+    id: PrimaryKey,
+    author: ForeignKey<Author>,
+    editor: ForeignKey<Author>,
+    title: String,
+}
+
+#[SqlTable]
+struct Comment {
+    id: PrimaryKey,
+    author: ForeignKey<Author>,
+    text: String,
+}
+
+#[SqlTable]
+struct Tag {
+    id: PrimaryKey,
+    name: String,
+}
+
+fn main() {
+    sql!(Author.annotate(count()));
+    //~^ ERROR Expected a field of a related table in the annotation
+    //~| HELP use a field of a table having a foreign key to this table, like `count(Table.field)`
+
+    sql!(Author.annotate(count(name)));
+    //~^ ERROR Expected a field of a related table in the annotation
+
+    sql!(Author.annotate(count(Post.titl)));
+    //~^ ERROR attempted access of field `titl` on type `Post`, but no field with that name was found
+
+    sql!(Author.annotate(count(Pst.author)));
+    //~^ ERROR `Pst` does not name an SQL table [E0422]
+    //~| HELP run `rustc --explain E0422` to see a detailed explanation
+    //~| HELP did you mean Post?
+
+    sql!(Author.annotate(count(Post.title)));
+    //~^ ERROR many foreign keys to the table `Author` found in the table `Post`
+    //~| HELP aggregate the foreign key field to choose which one to use, like `count(Table.foreign_key)`
+
+    sql!(Author.annotate(count(Tag.name)));
+    //~^ ERROR no foreign key to the table `Author` found in the table `Tag`
+
+    sql!(Author.annotate(count(Post.author), count(Comment.author)));
+    //~^ ERROR cannot annotate with aggregates over many tables
+
+    sql!(Author.annotate(count(Post.author)).sort(name));
+    //~^ ERROR cannot call the sort() method with the annotate() method
+}
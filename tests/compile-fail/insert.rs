@@ -22,7 +22,7 @@
 
 extern crate tql;
 
-use tql::{ForeignKey, PrimaryKey};
+use tql::{ForeignKey, ManyToMany, PrimaryKey};
 
 #[SqlTable]
 struct Table {
@@ -31,6 +31,7 @@ struct Table {
     i32_field: i32,
     field2: String,
     related_field: ForeignKey<RelatedTable>,
+    tags: ManyToMany<RelatedTable>,
 }
 
 #[SqlTable]
@@ -38,7 +39,34 @@ struct RelatedTable {
     id: PrimaryKey,
 }
 
+#[SqlTable]
+struct Comment {
+    #[default(false)]
+    hidden: bool,
+    id: PrimaryKey,
+    text: String,
+}
+
+#[SqlTable]
+#[primary_key(tenant_id, id)]
+struct Node {
+    id: PrimaryKey,
+    tenant_id: i32,
+}
+
+#[SqlTable]
+struct Child {
+    id: PrimaryKey,
+    #[foreign_key(tenant_id, parent)]
+    parent: ForeignKey<Node>,
+    tenant_id: i32,
+}
+
 fn main() {
+    sql!(Table.insert(field1 = "", i32_field = 91, field2 = "", related_field = 1, tags = 2));
+    //~^ ERROR cannot assign the many-to-many field `tags`
+    //~| HELP use the add_tags() method
+
     sql!(Table.insert(field1 = 42, i32_field = 91));
     //~^ ERROR missing fields: `field2`, `related_field` [E0063]
     //~| HELP run `rustc --explain E0063` to see a detailed explanation
@@ -55,6 +83,22 @@ fn main() {
     //~^ ERROR expected = but got -=
     //~| ERROR missing fields: `field2`, `related_field` [E0063]
 
+    sql!(Table.insert_many((field1, i32_field) = rows));
+    //~^ ERROR missing fields: `field2`, `related_field` [E0063]
+    //~| HELP run `rustc --explain E0063` to see a detailed explanation
+
+    sql!(Table.insert_many((field1, i32_fild, field2, related_field) = rows));
+    //~^ ERROR attempted access of field `i32_fild` on type `Table`, but no field with that name was found
+    //~| HELP did you mean i32_field?
+    //~| ERROR missing fields: `i32_field` [E0063]
+
+    sql!(Table.insert_many(rows, rows));
+    //~^ ERROR this function takes 1 parameter but 2 parameters were supplied [E0061]
+    //~| HELP run `rustc --explain E0061` to see a detailed explanation
+
+    sql!(RelatedTable.insert_many(rows));
+    //~^ ERROR no field to insert in table `RelatedTable`
+
     let related_field = RelatedTable {
         id: 1,
     };
@@ -71,4 +115,43 @@ fn main() {
     //~| found `integral variable` [E0308]
     //~| HELP run `rustc --explain E0308` to see a detailed explanation
     //~| NOTE in this expansion of sql! (defined in tql)
+
+    sql!(Table.insert(field1 = "", i32_field = 91, field2 = "", related_field = related_field).on_conflict(field1).ignore());
+    //~^ ERROR there is no unique or exclusion constraint matching the on_conflict() specification
+    //~| HELP did you mean on_conflict(id)?
+
+    sql!(Table.insert(field1 = "", i32_field = 91, field2 = "", related_field = related_field).ignore());
+    //~^ ERROR the ignore() method must be preceded by on_conflict() when called with insert()
+
+    sql!(Table.insert(field1 = "", i32_field = 91, field2 = "", related_field = related_field).on_conflict(id));
+    //~^ ERROR the on_conflict() method must be followed by either ignore() or update()
+
+    sql!(Table.insert(field1 = "", i32_field = 91, field2 = "", related_field = related_field).on_conflict().update(field1 = excluded::field1));
+    //~^ ERROR the on_conflict() method requires the conflicting fields when followed by update()
+
+    sql!(Table.insert(field1 = "", i32_field = 91, field2 = "", related_field = related_field).on_conflict(id).update(field1 = excluded::fild1));
+    //~^ ERROR attempted access of field `fild1` on type `Table`, but no field with that name was found
+    //~| HELP did you mean field1?
+
+    sql!(Table.insert(field1 = "", i32_field = 91, field2 = "", related_field = related_field).on_conflict(id).update(i32_field = excluded::i32_field.pow(2)));
+    //~^ ERROR cannot use an excluded::field value in this expression
+    //~| HELP only arithmetic operations can be applied to an excluded::field value
+
+    sql!(Table.insert(field1 = "", i32_field = 91, field2 = "", related_field = related_field).on_conflict(id).update(field1 = excluded::field1 + "suffix"));
+    //~^ ERROR binary operation `+` cannot be applied to type `String` [E0369]
+
+    sql!(Comment.insert(hidden = true));
+    //~^ ERROR missing fields: `text` [E0063]
+    //~| HELP run `rustc --explain E0063` to see a detailed explanation
+
+    sql!(Child.insert(parent = node, tenant_id = 1));
+    //~^ ERROR the field `tenant_id` is already assigned by the foreign key `parent`
+
+    sql!(Child.insert(parent = node).on_conflict(id).update(parent = excluded::tenant_id));
+    //~^ ERROR cannot assign this value to the foreign key `parent` which references the compound primary key of `Node`
+    //~| HELP assign an object of `Node` or excluded::parent
+
+    sql!(Child.insert_many(rows));
+    //~^ ERROR cannot insert the rows of `Child` since its foreign key `parent` references a compound primary key
+    //~| HELP specify the fields to insert, like insert_many((field1, parent) = rows)
 }
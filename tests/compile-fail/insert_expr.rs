@@ -49,5 +49,13 @@ fn main() {
     //~| found `i32` [E0308]
     //~| HELP run `rustc --explain E0308` to see a detailed explanation
     //~| NOTE in this expansion of sql! (defined in tql)
-}
 
+    let rows = vec![("test".to_owned(), 42i64, "test".to_owned())];
+
+    sql!(Table.insert_many((field1, i32_field, field2) = rows)).unwrap();
+    //~^ ERROR mismatched types:
+    //~| expected `&i32`,
+    //~| found `&i64`
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
+}
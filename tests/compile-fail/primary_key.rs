@@ -0,0 +1,97 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+//! Tests of the compound primary keys.
+
+#![feature(plugin)]
+#![plugin(tql_macros)]
+
+extern crate tql;
+
+use tql::{ManyToMany, PrimaryKey};
+
+#[SqlTable]
+#[primary_key(tenant_id, id)]
+struct Node {
+    id: PrimaryKey,
+    name: String,
+    tenant_id: i32,
+}
+
+#[SqlTable]
+#[primary_key(tenant_id, id)]
+struct Group {
+    id: PrimaryKey,
+    tenant_id: i32,
+    tags: ManyToMany<Tag>,
+    //~^ ERROR a table with a compound primary key cannot have a ManyToMany field
+}
+
+#[SqlTable]
+#[primary_key(id)]
+//~^ ERROR expected at least two fields in the #[primary_key] attribute
+//~| HELP use the #[primary_key] attribute on the field for a primary key with a single field
+struct Table {
+    id: i32,
+}
+
+#[SqlTable]
+struct Measure {
+    #[primary_key]
+    id: f64,
+    //~^ ERROR unsupported primary key type `f64`
+    //~| HELP use i32, i64, String or Uuid
+}
+
+#[SqlTable]
+struct Account {
+    #[primary_key]
+    id: i64,
+    #[primary_key]
+    //~^ ERROR only one primary key is allowed
+    //~| HELP use the #[primary_key(field1, field2)] attribute for a compound primary key
+    username: String,
+}
+
+#[SqlTable]
+struct Tag {
+    description: String,
+    #[primary_key]
+    name: String,
+}
+
+fn main() {
+    sql!(Node.get(1));
+    //~^ ERROR expected a tuple of 2 values for the compound primary key of `Node`
+    //~| HELP use a tuple, like get((tenant_id, id))
+
+    sql!(Node.get((1, 2, 3)));
+    //~^ ERROR expected a value for each field of the primary key (tenant_id, id), found 3 values
+
+    sql!(Node.get((1, "test")));
+    //~^ ERROR mismatched types:
+    //~| expected `i32`,
+    //~| found `String` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
+
+    sql!(Tag.insert(description = "A tag"));
+    //~^ ERROR missing fields: `name` [E0063]
+    //~| HELP run `rustc --explain E0063` to see a detailed explanation
+
+    sql!(Tag.create_junction_tables());
+    //~^ ERROR the table `Tag` has no ManyToMany field
+}
@@ -22,7 +22,7 @@
 
 extern crate tql;
 
-use tql::PrimaryKey;
+use tql::{ForeignKey, PrimaryKey};
 
 struct Connection {
     value: String,
@@ -35,6 +35,27 @@ struct Table {
     i32_field: i32,
 }
 
+#[SqlTable]
+struct RelatedTable {
+    id: PrimaryKey,
+    parent: ForeignKey<Table>,
+}
+
+#[SqlTable]
+#[primary_key(tenant_id, id)]
+struct Node {
+    id: PrimaryKey,
+    tenant_id: i32,
+}
+
+#[SqlTable]
+struct Child {
+    id: PrimaryKey,
+    #[foreign_key(tenant_id, parent)]
+    parent: ForeignKey<Node>,
+    tenant_id: i32,
+}
+
 fn main() {
     sql!(Table.filter(field1 == "value1" && field2 < 100).sort(-field2));
     //~^ ERROR attempted access of field `field2` on type `Table`, but no field with that name was found
@@ -117,6 +138,33 @@ fn main() {
     //~| HELP run `rustc --explain E0308` to see a detailed explanation
     //~| NOTE in this expansion of sql! (defined in tql)
 
+    sql!(Table.filter(i32_field.in([1, 2u32])));
+    //~^ ERROR mismatched types:
+    //~| expected `i32`,
+    //~| found `u32` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
+
+    sql!(Table.filter(field1.in("a".."z")));
+    //~^ ERROR mismatched types:
+    //~| expected `integer`,
+    //~| found `String` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
+
+    sql!(Table.filter(i32_field.in(1..)));
+    //~^ ERROR expected range with a start and an end
+
+    sql!(Table.filter(i32_field.in([])));
+    //~^ ERROR expected at least one value in the in() method
+
+    sql!(Table.filter(i32_field.in(42)));
+    //~^ ERROR expected array, range, slice or Vec expression
+
+    sql!(Table.filter(i32_fild.in([1, 2])));
+    //~^ ERROR attempted access of field `i32_fild` on type `Table`, but no field with that name was found
+    //~| HELP did you mean i32_field?
+
     sql!(Table.filter(i32_field >= 42).sort(fild1));
     //~^ ERROR attempted access of field `fild1` on type `Table`, but no field with that name was found
     //~| HELP did you mean field1?
@@ -201,5 +249,91 @@ fn main() {
     //~| HELP run `rustc --explain E0308` to see a detailed explanation
     //~| NOTE in this expansion of sql! (defined in tql)
 
+    sql!(Table.all().join(field1.id));
+    //~^ ERROR mismatched types:
+    //~| expected `ForeignKey<_>`,
+    //~| found `String` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
+
+    sql!(Table.all().join(test.id));
+    //~^ ERROR attempted access of field `test` on type `Table`, but no field with that name was found
+
     //to_sql!(Table.all().join(address, address)); // TODO: should span an error.
+
+    sql!(Table.only());
+    //~^ ERROR expected at least one field in the only() method
+
+    sql!(Table.defer(fild1));
+    //~^ ERROR attempted access of field `fild1` on type `Table`, but no field with that name was found
+    //~| HELP did you mean field1?
+
+    sql!(Table.defer(id));
+    //~^ ERROR cannot defer the primary key field `id`
+
+    sql!(Table.get(1).update(i32_field = 42).only(field1));
+    //~^ ERROR cannot call the only() method with the update() method
+
+    sql!(Table.prefetch(field1));
+    //~^ ERROR expected a foreign key field of another table (for instance, Table.field)
+
+    sql!(Table.prefetch(Table.field1));
+    //~^ ERROR mismatched types:
+    //~| expected `ForeignKey<Table>`,
+    //~| found `String` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
+
+    sql!(Table.all().prefetch(Table.fild1));
+    //~^ ERROR attempted access of field `fild1` on type `Table`, but no field with that name was found
+    //~| HELP did you mean field1?
+
+    sql!(Table.prefetch(RelatedTable.parent).prefetch(RelatedTable.parent));
+    //~^ ERROR cannot prefetch the related rows of more than one foreign key field
+
+    sql!(Table.distinct(field1));
+    //~^ ERROR this method takes 0 parameters but 1 parameter was supplied [E0061]
+
+    sql!(Table.distinct_on());
+    //~^ ERROR expected at least one field in the distinct_on() method
+
+    sql!(Table.distinct_on(fild1));
+    //~^ ERROR attempted access of field `fild1` on type `Table`, but no field with that name was found
+    //~| HELP did you mean field1?
+
+    sql!(Table.distinct_on(field1).sort(-i32_field));
+    //~^ ERROR the leading fields of the sort() method must match the fields of the distinct_on() method
+    //~| HELP sort by `field1` first
+
+    sql!(Table.distinct_on(field1, i32_field).sort(field1, id, i32_field));
+    //~^ ERROR the leading fields of the sort() method must match the fields of the distinct_on() method
+    //~| HELP sort by `i32_field` first
+
+    sql!(Table.filter(i32_field > 10).values(field1));
+    //~^ ERROR the values() method must be called with either the aggregate() or the distinct() method
+
+    sql!(Table.values(field1).distinct().sort(field1));
+    //~^ ERROR cannot call the sort() method with the values() and distinct() methods
+
+    sql!(Table.values(field1).aggregate(avg(i32_field)).distinct());
+    //~^ ERROR cannot call the distinct() method with the aggregate() method
+
+    sql!(Table.count(field1));
+    //~^ ERROR this method takes 0 parameters but 1 parameter was supplied [E0061]
+
+    sql!(Table.filter(i32_field > 10).sort(field1).count());
+    //~^ ERROR cannot call the sort() method with the count() method
+
+    sql!(Table.filter(i32_field > 10)[..10].count());
+    //~^ ERROR cannot call the limit() method with the count() method
+
+    sql!(Table.filter(i32_field > 10).sort(field1).exists());
+    //~^ ERROR cannot call the sort() method with the exists() method
+
+    sql!(Table.filter(i32_field > 10)[1..].exists());
+    //~^ ERROR cannot call the limit() method with the exists() method
+
+    sql!(Child.filter(parent > node));
+    //~^ ERROR cannot compare the foreign key `parent` which references the compound primary key of `Node` with this operator
+    //~| HELP use == or !=
 }
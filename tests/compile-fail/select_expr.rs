@@ -26,6 +26,14 @@ extern crate tql;
 use postgres::{Connection, SslMode};
 use tql::{ForeignKey, PrimaryKey};
 
+#[SqlTable]
+#[primary_key(tenant_id, id)]
+struct Node {
+    id: PrimaryKey,
+    name: String,
+    tenant_id: i32,
+}
+
 #[SqlTable]
 struct OtherTable {
     id: PrimaryKey,
@@ -90,4 +98,12 @@ fn main() {
 
     let other = sql!(OtherTable.get(1)).unwrap();
     sql!(Table.filter(other == other));
+
+    let key = (1i64, 2);
+    sql!(Node.get(key));
+    //~^ ERROR mismatched types:
+    //~| expected `i32`,
+    //~| found `i64` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
 }
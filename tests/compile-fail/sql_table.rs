@@ -57,3 +57,131 @@ struct Table<'a> {
     //~^ ERROR use of unsupported type name `Vec<i32>` [E0412]
     //~| run `rustc --explain E0412` to see a detailed explanation
 }
+
+#[SqlTable]
+#[unique(email, first_nam)]
+//~^ ERROR attempted access of field `first_nam` on type `Person`, but no field with that name was found
+//~| HELP did you mean first_name?
+#[unique()]
+//~^ ERROR expected at least one field in the #[unique] attribute
+struct Person {
+    #[unique]
+    email: String,
+    first_name: String,
+}
+
+#[SqlTable]
+struct Post {
+    id: PrimaryKey,
+    #[unique]
+    //~^ ERROR cannot use the #[unique] attribute on the many-to-many field `tags`
+    tags: ManyToMany<Article>,
+}
+
+#[SqlTable]
+#[index(fields(titl), method = "gim")]
+//~^ ERROR attempted access of field `titl` on type `Article`, but no field with that name was found
+//~| HELP did you mean title?
+//~| ERROR unknown index method `gim`
+//~| HELP did you mean gin?
+#[index(unique)]
+//~^ ERROR expected at least one field in the #[index] attribute
+//~| HELP specify the fields with #[index(fields(field1, field2))]
+struct Article {
+    #[index(fields(title))]
+    //~^ ERROR the #[index] attribute of a field does not take any field
+    author: String,
+    #[index(unique, method = "hash")]
+    //~^ ERROR the index method `hash` does not support unique indexes
+    //~| HELP use the btree method for a unique index
+    slug: String,
+    #[index(name = 42, sorted)]
+    //~^ ERROR expected a string literal
+    //~| ERROR unknown option in the #[index] attribute
+    //~| HELP expected one of `fields(...)`, `method = "..."`, `name = "..."` or `unique`
+    title: String,
+}
+
+#[SqlTable]
+struct Comment {
+    #[default]
+    //~^ ERROR expected a default value in the #[default] attribute
+    //~| HELP use #[default = "value"] or #[default(value)]
+    hidden: bool,
+    #[default = 0]
+    //~^ ERROR expected a string literal
+    likes: i32,
+    #[default(false)]
+    #[default(true)]
+    //~^ ERROR only one #[default] attribute is allowed
+    published: bool,
+    #[default = "1.5"]
+    //~^ ERROR mismatched types:
+    //~| expected `i32`,
+    //~| found `floating-point variable` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    rank: i32,
+    #[default(false)]
+    //~^ ERROR mismatched types:
+    //~| expected `i32`,
+    //~| found `bool` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    views: i32,
+}
+
+#[SqlTable]
+#[check = "pric > 0.0"]
+//~^ ERROR attempted access of field `pric` on type `Product`, but no field with that name was found
+//~| HELP did you mean price?
+#[check = "quantity >= 1.5"]
+//~^ ERROR mismatched types:
+//~| expected `i32`,
+//~| found `floating-point variable` [E0308]
+//~| HELP run `rustc --explain E0308` to see a detailed explanation
+#[check = "quantity >= -1.5"]
+//~^ ERROR mismatched types:
+//~| expected `i32`,
+//~| found `floating-point variable` [E0308]
+//~| HELP run `rustc --explain E0308` to see a detailed explanation
+#[check = "quantity >= price"]
+//~^ ERROR mismatched types:
+//~| expected `i32`,
+//~| found `f64` [E0308]
+//~| HELP run `rustc --explain E0308` to see a detailed explanation
+#[check = "quantity >= minimum()"]
+//~^ ERROR expected a literal or a field in the #[check] attribute
+#[check(quantity)]
+//~^ ERROR expected an expression in the #[check] attribute
+//~| HELP use #[check = "expression"]
+struct Product {
+    price: f64,
+    quantity: i32,
+}
+
+#[SqlTable]
+#[primary_key(tenant_id, id)]
+struct Node {
+    id: PrimaryKey,
+    tenant_id: i32,
+}
+
+#[SqlTable]
+struct Child {
+    id: PrimaryKey,
+    #[foreign_key(tenant_id)]
+    //~^ ERROR expected at least two fields in the #[foreign_key] attribute
+    //~| HELP use #[foreign_key(field1, parent)]
+    parent: ForeignKey<Node>,
+    #[foreign_key(other_parent, tenant_id)]
+    //~^ ERROR the last field of the #[foreign_key] attribute must be `other_parent`
+    //~| HELP use #[foreign_key(tenant_id, other_parent)]
+    other_parent: ForeignKey<Node>,
+    #[foreign_key(tenant_id, tenant_i, related)]
+    //~^ ERROR attempted access of field `tenant_i` on type `Child`, but no field with that name was found
+    //~| HELP did you mean tenant_id?
+    related: ForeignKey<Node>,
+    #[foreign_key(tenant_id, other_id)]
+    //~^ ERROR cannot use the #[foreign_key] attribute on the field `other_id` which is not a ForeignKey
+    other_id: i32,
+    tenant_id: i32,
+}
@@ -23,7 +23,7 @@
 extern crate postgres;
 extern crate tql;
 
-use tql::{ForeignKey, PrimaryKey};
+use tql::{ForeignKey, ManyToMany, PrimaryKey};
 
 struct Connection {
     value: String,
@@ -44,3 +44,52 @@ struct Table {
 struct RelatedTable {
     id: PrimaryKey,
 }
+
+#[SqlTable]
+#[primary_key(tenant_id, id)]
+struct Node {
+    id: PrimaryKey,
+    tenant_id: i32,
+}
+
+#[SqlTable]
+struct Child {
+    id: PrimaryKey,
+    parent: ForeignKey<Node>,
+    //~^ ERROR the foreign key `parent` references the compound primary key (tenant_id, id) of `Node`, but has no #[foreign_key] attribute
+    //~| HELP add a #[foreign_key] attribute with a field of `Child` for each field of this key, like #[foreign_key(field1, parent)]
+    nodes: ManyToMany<Node>,
+    //~^ ERROR a ManyToMany field cannot reference the table `Node` which has a compound primary key
+}
+
+#[SqlTable]
+struct OtherChild {
+    id: PrimaryKey,
+    #[foreign_key(tenant_id, other_id, parent)]
+    parent: ForeignKey<Node>,
+    //~^ ERROR the foreign key `parent` contains 3 fields, but the primary key (tenant_id, id) of `Node` contains 2 fields
+    #[foreign_key(tenant, other_parent)]
+    other_parent: ForeignKey<Node>,
+    //~^ ERROR mismatched types:
+    //~| expected `i32`,
+    //~| found `String`
+    //~| (the field `tenant` references `Node.tenant_id`) [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    #[foreign_key(other_id, related)]
+    related: ForeignKey<RelatedTable>,
+    //~^ ERROR the #[foreign_key] attribute of `related` is not needed because `RelatedTable` does not have a compound primary key
+    //~| HELP remove the #[foreign_key] attribute
+    other_id: i32,
+    tenant: String,
+    tenant_id: i32,
+}
+
+#[SqlTable]
+struct NullableChild {
+    id: PrimaryKey,
+    #[foreign_key(tenant_id, parent)]
+    parent: Option<ForeignKey<Node>>,
+    //~^ ERROR a nullable foreign key cannot reference the table `Node` which has a compound primary key
+    //~| HELP use the type ForeignKey<Node>
+    tenant_id: i32,
+}
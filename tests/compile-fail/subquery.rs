@@ -0,0 +1,83 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Tests of the subqueries in the filter() method.
+
+#![feature(plugin)]
+#![plugin(tql_macros)]
+
+extern crate tql;
+
+use tql::{ForeignKey, PrimaryKey};
+
+#[SqlTable]
+struct Table {
+    id: PrimaryKey,
+    field1: String,
+    i32_field: i32,
+    related_field: ForeignKey<RelatedTable>,
+}
+
+#[SqlTable]
+struct RelatedTable {
+    id: PrimaryKey,
+    field1: String,
+}
+
+fn main() {
+    sql!(Table.filter(field1.in(RelatedTable.filter(field1 == "value1").values(id))));
+    //~^ ERROR mismatched types:
+    //~| expected `String`,
+    //~| found `i32` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
+
+    sql!(Table.filter(related_field.in(RelatedTable.all())));
+    //~^ ERROR expected a subquery selecting 1 column, found 2 columns
+    //~| HELP select a field with the values() method
+
+    sql!(Table.filter(related_field.in(RelatedTable.values(id, field1))));
+    //~^ ERROR expected a subquery selecting 1 column, found 2 columns
+    //~| HELP select a field with the values() method
+
+    sql!(Table.filter(related_field.in(RelatedTable.values(fild1))));
+    //~^ ERROR attempted access of field `fild1` on type `RelatedTable`, but no field with that name was found
+    //~| HELP did you mean field1?
+
+    sql!(Table.filter(exists(RelatedTable.filter(field1 == outer.fild1))));
+    //~^ ERROR attempted access of field `fild1` on type `Table`, but no field with that name was found
+    //~| HELP did you mean field1?
+
+    sql!(RelatedTable.filter(exists(Table.filter(related_field == outer.field1))));
+    //~^ ERROR mismatched types:
+    //~| expected `RelatedTable`,
+    //~| found `String` [E0308]
+    //~| NOTE in this expansion of sql! (defined in tql)
+
+    sql!(Table.filter(exists(Table.filter(field1 == outer.field1))));
+    //~^ ERROR cannot refer to the outer query on the same table `Table` in a subquery
+
+    sql!(RelatedTable.filter(exists(Table.filter(related_field == outer.id + 1))));
+    //~^ ERROR cannot use a field of the outer query in an expression
+    //~| HELP compare a field of the subquery to the outer.field value directly
+
+    sql!(Table.filter(exists(RelatedTable.all(), RelatedTable.all())));
+    //~^ ERROR this function takes 1 parameter but 2 parameters were supplied [E0061]
+
+    sql!(Table.filter(exists(RelatedTable.filter(field1 == "value1").delete())));
+    //~^ ERROR cannot call the delete() method in a subquery
+}
@@ -42,4 +42,11 @@ fn main() {
 
     sql!(Table.get(1).update(value += " test"));
     //~^ ERROR attempted access of field `value` on type `Table`, but no field with that name was found
+
+    sql!(Table.get(1).update(i32_field = 42).returning(i32_fild));
+    //~^ ERROR attempted access of field `i32_fild` on type `Table`, but no field with that name was found
+    //~| HELP did you mean i32_field?
+
+    sql!(Table.get(1).returning(id));
+    //~^ ERROR cannot call the returning() method with the all() method
 }
@@ -0,0 +1,64 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Tests of the window functions.
+
+#![feature(plugin)]
+#![plugin(tql_macros)]
+
+extern crate tql;
+
+use tql::PrimaryKey;
+
+#[SqlTable]
+struct Table {
+    id: PrimaryKey,
+    field1: String,
+    i32_field: i32,
+}
+
+fn main() {
+    sql!(Table.annotate(rnk().over(sort(i32_field))));
+    //~^ ERROR unresolved name `rnk` [E0425]
+    //~| HELP run `rustc --explain E0425` to see a detailed explanation
+    //~| HELP did you mean rank?
+
+    sql!(Table.annotate(rank().over(partition(field2))));
+    //~^ ERROR attempted access of field `field2` on type `Table`, but no field with that name was found
+
+    sql!(Table.annotate(rank().over(sort(-fild1))));
+    //~^ ERROR attempted access of field `fild1` on type `Table`, but no field with that name was found
+
+    sql!(Table.annotate(rank().over(group(field1))));
+    //~^ ERROR Expected partition() or sort()
+
+    sql!(Table.annotate(rank().over(partition(field1), sort(i32_field))));
+    //~^ ERROR this function takes 1 parameter but 2 parameters were supplied [E0061]
+
+    sql!(Table.annotate(rank(i32_field).over(sort(i32_field))));
+    //~^ ERROR this function takes 0 parameters but 1 parameter was supplied [E0061]
+
+    sql!(Table.annotate(lag(i32_field, "1").over(sort(i32_field))));
+    //~^ ERROR mismatched types:
+    //~| expected `i32`,
+    //~| found `String` [E0308]
+    //~| HELP run `rustc --explain E0308` to see a detailed explanation
+    //~| NOTE in this expansion of sql! (defined in tql)
+
+    sql!(Table.annotate(sum(field1).over(sort(i32_field))));
+    //~^ ERROR no aggregate function named `sum` found for type `String`
+}
@@ -21,6 +21,7 @@
 extern crate chrono;
 extern crate postgres;
 extern crate tql;
+extern crate uuid;
 
 use chrono::datetime::DateTime;
 use chrono::naive::date::NaiveDate;
@@ -28,7 +29,8 @@ use chrono::naive::datetime::NaiveDateTime;
 use chrono::naive::time::NaiveTime;
 use chrono::offset::local::Local;
 use chrono::offset::utc::UTC;
-use tql::{ForeignKey, PrimaryKey};
+use tql::{ForeignKey, ManyToMany, PrimaryKey};
+use uuid::Uuid;
 
 #[SqlTable]
 struct Table {
@@ -45,6 +47,108 @@ struct RelatedTable {
     field1: String,
 }
 
+#[SqlTable]
+struct TaggedTable {
+    id: PrimaryKey,
+    field1: String,
+    tags: ManyToMany<RelatedTable>,
+}
+
+#[SqlTable]
+#[unique(first_name, last_name)]
+struct Person {
+    id: PrimaryKey,
+    #[unique]
+    email: String,
+    first_name: String,
+    last_name: String,
+}
+
+#[SqlTable]
+#[index(fields(title, published))]
+#[index(name = "Article_tags_gin", fields(tags), method = "gin")]
+#[index(fields(slug, published), unique)]
+struct Article {
+    id: PrimaryKey,
+    #[index]
+    author: String,
+    published: bool,
+    #[index(unique, method = "btree")]
+    slug: String,
+    tags: String,
+    title: String,
+}
+
+#[SqlTable]
+#[primary_key(tenant_id, id)]
+struct Node {
+    id: PrimaryKey,
+    name: String,
+    tenant_id: i32,
+}
+
+#[SqlTable]
+struct Child {
+    id: PrimaryKey,
+    #[foreign_key(tenant_id, parent)]
+    parent: ForeignKey<Node>,
+    tenant_id: i32,
+}
+
+#[SqlTable]
+#[primary_key(post, tag)]
+struct PostTag {
+    post: ForeignKey<RelatedTable>,
+    tag: ForeignKey<RelatedTable>,
+}
+
+#[SqlTable]
+struct Event {
+    #[primary_key]
+    id: i64,
+    members: ManyToMany<Account>,
+    name: String,
+}
+
+#[SqlTable]
+struct Account {
+    event: ForeignKey<Event>,
+    #[primary_key]
+    username: String,
+}
+
+#[SqlTable]
+struct Session {
+    account: ForeignKey<Account>,
+    #[primary_key]
+    token: Uuid,
+}
+
+#[SqlTable]
+struct Comment {
+    #[default = "now()"]
+    created_at: DateTime<UTC>,
+    #[default(false)]
+    hidden: bool,
+    id: PrimaryKey,
+    #[default = "0"]
+    likes: i32,
+    text: String,
+}
+
+#[SqlTable]
+#[check = "price > 0.0 && quantity >= 0"]
+#[check = "name.len() > 2"]
+#[check = "price >= cost"]
+#[check = "quantity > -100"]
+struct Product {
+    cost: f64,
+    id: PrimaryKey,
+    name: String,
+    price: f64,
+    quantity: i32,
+}
+
 #[SqlTable]
 struct Dates {
     pk: PrimaryKey,
@@ -87,4 +191,56 @@ fn test_create() {
         "CREATE TABLE OtherTypes (boolean BOOLEAN NOT NULL, bytestring BYTEA NOT NULL, character CHARACTER(1) NOT NULL, float32 REAL NOT NULL, float64 DOUBLE PRECISION NOT NULL, int16 SMALLINT NOT NULL, int32 INTEGER NOT NULL, int64 BIGINT NOT NULL, int8 CHARACTER(1) NOT NULL, pk SERIAL PRIMARY KEY NOT NULL)",
         to_sql!(OtherTypes.create())
     );
+    assert_eq!(
+        "CREATE TABLE Article (author CHARACTER VARYING NOT NULL, id SERIAL PRIMARY KEY NOT NULL, published BOOLEAN NOT NULL, slug CHARACTER VARYING NOT NULL, tags CHARACTER VARYING NOT NULL, title CHARACTER VARYING NOT NULL); CREATE INDEX Article_author_idx ON Article (author); CREATE UNIQUE INDEX Article_slug_idx ON Article USING btree (slug); CREATE INDEX Article_title_published_idx ON Article (title, published); CREATE INDEX Article_tags_gin ON Article USING gin (tags); CREATE UNIQUE INDEX Article_slug_published_idx ON Article (slug, published)",
+        to_sql!(Article.create())
+    );
+    assert_eq!(
+        "CREATE TABLE Node (id SERIAL NOT NULL, name CHARACTER VARYING NOT NULL, tenant_id INTEGER NOT NULL, PRIMARY KEY (tenant_id, id))",
+        to_sql!(Node.create())
+    );
+    assert_eq!(
+        "CREATE TABLE Child (id SERIAL PRIMARY KEY NOT NULL, parent INTEGER NOT NULL, tenant_id INTEGER NOT NULL, FOREIGN KEY (tenant_id, parent) REFERENCES Node(tenant_id, id))",
+        to_sql!(Child.create())
+    );
+    assert_eq!(
+        "CREATE TABLE PostTag (post INTEGER REFERENCES RelatedTable(id) NOT NULL, tag INTEGER REFERENCES RelatedTable(id) NOT NULL, PRIMARY KEY (post, tag))",
+        to_sql!(PostTag.create())
+    );
+    assert_eq!(
+        "CREATE TABLE Person (email CHARACTER VARYING NOT NULL, first_name CHARACTER VARYING NOT NULL, id SERIAL PRIMARY KEY NOT NULL, last_name CHARACTER VARYING NOT NULL, UNIQUE (email), UNIQUE (first_name, last_name))",
+        to_sql!(Person.create())
+    );
+    assert_eq!(
+        "CREATE TABLE TaggedTable (field1 CHARACTER VARYING NOT NULL, id SERIAL PRIMARY KEY NOT NULL)",
+        to_sql!(TaggedTable.create())
+    );
+    assert_eq!(
+        "CREATE TABLE TaggedTable_tags (TaggedTable INTEGER NOT NULL REFERENCES TaggedTable(id) ON DELETE CASCADE, tags INTEGER NOT NULL REFERENCES RelatedTable(id) ON DELETE CASCADE, PRIMARY KEY (TaggedTable, tags))",
+        to_sql!(TaggedTable.create_junction_tables())
+    );
+    assert_eq!(
+        "CREATE TABLE Event (id BIGSERIAL PRIMARY KEY NOT NULL, name CHARACTER VARYING NOT NULL)",
+        to_sql!(Event.create())
+    );
+    assert_eq!(
+        "CREATE TABLE Event_members (Event BIGINT NOT NULL REFERENCES Event(id) ON DELETE CASCADE, members CHARACTER VARYING NOT NULL REFERENCES Account(username) ON DELETE CASCADE, PRIMARY KEY (Event, members))",
+        to_sql!(Event.create_junction_tables())
+    );
+    assert_eq!(
+        "CREATE TABLE Account (event BIGINT REFERENCES Event(id) NOT NULL, username CHARACTER VARYING PRIMARY KEY NOT NULL)",
+        to_sql!(Account.create())
+    );
+    assert_eq!(
+        "CREATE TABLE Session (account CHARACTER VARYING REFERENCES Account(username) NOT NULL, token UUID PRIMARY KEY NOT NULL)",
+        to_sql!(Session.create())
+    );
+    assert_eq!(
+        "CREATE TABLE Comment (created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(), hidden BOOLEAN NOT NULL DEFAULT false, id SERIAL PRIMARY KEY NOT NULL, likes INTEGER NOT NULL DEFAULT 0, text CHARACTER VARYING NOT NULL)",
+        to_sql!(Comment.create())
+    );
+    assert_eq!(
+        "CREATE TABLE Product (cost DOUBLE PRECISION NOT NULL, id SERIAL PRIMARY KEY NOT NULL, name CHARACTER VARYING NOT NULL, price DOUBLE PRECISION NOT NULL, quantity INTEGER NOT NULL, CHECK (price > 0.0 AND quantity >= 0), CHECK (CHAR_LENGTH(name) > 2), CHECK (price >= cost), CHECK (quantity > -100))",
+        to_sql!(Product.create())
+    );
 }
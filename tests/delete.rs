@@ -43,3 +43,15 @@ fn test_delete() {
         to_sql!(Table.filter(field1 == "test").delete())
     );
 }
+
+#[test]
+fn test_delete_returning() {
+    assert_eq!(
+        "DELETE FROM Table WHERE field1 = 'test' RETURNING Table.field1, Table.field2, Table.id",
+        to_sql!(Table.filter(field1 == "test").delete().returning())
+    );
+    assert_eq!(
+        "DELETE FROM Table WHERE field1 = 'test' RETURNING id",
+        to_sql!(Table.filter(field1 == "test").delete().returning(id))
+    );
+}
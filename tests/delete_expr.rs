@@ -106,3 +106,29 @@ fn test_delete() {
     let table = sql!(TableDeleteExpr.get(id));
     assert!(table.is_none());
 }
+
+#[test]
+fn test_delete_returning() {
+    let connection = get_connection();
+
+    let _teardown = TearDown::new(|| {
+        let _ = sql!(TableDeleteExpr.drop());
+    });
+
+    let _ = sql!(TableDeleteExpr.create());
+
+    let id1 = sql!(TableDeleteExpr.insert(field1 = "value1", field2 = 1)).unwrap();
+    let id2 = sql!(TableDeleteExpr.insert(field1 = "value2", field2 = 2)).unwrap();
+
+    let tables = sql!(TableDeleteExpr.filter(field2 < 2).delete().returning()).unwrap();
+    assert_eq!(1, tables.len());
+    assert_eq!(id1, tables[0].id);
+    assert_eq!("value1", tables[0].field1);
+    assert_eq!(1, tables[0].field2);
+
+    let rows = sql!(TableDeleteExpr.get(id2).delete().returning(field1, field2)).unwrap();
+    assert_eq!(vec![("value2".to_owned(), 2)], rows);
+
+    let ids = sql!(TableDeleteExpr.filter(field2 < 5).delete().returning(id)).unwrap();
+    assert!(ids.is_empty());
+}
@@ -21,7 +21,7 @@
 extern crate postgres;
 extern crate tql;
 
-use tql::{ForeignKey, PrimaryKey};
+use tql::{ForeignKey, ManyToMany, PrimaryKey};
 
 #[SqlTable]
 struct Table {
@@ -38,6 +38,13 @@ struct RelatedTable {
     field1: String,
 }
 
+#[SqlTable]
+struct TaggedTable {
+    id: PrimaryKey,
+    field1: String,
+    tags: ManyToMany<RelatedTable>,
+}
+
 #[test]
 fn test_drop() {
     assert_eq!(
@@ -48,4 +55,8 @@ fn test_drop() {
         "DROP TABLE RelatedTable",
         to_sql!(RelatedTable.drop())
     );
+    assert_eq!(
+        "DROP TABLE TaggedTable, TaggedTable_tags",
+        to_sql!(TaggedTable.drop())
+    );
 }
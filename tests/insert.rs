@@ -40,6 +40,69 @@ struct RelatedTable {
     field1: String,
 }
 
+#[SqlTable]
+#[allow(dead_code)]
+#[unique(first_name, last_name)]
+struct Person {
+    id: PrimaryKey,
+    #[unique]
+    email: String,
+    first_name: String,
+    last_name: String,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct Event {
+    #[primary_key]
+    id: i64,
+    name: String,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct Account {
+    event: ForeignKey<Event>,
+    #[primary_key]
+    username: String,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct Comment {
+    #[default(false)]
+    hidden: bool,
+    id: PrimaryKey,
+    #[default = "0"]
+    likes: i32,
+    text: String,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+#[primary_key(tenant_id, id)]
+struct Node {
+    id: PrimaryKey,
+    tenant_id: i32,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct Child {
+    id: PrimaryKey,
+    #[foreign_key(tenant_id, parent)]
+    parent: ForeignKey<Node>,
+    tenant_id: i32,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+#[primary_key(post, tag)]
+struct PostTag {
+    post: ForeignKey<RelatedTable>,
+    tag: ForeignKey<RelatedTable>,
+}
+
 #[test]
 fn test_insert() {
     assert_eq!(
@@ -58,4 +121,88 @@ fn test_insert() {
         "INSERT INTO Table(field1, field2, related_field, optional_field) VALUES('value1', 55, $1, 42) RETURNING id",
         to_sql!(Table.insert(field1 = "value1", field2 = 55, related_field = related_object, optional_field = 42))
     );
+    assert_eq!(
+        "INSERT INTO PostTag(post, tag) VALUES($1, $2)",
+        to_sql!(PostTag.insert(post = related_object1, tag = related_object2))
+    );
+    assert_eq!(
+        "INSERT INTO Event(name) VALUES('Meetup') RETURNING id",
+        to_sql!(Event.insert(name = "Meetup"))
+    );
+    assert_eq!(
+        "INSERT INTO Account(username, event) VALUES('john', $1) RETURNING username",
+        to_sql!(Account.insert(username = "john", event = event))
+    );
+    assert_eq!(
+        "INSERT INTO Comment(text) VALUES('Hello') RETURNING id",
+        to_sql!(Comment.insert(text = "Hello"))
+    );
+    assert_eq!(
+        "INSERT INTO Comment(text, hidden) VALUES('Hello', TRUE) RETURNING id",
+        to_sql!(Comment.insert(text = "Hello", hidden = true))
+    );
+    assert_eq!(
+        "INSERT INTO Child(tenant_id, parent) VALUES($1, $2) RETURNING id",
+        to_sql!(Child.insert(parent = node))
+    );
+}
+
+#[test]
+fn test_insert_many() {
+    assert_eq!(
+        "INSERT INTO Table(field1, field2, optional_field, related_field) VALUES {} RETURNING id",
+        to_sql!(Table.insert_many(rows))
+    );
+    assert_eq!(
+        "INSERT INTO Table(field1, field2, related_field) VALUES {} RETURNING id",
+        to_sql!(Table.insert_many((field1, field2, related_field) = rows))
+    );
+    assert_eq!(
+        "INSERT INTO RelatedTable(field1) VALUES {} RETURNING id",
+        to_sql!(RelatedTable.insert_many(field1 = values))
+    );
+    assert_eq!(
+        "INSERT INTO Child(tenant_id, parent) VALUES {} RETURNING id",
+        to_sql!(Child.insert_many(parent = nodes))
+    );
+}
+
+#[test]
+fn test_insert_on_conflict() {
+    assert_eq!(
+        "INSERT INTO Table(id, field1, field2, related_field) VALUES(1, 'value1', 55, $1) ON CONFLICT (id) DO NOTHING RETURNING id",
+        to_sql!(Table.insert(id = 1, field1 = "value1", field2 = 55, related_field = related_object).on_conflict(id).ignore())
+    );
+    assert_eq!(
+        "INSERT INTO Table(field1, field2, related_field) VALUES('value1', 55, $1) ON CONFLICT DO NOTHING RETURNING id",
+        to_sql!(Table.insert(field1 = "value1", field2 = 55, related_field = related_object).on_conflict().ignore())
+    );
+    assert_eq!(
+        "INSERT INTO Table(id, field1, field2, related_field) VALUES(1, 'value1', 55, $1) ON CONFLICT (id) DO UPDATE SET field2 = EXCLUDED.field2 RETURNING id",
+        to_sql!(Table.insert(id = 1, field1 = "value1", field2 = 55, related_field = related_object).on_conflict(id).update(field2 = excluded::field2))
+    );
+    assert_eq!(
+        "INSERT INTO Table(id, field1, field2, related_field) VALUES(1, 'value1', $1, $2) ON CONFLICT (id) DO UPDATE SET field1 = EXCLUDED.field1, field2 = Table.field2 + $3 RETURNING id",
+        to_sql!(Table.insert(id = 1, field1 = "value1", field2 = new_field2, related_field = related_object).on_conflict(id).update(field1 = excluded::field1, field2 += new_field2))
+    );
+    assert_eq!(
+        "INSERT INTO Table(id, field1, field2, related_field) VALUES(1, 'value1', 55, $1) ON CONFLICT (id) DO UPDATE SET field2 = Table.field2 + EXCLUDED.field2 RETURNING id",
+        to_sql!(Table.insert(id = 1, field1 = "value1", field2 = 55, related_field = related_object).on_conflict(id).update(field2 += excluded::field2))
+    );
+    assert_eq!(
+        "INSERT INTO Table(id, field1, field2, related_field) VALUES(1, 'value1', $1, $2) ON CONFLICT (id) DO UPDATE SET field2 = (EXCLUDED.field2 * 2) + $3 RETURNING id",
+        to_sql!(Table.insert(id = 1, field1 = "value1", field2 = new_field2, related_field = related_object).on_conflict(id).update(field2 = excluded::field2 * 2 + new_field2))
+    );
+    assert_eq!(
+        "INSERT INTO Table(id, field1, field2, related_field) VALUES(1, 'value1', 55, $1) ON CONFLICT (id) DO UPDATE SET field2 = Table.field2 * (EXCLUDED.field2 + 1) RETURNING id",
+        to_sql!(Table.insert(id = 1, field1 = "value1", field2 = 55, related_field = related_object).on_conflict(id).update(field2 *= excluded::field2 + 1))
+    );
+    assert_eq!(
+        "INSERT INTO Person(email, first_name, last_name) VALUES('me@example.com', 'John', 'Doe') ON CONFLICT (email) DO NOTHING RETURNING id",
+        to_sql!(Person.insert(email = "me@example.com", first_name = "John", last_name = "Doe").on_conflict(email).ignore())
+    );
+    assert_eq!(
+        "INSERT INTO Person(email, first_name, last_name) VALUES($1, 'John', 'Doe') ON CONFLICT (last_name, first_name) DO UPDATE SET email = EXCLUDED.email RETURNING id",
+        to_sql!(Person.insert(email = email, first_name = "John", last_name = "Doe").on_conflict(last_name, first_name).update(email = excluded::email))
+    );
 }
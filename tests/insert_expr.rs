@@ -127,4 +127,40 @@ fn test_insert() {
     let int64 = 42i64;
     let id = sql!(TableInsertExpr.insert(field1 = new_field1, field2 = new_field2, related_field = related_field, optional_field = 12, boolean = boolean_value, /*character = character,*/ float32 = float32, float64 = float64, /*int8 = int8,*/ int16 = int16, int64 = int64)).unwrap();
     assert_eq!(4, id);
+
+    let rows = vec![("value5", 5, &related_field), ("value6", 6, &related_field)];
+    let ids = sql!(TableInsertExpr.insert_many((field1, field2, related_field) = rows)).unwrap();
+    assert_eq!(vec![5, 6], ids);
+
+    let tables = sql!(TableInsertExpr.filter(field2 < 10).join(related_field));
+    assert_eq!(2, tables.len());
+    let ids = sql!(TableInsertExpr.insert_many(tables)).unwrap();
+    assert_eq!(vec![7, 8], ids);
+
+    let tables = sql!(TableInsertExpr.filter(field2 < 10));
+    assert_eq!(4, tables.len());
+    assert_eq!("value5", tables[2].field1);
+    assert_eq!(5, tables[2].field2);
+    assert_eq!("value6", tables[3].field1);
+    assert_eq!(6, tables[3].field2);
+
+    let id = sql!(TableInsertExpr.insert(primary_key = 1, field1 = "conflict", field2 = 99, related_field = related_field).on_conflict(primary_key).ignore()).unwrap();
+    assert!(id.is_none());
+
+    let table = sql!(TableInsertExpr.get(1)).unwrap();
+    assert_eq!("value1", table.field1);
+    assert_eq!(55, table.field2);
+
+    let id = sql!(TableInsertExpr.insert(primary_key = 1, field1 = "conflict", field2 = 99, related_field = related_field).on_conflict(primary_key).update(field2 = excluded::field2)).unwrap();
+    assert_eq!(1, id);
+
+    let table = sql!(TableInsertExpr.get(1)).unwrap();
+    assert_eq!("value1", table.field1);
+    assert_eq!(99, table.field2);
+
+    let id = sql!(TableInsertExpr.insert(primary_key = 1, field1 = "conflict", field2 = 10, related_field = related_field).on_conflict(primary_key).update(field2 += excluded::field2 - 1)).unwrap();
+    assert_eq!(1, id);
+
+    let table = sql!(TableInsertExpr.get(1)).unwrap();
+    assert_eq!(108, table.field2);
 }
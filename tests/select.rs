@@ -24,7 +24,7 @@ extern crate tql;
 
 use chrono::datetime::DateTime;
 use chrono::offset::utc::UTC;
-use tql::{ForeignKey, PrimaryKey};
+use tql::{ForeignKey, ManyToMany, PrimaryKey};
 
 #[SqlTable]
 #[allow(dead_code)]
@@ -34,7 +34,9 @@ struct Table {
     field2: i32,
     field3: Option<i32>,
     related_field: ForeignKey<RelatedTable>,
+    optional_related_field: Option<ForeignKey<RelatedTable>>,
     date: DateTime<UTC>,
+    tags: ManyToMany<RelatedTable>,
 }
 
 #[SqlTable]
@@ -44,6 +46,80 @@ struct RelatedTable {
     field1: String,
 }
 
+#[SqlTable]
+#[allow(dead_code)]
+struct Review {
+    id: PrimaryKey,
+    optional_table: Option<ForeignKey<Table>>,
+    required_table: ForeignKey<Table>,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct TreeTable {
+    id: PrimaryKey,
+    field1: String,
+    parent: Option<ForeignKey<Box<TreeTable>>>,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct Category {
+    id: PrimaryKey,
+    parent: Option<ForeignKey<Box<Category>>>,
+}
+
+// NOTE: This table is named like the alias of Category joined with itself.
+#[SqlTable]
+#[allow(dead_code, non_camel_case_types)]
+struct Category_parent {
+    id: PrimaryKey,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+#[unique(first_name, last_name)]
+struct Person {
+    id: PrimaryKey,
+    #[unique]
+    email: String,
+    first_name: String,
+    last_name: String,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct Profile {
+    bio: String,
+    #[primary_key]
+    person: ForeignKey<Person>,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct Avatar {
+    id: PrimaryKey,
+    profile: ForeignKey<Profile>,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+#[primary_key(tenant_id, id)]
+struct Node {
+    id: PrimaryKey,
+    name: String,
+    tenant_id: i32,
+}
+
+#[SqlTable]
+#[allow(dead_code)]
+struct Child {
+    id: PrimaryKey,
+    #[foreign_key(tenant_id, parent)]
+    parent: ForeignKey<Node>,
+    tenant_id: i32,
+}
+
 const SELECT: &'static str = "SELECT Table.date, Table.field1, Table.field2, Table.field3, Table.id";
 
 #[test]
@@ -54,6 +130,82 @@ fn test_all() {
     );
 }
 
+#[test]
+fn test_count() {
+    assert_eq!(
+        "SELECT COUNT(*) FROM Table",
+        to_sql!(Table.count())
+    );
+    assert_eq!(
+        "SELECT COUNT(*) FROM Table WHERE field2 > 10 AND field1 = 'value1'",
+        to_sql!(Table.filter(field2 > 10 && field1 == "value1").count())
+    );
+    assert_eq!(
+        "SELECT COUNT(*) FROM Table INNER JOIN RelatedTable ON Table.related_field = RelatedTable.id WHERE Table.field2 > 10",
+        to_sql!(Table.filter(field2 > 10).join(related_field).count())
+    );
+}
+
+#[test]
+fn test_defer() {
+    assert_eq!(
+        "SELECT Table.date, Table.field1, Table.field2, Table.id FROM Table",
+        to_sql!(Table.defer(field3))
+    );
+    assert_eq!(
+        "SELECT Table.date, Table.id FROM Table WHERE field2 > 10",
+        to_sql!(Table.filter(field2 > 10).defer(field1, field3).defer(field2))
+    );
+}
+
+#[test]
+fn test_distinct() {
+    assert_eq!(
+        "SELECT DISTINCT Table.date, Table.field1, Table.field2, Table.field3, Table.id FROM Table",
+        to_sql!(Table.distinct())
+    );
+    assert_eq!(
+        "SELECT DISTINCT Table.date, Table.field1, Table.field2, Table.field3, Table.id FROM Table WHERE field2 > 10 ORDER BY field1",
+        to_sql!(Table.filter(field2 > 10).distinct().sort(field1))
+    );
+    assert_eq!(
+        "SELECT DISTINCT ON (field1) Table.date, Table.field1, Table.field2, Table.field3, Table.id FROM Table ORDER BY field1, date DESC",
+        to_sql!(Table.distinct_on(field1).sort(field1, -date))
+    );
+    assert_eq!(
+        "SELECT DISTINCT ON (field1, field2) Table.date, Table.field1, Table.field2, Table.field3, Table.id FROM Table ORDER BY field2 DESC, field1, date",
+        to_sql!(Table.distinct_on(field1, field2).sort(-field2, field1, date))
+    );
+    assert_eq!(
+        "SELECT DISTINCT ON (field1, field2) Table.date, Table.field1, Table.field2, Table.field3, Table.id FROM Table ORDER BY field1",
+        to_sql!(Table.distinct_on(field1, field2).sort(field1))
+    );
+    assert_eq!(
+        "SELECT DISTINCT ON (field1) Table.date, Table.field1, Table.field2, Table.field3, Table.id FROM Table",
+        to_sql!(Table.distinct_on(field1))
+    );
+    assert_eq!(
+        "SELECT DISTINCT field1 FROM Table",
+        to_sql!(Table.values(field1).distinct())
+    );
+    assert_eq!(
+        "SELECT DISTINCT field1, field2 FROM Table WHERE field2 > 10",
+        to_sql!(Table.filter(field2 > 10).values(field1, field2).distinct())
+    );
+}
+
+#[test]
+fn test_exists() {
+    assert_eq!(
+        "SELECT EXISTS(SELECT 1 FROM Table)",
+        to_sql!(Table.exists())
+    );
+    assert_eq!(
+        "SELECT EXISTS(SELECT 1 FROM Table WHERE field2 > 10)",
+        to_sql!(Table.filter(field2 > 10).exists())
+    );
+}
+
 #[test]
 fn test_filter() {
     assert_eq!(
@@ -102,6 +254,42 @@ fn test_filter() {
     );
 }
 
+#[test]
+fn test_filter_in() {
+    assert_eq!(
+        format!("{} FROM Table WHERE field2 IN (3, 4, 5)", SELECT),
+        to_sql!(Table.filter(field2.in([3, 4, 5])))
+    );
+    assert_eq!(
+        format!("{} FROM Table WHERE field1 IN ('value1', $1)", SELECT),
+        to_sql!(Table.filter(field1.in(["value1", value2])))
+    );
+    assert_eq!(
+        format!("{} FROM Table WHERE field2 BETWEEN 3 AND 5", SELECT),
+        to_sql!(Table.filter(field2.in(3..6)))
+    );
+    assert_eq!(
+        format!("{} FROM Table WHERE field2 BETWEEN $1 AND $2 - 1", SELECT),
+        to_sql!(Table.filter(field2.in(start..end)))
+    );
+    assert_eq!(
+        format!("{} FROM Table WHERE CHAR_LENGTH(field1) BETWEEN 3 AND 5", SELECT),
+        to_sql!(Table.filter(field1.len().in(3..6)))
+    );
+    assert_eq!(
+        format!("{} FROM Table WHERE field2 = ANY($1)", SELECT),
+        to_sql!(Table.filter(field2.in(values)))
+    );
+    assert_eq!(
+        format!("{} FROM Table WHERE field2 = ANY($1)", SELECT),
+        to_sql!(Table.filter(field2.in(&values[1..])))
+    );
+    assert_eq!(
+        format!("{} FROM Table WHERE NOT field2 IN (3, 4) AND field1 = 'value1'", SELECT),
+        to_sql!(Table.filter(!field2.in([3, 4]) && field1 == "value1"))
+    );
+}
+
 #[test]
 fn test_filter_method_call() {
     assert_eq!(
@@ -190,6 +378,34 @@ fn test_filter_sort_limit() {
     );
 }
 
+#[test]
+fn test_filter_subquery() {
+    assert_eq!(
+        format!("{} FROM Table WHERE related_field IN (SELECT RelatedTable.id FROM RelatedTable WHERE field1 = 'value1')", SELECT),
+        to_sql!(Table.filter(related_field.in(RelatedTable.filter(field1 == "value1").values(id))))
+    );
+    assert_eq!(
+        format!("{} FROM Table WHERE field2 > $1 AND related_field IN (SELECT RelatedTable.id FROM RelatedTable WHERE field1 = $2) AND field1 = $3", SELECT),
+        to_sql!(Table.filter(field2 > value1 && related_field.in(RelatedTable.filter(field1 == value2).values(id)) && field1 == value3))
+    );
+    assert_eq!(
+        format!("{} FROM Table WHERE field2 IN (SELECT DISTINCT field2 FROM Table WHERE field1 = $1)", SELECT),
+        to_sql!(Table.filter(field2.in(Table.filter(field1 == value1).values(field2).distinct())))
+    );
+    assert_eq!(
+        format!("{} FROM Table WHERE field2 IN (SELECT CAST(MAX(field2) AS INTEGER) FROM Table)", SELECT),
+        to_sql!(Table.filter(field2.in(Table.aggregate(max(field2)))))
+    );
+    assert_eq!(
+        format!("SELECT RelatedTable.field1, RelatedTable.id FROM RelatedTable WHERE EXISTS({} FROM Table WHERE related_field = RelatedTable.id)", SELECT),
+        to_sql!(RelatedTable.filter(exists(Table.filter(related_field == outer.id))))
+    );
+    assert_eq!(
+        format!("SELECT RelatedTable.field1, RelatedTable.id FROM RelatedTable WHERE field1 = $1 AND NOT EXISTS({} FROM Table WHERE related_field = RelatedTable.id AND field2 > $2)", SELECT),
+        to_sql!(RelatedTable.filter(field1 == value1 && !exists(Table.filter(related_field == outer.id && field2 > value2))))
+    );
+}
+
 #[test]
 fn test_get() {
     assert_eq!(
@@ -220,6 +436,30 @@ fn test_get() {
         format!("{} FROM Table WHERE NOT (field2 < 24) OFFSET 0 LIMIT 1", SELECT),
         to_sql!(Table.get(!(field2 < 24)))
     );
+    assert_eq!(
+        "SELECT Person.email, Person.first_name, Person.id, Person.last_name FROM Person WHERE email = 'me@example.com'",
+        to_sql!(Person.get(email == "me@example.com"))
+    );
+    assert_eq!(
+        "SELECT Person.email, Person.first_name, Person.id, Person.last_name FROM Person WHERE first_name = $1 AND last_name = $2",
+        to_sql!(Person.get(first_name == first_name && last_name == last_name))
+    );
+    assert_eq!(
+        "SELECT Person.email, Person.first_name, Person.id, Person.last_name FROM Person WHERE first_name = $1 OFFSET 0 LIMIT 1",
+        to_sql!(Person.get(first_name == first_name))
+    );
+    assert_eq!(
+        "SELECT Node.id, Node.name, Node.tenant_id FROM Node WHERE tenant_id = 1 AND id = 2",
+        to_sql!(Node.get((1, 2)))
+    );
+    assert_eq!(
+        "SELECT Node.id, Node.name, Node.tenant_id FROM Node WHERE tenant_id = $1 AND id = $2",
+        to_sql!(Node.get((tenant_id, id)))
+    );
+    assert_eq!(
+        "SELECT Node.id, Node.name, Node.tenant_id FROM Node WHERE tenant_id = $1 AND id = $2",
+        to_sql!(Node.get(key))
+    );
 }
 
 #[test]
@@ -232,6 +472,80 @@ fn test_join() {
         format!("{}, RelatedTable.field1, RelatedTable.id FROM Table INNER JOIN RelatedTable ON Table.related_field = RelatedTable.id", SELECT),
         to_sql!(Table.all().join(related_field))
     );
+    assert_eq!(
+        format!("{}, RelatedTable.field1, RelatedTable.id FROM Table LEFT JOIN RelatedTable ON Table.optional_related_field = RelatedTable.id", SELECT),
+        to_sql!(Table.join(optional_related_field))
+    );
+    assert_eq!(
+        format!("{}, RelatedTable.field1, RelatedTable.id FROM Table LEFT JOIN RelatedTable ON Table.related_field = RelatedTable.id", SELECT),
+        to_sql!(Table.left_join(related_field))
+    );
+    assert_eq!(
+        format!("{}, RelatedTable.field1, RelatedTable.id FROM Table INNER JOIN RelatedTable ON Table.optional_related_field = RelatedTable.id", SELECT),
+        to_sql!(Table.inner_join(optional_related_field))
+    );
+    assert_eq!(
+        format!("{}, Table_optional_related_field.field1, Table_optional_related_field.id, RelatedTable.field1, RelatedTable.id FROM Table INNER JOIN RelatedTable ON Table.related_field = RelatedTable.id LEFT JOIN RelatedTable AS Table_optional_related_field ON Table.optional_related_field = Table_optional_related_field.id", SELECT),
+        to_sql!(Table.join(related_field, optional_related_field))
+    );
+    assert_eq!(
+        format!("{}, RelatedTable.field1, RelatedTable.id, Table_related_field.field1, Table_related_field.id FROM Table LEFT JOIN RelatedTable ON Table.optional_related_field = RelatedTable.id INNER JOIN RelatedTable AS Table_related_field ON Table.related_field = Table_related_field.id", SELECT),
+        to_sql!(Table.join(optional_related_field).join(related_field))
+    );
+    assert_eq!(
+        "SELECT TreeTable.field1, TreeTable.id, TreeTable_parent.field1, TreeTable_parent.id FROM TreeTable LEFT JOIN TreeTable AS TreeTable_parent ON TreeTable.parent = TreeTable_parent.id",
+        to_sql!(TreeTable.join(parent))
+    );
+    assert_eq!(
+        "SELECT TreeTable.field1, TreeTable.id, TreeTable_parent.field1, TreeTable_parent.id FROM TreeTable LEFT JOIN TreeTable AS TreeTable_parent ON TreeTable.parent = TreeTable_parent.id WHERE TreeTable.field1 = 'value' ORDER BY TreeTable.id DESC",
+        to_sql!(TreeTable.filter(field1 == "value").join(parent).sort(-id))
+    );
+    assert_eq!(
+        "SELECT TreeTable.field1, TreeTable.id, TreeTable_parent.field1, TreeTable_parent.id, TreeTable_parent_parent.field1, TreeTable_parent_parent.id FROM TreeTable LEFT JOIN TreeTable AS TreeTable_parent ON TreeTable.parent = TreeTable_parent.id LEFT JOIN TreeTable AS TreeTable_parent_parent ON TreeTable_parent.parent = TreeTable_parent_parent.id",
+        to_sql!(TreeTable.join(parent.parent))
+    );
+    assert_eq!(
+        "SELECT TreeTable.field1, TreeTable.id, TreeTable_parent.field1, TreeTable_parent.id, TreeTable_parent_parent.field1, TreeTable_parent_parent.id FROM TreeTable INNER JOIN TreeTable AS TreeTable_parent ON TreeTable.parent = TreeTable_parent.id INNER JOIN TreeTable AS TreeTable_parent_parent ON TreeTable_parent.parent = TreeTable_parent_parent.id",
+        to_sql!(TreeTable.join(parent).inner_join(parent.parent))
+    );
+    assert_eq!(
+        "SELECT Review.id, Table.date, Table.field1, Table.field2, Table.field3, Table.id, RelatedTable.field1, RelatedTable.id FROM Review LEFT JOIN Table ON Review.optional_table = Table.id LEFT JOIN RelatedTable ON Table.related_field = RelatedTable.id",
+        to_sql!(Review.join(optional_table.related_field))
+    );
+    assert_eq!(
+        "SELECT Review.id, Table.date, Table.field1, Table.field2, Table.field3, Table.id, RelatedTable.field1, RelatedTable.id FROM Review LEFT JOIN Table ON Review.required_table = Table.id LEFT JOIN RelatedTable ON Table.related_field = RelatedTable.id",
+        to_sql!(Review.join(required_table.related_field).left_join(required_table))
+    );
+    // NOTE: The related objects of a many-to-many field are fetched with a second query.
+    assert_eq!(
+        format!("{}, RelatedTable.field1, RelatedTable.id FROM Table INNER JOIN RelatedTable ON Table.related_field = RelatedTable.id", SELECT),
+        to_sql!(Table.join(related_field, tags))
+    );
+    assert_eq!(
+        "SELECT Child.id, Node.id, Node.name, Node.tenant_id, Child.tenant_id FROM Child INNER JOIN Node ON Child.parent = Node.id AND Child.tenant_id = Node.tenant_id",
+        to_sql!(Child.join(parent))
+    );
+    assert_eq!(
+        "SELECT Category.id, Category_parent_2.id FROM Category LEFT JOIN Category AS Category_parent_2 ON Category.parent = Category_parent_2.id",
+        to_sql!(Category.join(parent))
+    );
+    // NOTE: The primary key of a joined table is fetched even when it is a foreign key.
+    assert_eq!(
+        "SELECT Avatar.id, Profile.person, Profile.bio FROM Avatar LEFT JOIN Profile ON Avatar.profile = Profile.person",
+        to_sql!(Avatar.left_join(profile))
+    );
+}
+
+#[test]
+fn test_filter_compound_foreign_key() {
+    assert_eq!(
+        "SELECT Child.id, Child.tenant_id FROM Child WHERE tenant_id = $1 AND parent = $2",
+        to_sql!(Child.filter(parent == node))
+    );
+    assert_eq!(
+        "SELECT Child.id, Child.tenant_id FROM Child WHERE NOT (tenant_id = $1 AND parent = $2)",
+        to_sql!(Child.filter(parent != node))
+    );
 }
 
 #[test]
@@ -309,3 +623,27 @@ fn test_limit() {
         to_sql!(Table.all()[-index as i64])
     );
 }
+
+#[test]
+fn test_only() {
+    assert_eq!(
+        "SELECT Table.date, Table.field1, Table.id FROM Table",
+        to_sql!(Table.only(field1, date))
+    );
+    assert_eq!(
+        "SELECT Table.date, Table.id, RelatedTable.field1, RelatedTable.id FROM Table INNER JOIN RelatedTable ON Table.related_field = RelatedTable.id",
+        to_sql!(Table.only(id, date).join(related_field))
+    );
+}
+
+#[test]
+fn test_prefetch() {
+    assert_eq!(
+        "SELECT RelatedTable.field1, RelatedTable.id FROM RelatedTable",
+        to_sql!(RelatedTable.prefetch(Table.related_field))
+    );
+    assert_eq!(
+        "SELECT RelatedTable.field1, RelatedTable.id FROM RelatedTable WHERE field1 = 'value'",
+        to_sql!(RelatedTable.filter(field1 == "value").prefetch(Table.optional_related_field))
+    );
+}
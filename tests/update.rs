@@ -79,3 +79,15 @@ fn test_update_operation() {
         to_sql!(Table.get(1).update(field2 += 10, field3 /= 3))
     );
 }
+
+#[test]
+fn test_update_returning() {
+    assert_eq!(
+        "UPDATE Table SET field2 = 55 WHERE field1 = 'value1' RETURNING Table.field1, Table.field2, Table.field3, Table.id",
+        to_sql!(Table.filter(field1 == "value1").update(field2 = 55).returning())
+    );
+    assert_eq!(
+        "UPDATE Table SET field2 = 55 WHERE field1 = 'value1' RETURNING id, field3",
+        to_sql!(Table.filter(field1 == "value1").update(field2 = 55).returning(id, field3))
+    );
+}
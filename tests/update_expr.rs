@@ -187,3 +187,31 @@ fn test_update_operation() {
     assert_eq!(0, table.field2);
     assert_eq!(3, table.field3);
 }
+
+#[test]
+fn test_update_returning() {
+    let table_mutex = DatabaseMutex::new();
+    let connection = &table_mutex.connection;
+
+    let id = sql!(RelatedTable.insert(field1 = "")).unwrap();
+    let related_field = sql!(RelatedTable.get(id)).unwrap();
+
+    let id1 = sql!(TableUpdateExpr.insert(field1 = "", field2 = 0, field3 = 1, related_field = related_field)).unwrap();
+    let id2 = sql!(TableUpdateExpr.insert(field1 = "", field2 = 0, field3 = 2, related_field = related_field)).unwrap();
+
+    let tables = sql!(TableUpdateExpr.filter(field3 > 1).update(field1 = "value1", field2 += 5).returning()).unwrap();
+    assert_eq!(1, tables.len());
+    assert_eq!(id2, tables[0].id);
+    assert_eq!("value1", tables[0].field1);
+    assert_eq!(5, tables[0].field2);
+    assert_eq!(2, tables[0].field3);
+    assert!(tables[0].related_field.is_none());
+
+    let rows = sql!(TableUpdateExpr.filter(field2 < 10).update(field2 += 1).returning(id, field2)).unwrap();
+    assert_eq!(2, rows.len());
+    assert!(rows.contains(&(id1, 1)));
+    assert!(rows.contains(&(id2, 6)));
+
+    let ids = sql!(TableUpdateExpr.get(id1).update(field1 = "value2").returning(id)).unwrap();
+    assert_eq!(vec![id1], ids);
+}
@@ -0,0 +1,138 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Aggregate functions definition for use in aggregate() and window functions definition for use
+//! in annotate().
+
+use state::{SqlAggregateTypes, aggregates_singleton, window_functions_singleton};
+use types::{Type, is_number};
+
+/// Add a new aggregate `rust_function` of type `argument_types` mapping to the SQL `template`.
+/// The template is the resulting SQL with `$0` as a placeholder for the aggregated field and `$1`,
+/// `$2`, … as placeholders for the other arguments.
+/// The `result_type` function gives the result type from the type of the aggregated field (or
+/// `None` if the function cannot aggregate this type).
+/// The same `rust_function` can be added many times with a different number of arguments.
+pub fn add_aggregate(rust_function: &str, argument_types: Vec<Type>, result_type: fn(&Type) -> Option<Type>, template: &str) {
+    let aggregates = aggregates_singleton();
+    let overloads = aggregates.entry(rust_function.to_owned()).or_insert(vec![]);
+    overloads.push(SqlAggregateTypes {
+        argument_types: argument_types,
+        result_type: result_type,
+        template: template.to_owned(),
+    });
+}
+
+/// Add a new window `rust_function` (a function which can only be called with over()).
+/// The arguments are the same as for `add_aggregate()`.
+pub fn add_window_function(rust_function: &str, argument_types: Vec<Type>, result_type: fn(&Type) -> Option<Type>, template: &str) {
+    let window_functions = window_functions_singleton();
+    let overloads = window_functions.entry(rust_function.to_owned()).or_insert(vec![]);
+    overloads.push(SqlAggregateTypes {
+        argument_types: argument_types,
+        result_type: result_type,
+        template: template.to_owned(),
+    });
+}
+
+/// Add the default SQL aggregate functions.
+/// The result of an aggregate function other than `count()` is NULL when there is no row to
+/// aggregate (for instance, in an empty group or in a LEFT JOIN without related rows).
+pub fn add_initial_aggregates() {
+    add_aggregate("array_agg", vec![Type::Generic], array_type, "ARRAY_AGG($0)");
+    add_aggregate("avg", vec![Type::Generic], average_type, "AVG($0)");
+    add_aggregate("bool_and", vec![Type::Bool], bool_type, "BOOL_AND($0)");
+    add_aggregate("bool_or", vec![Type::Bool], bool_type, "BOOL_OR($0)");
+    add_aggregate("count", vec![], count_type, "COUNT(*)");
+    add_aggregate("count", vec![Type::Generic], count_type, "COUNT($0)");
+    add_aggregate("count_distinct", vec![Type::Generic], count_type, "COUNT(DISTINCT $0)");
+    add_aggregate("max", vec![Type::Generic], nullable_type, "MAX($0)");
+    add_aggregate("min", vec![Type::Generic], nullable_type, "MIN($0)");
+    add_aggregate("string_agg", vec![Type::String, Type::String], string_type, "STRING_AGG($0, $1)");
+    add_aggregate("sum", vec![Type::Generic], sum_type, "SUM($0)");
+}
+
+/// Add the default SQL window functions.
+/// The aggregate functions can also be used as window functions.
+pub fn add_initial_window_functions() {
+    add_window_function("dense_rank", vec![], count_type, "DENSE_RANK()");
+    add_window_function("lag", vec![Type::Generic], nullable_type, "LAG($0)");
+    add_window_function("lag", vec![Type::Generic, Type::I32], nullable_type, "LAG($0, $1)");
+    add_window_function("lead", vec![Type::Generic], nullable_type, "LEAD($0)");
+    add_window_function("lead", vec![Type::Generic, Type::I32], nullable_type, "LEAD($0, $1)");
+    add_window_function("rank", vec![], count_type, "RANK()");
+    add_window_function("row_number", vec![], count_type, "ROW_NUMBER()");
+}
+
+/// The result of `array_agg()` is an array of the field type.
+fn array_type(typ: &Type) -> Option<Type> {
+    same_type(typ).map(|typ| Type::Nullable(box Type::Array(box typ)))
+}
+
+/// The average of numbers (even integers) is a floating-point number.
+fn average_type(typ: &Type) -> Option<Type> {
+    if is_number(typ) {
+        Some(Type::Nullable(box Type::F64))
+    }
+    else {
+        None
+    }
+}
+
+fn bool_type(_typ: &Type) -> Option<Type> {
+    Some(Type::Nullable(box Type::Bool))
+}
+
+/// The SQL COUNT() function returns a BIGINT.
+fn count_type(_typ: &Type) -> Option<Type> {
+    Some(Type::I64)
+}
+
+/// The result of `max()` and `min()` is of the field type.
+/// The result of `lag()` and `lead()` is also NULL when there is no row at the offset.
+fn nullable_type(typ: &Type) -> Option<Type> {
+    same_type(typ).map(|typ|
+        match typ {
+            Type::Nullable(_) => typ,
+            typ => Type::Nullable(box typ),
+        }
+    )
+}
+
+/// Get the type of the values of a field of type `typ`.
+fn same_type(typ: &Type) -> Option<Type> {
+    match *typ {
+        // NOTE: A foreign key or a many-to-many field cannot be aggregated this way.
+        Type::Custom(_) | Type::Generic | Type::ManyToMany(_) | Type::UnsupportedType(_) => None,
+        Type::Serial => Some(Type::I32),
+        ref typ => Some(typ.clone()),
+    }
+}
+
+fn string_type(_typ: &Type) -> Option<Type> {
+    Some(Type::Nullable(box Type::String))
+}
+
+/// The sum of integers is a BIGINT to avoid overflows.
+fn sum_type(typ: &Type) -> Option<Type> {
+    match *typ {
+        Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::Serial => Some(Type::Nullable(box Type::I64)),
+        Type::F32 => Some(Type::Nullable(box Type::F32)),
+        Type::F64 => Some(Type::Nullable(box Type::F64)),
+        _ => None,
+    }
+}
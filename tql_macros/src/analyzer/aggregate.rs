@@ -18,73 +18,140 @@
 /// Analyzer for the aggregate() method.
 
 use syntax::ast::{BinOp_, ExprAssign, ExprCall, ExprParen, ExprPath, ExprUnary};
-use syntax::ast::Expr_::ExprBinary;
+use syntax::ast::Expr_::{ExprBinary, ExprField, ExprLit};
 use syntax::ast::UnOp;
 use syntax::codemap::{Span, Spanned};
 
-use ast::{Aggregate, AggregateFilter, AggregateFilterExpression, AggregateFilters, Expression, Identifier};
+use ast::{Aggregate, AggregateFilter, AggregateFilterExpression, AggregateFilters, AggregateOperand, ArithmeticOperator, Expression, Identifier};
 use error::{SqlError, SqlResult, res};
-use state::{SqlTable, aggregates_singleton};
-use super::{check_argument_count, check_field, path_expr_to_identifier, propose_similar_name};
+use gen::ToSql;
+use state::{SqlAggregate, SqlAggregates, SqlTable, aggregates_singleton};
+use string::plural_verb;
+use types::{Type, is_number};
+use super::{check_field, check_type, mismatched_types, path_expr_to_identifier, propose_similar_name};
 use super::filter::{binop_to_logical_operator, binop_to_relational_operator, is_logical_operator, is_relational_operator};
 
 /// Convert an `Expression` to an `Aggregate`.
-pub fn argument_to_aggregate(arg: &Expression, _table: &SqlTable) -> SqlResult<Aggregate> {
+pub fn argument_to_aggregate(arg: &Expression, table: &SqlTable) -> SqlResult<Aggregate> {
+    expression_to_aggregate(arg, &mut |expression, errors| table_field(expression, table, errors))
+}
+
+/// Convert an `Expression` to an `Aggregate`.
+/// The `resolve_field` function converts a field expression to its SQL identifier and type.
+pub fn expression_to_aggregate(arg: &Expression, resolve_field: &mut FnMut(&Expression, &mut Vec<SqlError>) -> Option<(Identifier, Type)>) -> SqlResult<Aggregate> {
     let mut errors = vec![];
     let mut aggregate = Aggregate::default();
-    let aggregates = aggregates_singleton();
+    let aggregates: &SqlAggregates = aggregates_singleton();
 
     let call = get_call_from_aggregate(arg, &mut aggregate, &mut errors);
+    call_to_aggregate(call, &[aggregates], &mut aggregate, resolve_field, &mut errors);
+
+    res(aggregate, errors)
+}
+
+/// Convert an `Expression` to a group `Identifier`.
+pub fn argument_to_group(arg: &Expression, table: &SqlTable) -> SqlResult<Identifier> {
+    let mut errors = vec![];
+    let mut group = "".to_owned();
 
+    if let Some(identifier) = path_expr_to_identifier(arg, &mut errors) {
+        check_field(&identifier, arg.span, table, &mut errors);
+        group = identifier;
+    }
+
+    res(group, errors)
+}
+
+/// Convert a function `call` to an `Aggregate` using the function definitions from the first of
+/// the `functions` collections containing it.
+/// The `resolve_field` function converts a field expression to its SQL identifier and type.
+pub fn call_to_aggregate(call: &Expression, functions: &[&SqlAggregates], aggregate: &mut Aggregate, resolve_field: &mut FnMut(&Expression, &mut Vec<SqlError>) -> Option<(Identifier, Type)>, errors: &mut Vec<SqlError>) {
     if let ExprCall(ref function, ref arguments) = call.node {
-        if let Some(identifier) = path_expr_to_identifier(function, &mut errors) {
-            if let Some(sql_function) = aggregates.get(&identifier) {
-                aggregate.function = sql_function.clone();
+        if let Some(identifier) = path_expr_to_identifier(function, errors) {
+            if let Some(overloads) = functions.iter().filter_map(|functions| functions.get(&identifier)).next() {
+                let aggregate_types = overloads.iter().find(|types| types.argument_types.len() == arguments.len());
+                if let Some(aggregate_types) = aggregate_types {
+                    aggregate.function = identifier.clone();
+                    aggregate.template = aggregate_types.template.clone();
+
+                    if arguments.is_empty() {
+                        if aggregate.result_name.is_empty() {
+                            aggregate.result_name = identifier.clone();
+                        }
+                        aggregate.result_type = (aggregate_types.result_type)(&Type::Generic).unwrap_or(Type::Generic);
+                    }
+                    else if let Some((operand, operand_type)) = expression_to_aggregate_operand(&arguments[0], resolve_field, errors) {
+                        if let Some(operand_type) = operand_type {
+                            let expected_type = &aggregate_types.argument_types[0];
+                            if *expected_type != Type::Generic && operand_type != *expected_type {
+                                mismatched_types(expected_type, &operand_type, arguments[0].span, errors);
+                            }
+                            else {
+                                match (aggregate_types.result_type)(&operand_type) {
+                                    Some(result_type) => aggregate.result_type = result_type,
+                                    None => {
+                                        errors.push(SqlError::new(
+                                            &format!("no aggregate function named `{}` found for type `{}`", identifier, operand_type),
+                                            call.span,
+                                        ));
+                                    },
+                                }
+                            }
+                        }
+                        else {
+                            errors.push(SqlError::new(
+                                "Expected a field in the aggregated expression", // TODO: improve this message.
+                                arguments[0].span,
+                            ));
+                        }
+
+                        if aggregate.result_name.is_empty() {
+                            let mut fields = vec![];
+                            operand_fields(&operand, &mut fields);
+                            aggregate.result_name = fields.join("_") + "_" + &identifier;
+                        }
+                        aggregate.operand = Some(operand);
+                    }
+
+                    for (argument, argument_type) in arguments.iter().zip(&aggregate_types.argument_types).skip(1) {
+                        check_type(argument_type, argument, errors);
+                        aggregate.arguments.push(argument.clone());
+                    }
+                }
+                else {
+                    wrong_argument_count(overloads, arguments.len(), call.span, errors);
+                }
             }
             else {
                 errors.push(SqlError::new_with_code(
                     &format!("unresolved name `{}`", identifier),
-                    arg.span,
+                    call.span,
                     "E0425",
                 ));
-                propose_similar_name(&identifier, aggregates.keys(), arg.span, &mut errors);
-            }
-        }
-
-        if check_argument_count(arguments, 1, arg.span, &mut errors) {
-            if let ExprPath(_, ref path) = arguments[0].node {
-                aggregate.field = path.segments[0].identifier.to_string();
-
-                if aggregate.result_name.is_empty() {
-                    aggregate.result_name = aggregate.field.clone() + "_" + &aggregate.function.to_lowercase();
-                }
-            }
-            else {
-                // TODO: allow expressions (field2 / field1).
+                let names = functions.iter().flat_map(|functions| functions.keys());
+                propose_similar_name(&identifier, names, call.span, errors);
             }
         }
     }
     else {
         errors.push(SqlError::new(
             "Expected function call", // TODO: improve this message.
-            arg.span,
+            call.span,
         ));
     }
-
-    res(aggregate, errors)
 }
 
-/// Convert an `Expression` to a group `Identifier`.
-pub fn argument_to_group(arg: &Expression, table: &SqlTable) -> SqlResult<Identifier> {
-    let mut errors = vec![];
-    let mut group = "".to_owned();
-
-    if let Some(identifier) = path_expr_to_identifier(arg, &mut errors) {
-        check_field(&identifier, arg.span, table, &mut errors);
-        group = identifier;
+/// Convert a `BinOp_` to an `ArithmeticOperator`.
+/// Returns `None` if the operator is not an arithmetic operator.
+pub fn binop_to_arithmetic_operator(binop: BinOp_) -> Option<ArithmeticOperator> {
+    match binop {
+        BinOp_::BiAdd => Some(ArithmeticOperator::Add),
+        BinOp_::BiSub => Some(ArithmeticOperator::Sub),
+        BinOp_::BiMul => Some(ArithmeticOperator::Mul),
+        BinOp_::BiDiv => Some(ArithmeticOperator::Divide),
+        BinOp_::BiRem => Some(ArithmeticOperator::Modulo),
+        _ => None,
     }
-
-    res(group, errors)
 }
 
 /// Convert a Rust binary expression to an `AggregateFilterExpression` for an aggregate filter.
@@ -177,8 +244,88 @@ pub fn expression_to_aggregate_filter_expression(arg: &Expression, aggregates: &
     res(filter, errors)
 }
 
+/// Convert a Rust expression to an `AggregateOperand` with its type.
+/// The type is `None` when the operand only contains literals (their type depends on the other
+/// operands).
+/// Returns `None` if the expression is not a valid operand.
+fn expression_to_aggregate_operand(expression: &Expression, resolve_field: &mut FnMut(&Expression, &mut Vec<SqlError>) -> Option<(Identifier, Type)>, errors: &mut Vec<SqlError>) -> Option<(AggregateOperand, Option<Type>)> {
+    match expression.node {
+        ExprBinary(Spanned { node: op, span }, ref expr1, ref expr2) => {
+            let operator =
+                match binop_to_arithmetic_operator(op) {
+                    Some(operator) => operator,
+                    None => {
+                        errors.push(SqlError::new(
+                            "Expected arithmetic operation", // TODO: improve this message.
+                            span,
+                        ));
+                        return None;
+                    },
+                };
+            let operand1 = expression_to_aggregate_operand(expr1, resolve_field, errors);
+            let operand2 = expression_to_aggregate_operand(expr2, resolve_field, errors);
+            if let (Some((operand1, type1)), Some((operand2, type2))) = (operand1, operand2) {
+                let typ =
+                    match (type1, type2) {
+                        (Some(type1), Some(type2)) => {
+                            if type1 != type2 {
+                                mismatched_types(&type1, &type2, expr2.span, errors);
+                            }
+                            Some(type1)
+                        },
+                        (Some(typ), None) => {
+                            check_type(&typ, expr2, errors);
+                            Some(typ)
+                        },
+                        (None, Some(typ)) => {
+                            check_type(&typ, expr1, errors);
+                            Some(typ)
+                        },
+                        (None, None) => None,
+                    };
+                if let Some(ref typ) = typ {
+                    if !is_number(typ) {
+                        errors.push(SqlError::new_with_code(
+                            &format!("binary operation `{}` cannot be applied to type `{}`", operator.to_sql(), typ),
+                            expression.span,
+                            "E0369",
+                        ));
+                    }
+                }
+                Some((AggregateOperand::Binary(box operand1, operator, box operand2), typ))
+            }
+            else {
+                None
+            }
+        },
+        ExprLit(_) => Some((AggregateOperand::Literal(expression.clone()), None)),
+        ExprField(_, _) | ExprPath(None, _) => {
+            resolve_field(expression, errors)
+                .map(|(identifier, typ)| (AggregateOperand::Field(identifier), Some(typ)))
+        },
+        ExprParen(ref expr) => expression_to_aggregate_operand(expr, resolve_field, errors),
+        _ => {
+            errors.push(SqlError::new(
+                "Expected field or arithmetic operation", // TODO: improve this message.
+                expression.span,
+            ));
+            None
+        },
+    }
+}
+
+/// Get the type of an aggregate operand from the type of a field.
+pub fn field_operand_type(field_type: &Type) -> Type {
+    match *field_type {
+        // NOTE: The NULL values are ignored by the aggregate functions.
+        Type::Nullable(box ref typ) => typ.clone(),
+        Type::Serial => Type::I32,
+        ref typ => typ.clone(),
+    }
+}
+
 /// Get the call expression from an `arg` expression.
-fn get_call_from_aggregate<'a>(arg: &'a Expression, aggregate: &mut Aggregate, errors: &mut Vec<SqlError>) -> &'a Expression {
+pub fn get_call_from_aggregate<'a>(arg: &'a Expression, aggregate: &mut Aggregate, errors: &mut Vec<SqlError>) -> &'a Expression {
     // If the `arg` expression is an assignment, the call is on the right side.
     if let ExprAssign(ref left_value, ref right_value) = arg.node {
         if let Some(identifier) = path_expr_to_identifier(left_value, errors) {
@@ -190,3 +337,60 @@ fn get_call_from_aggregate<'a>(arg: &'a Expression, aggregate: &mut Aggregate, e
         arg
     }
 }
+
+/// Add the fields used in the `operand` to `fields`.
+fn operand_fields(operand: &AggregateOperand, fields: &mut Vec<Identifier>) {
+    match *operand {
+        AggregateOperand::Binary(ref operand1, _, ref operand2) => {
+            operand_fields(operand1, fields);
+            operand_fields(operand2, fields);
+        },
+        AggregateOperand::Field(ref identifier) => {
+            // NOTE: The table name (of an annotation field) is not part of the name.
+            let name = identifier.rsplit('.').next().unwrap_or(identifier);
+            fields.push(name.to_owned());
+        },
+        AggregateOperand::Literal(_) => (),
+    }
+}
+
+/// Get the identifier and the type of a field of the `table` from a field `expression`.
+pub fn table_field(expression: &Expression, table: &SqlTable, errors: &mut Vec<SqlError>) -> Option<(Identifier, Type)> {
+    if let ExprPath(None, ref path) = expression.node {
+        let identifier = path.segments[0].identifier.to_string();
+        check_field(&identifier, path.span, table, errors);
+        table.fields.get(&identifier)
+            .map(|field_type| (identifier.clone(), field_operand_type(&field_type.node)))
+    }
+    else {
+        errors.push(SqlError::new(
+            "Expected field or arithmetic operation", // TODO: improve this message.
+            expression.span,
+        ));
+        None
+    }
+}
+
+/// Add an error for an aggregate call with a number of arguments not matching any of the
+/// `overloads`.
+fn wrong_argument_count(overloads: &SqlAggregate, argument_count: usize, position: Span, errors: &mut Vec<SqlError>) {
+    let counts: Vec<_> = overloads.iter()
+        .map(|types| types.argument_types.len().to_string())
+        .collect();
+    let max_count = overloads.iter()
+        .map(|types| types.argument_types.len())
+        .max()
+        .unwrap_or(0);
+    let plural =
+        if max_count == 1 {
+            ""
+        }
+        else {
+            "s"
+        };
+    errors.push(SqlError::new_with_code(
+        &format!("this function takes {} parameter{} but {} parameter{} supplied", counts.join(" or "), plural, argument_count, plural_verb(argument_count)),
+        position,
+        "E0061",
+    ));
+}
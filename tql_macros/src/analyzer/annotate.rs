@@ -0,0 +1,205 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+
+/// Analyzer for the annotate() method.
+
+use syntax::ast::Expr_::{ExprField, ExprPath};
+use syntax::codemap::Span;
+
+use ast::{Aggregate, Expression, Identifier, Join, JoinType, Window};
+use error::{SqlError, SqlResult};
+use state::{SqlTable, get_foreign_key_fields, get_primary_key_fields, tables_singleton};
+use types::{Type, get_related_table_name};
+use super::{check_field, no_primary_key, unknown_table_error};
+use super::aggregate::{expression_to_aggregate, field_operand_type};
+use super::window::{argument_to_window, is_window};
+
+/// An annotation of the objects.
+pub enum Annotation {
+    /// An aggregate over the rows of a related table with the `Join` fetching these rows.
+    Aggregate(Aggregate, Join),
+    /// A window function over the rows of the table.
+    Window(Window),
+}
+
+/// Convert an `Expression` to an `Annotation`.
+pub fn argument_to_annotation(arg: &Expression, table: &SqlTable) -> SqlResult<Annotation> {
+    if is_window(arg) {
+        argument_to_window(arg, table).map(Annotation::Window)
+    }
+    else {
+        argument_to_related_aggregate(arg, table).map(|(aggregate, join)| Annotation::Aggregate(aggregate, join))
+    }
+}
+
+/// Convert an `Expression` to an `Aggregate` over the rows of a related table and to the `Join`
+/// fetching these rows.
+fn argument_to_related_aggregate(arg: &Expression, table: &SqlTable) -> SqlResult<(Aggregate, Join)> {
+    let mut join = None;
+
+    let aggregate = {
+        let mut resolve_field = |expression: &Expression, errors: &mut Vec<SqlError>| {
+            related_table_field(expression, table, &mut join, errors)
+        };
+        try!(expression_to_aggregate(arg, &mut resolve_field))
+    };
+
+    match join {
+        Some(join) => Ok((aggregate, join)),
+        None => Err(vec![
+            SqlError::new(
+                "Expected a field of a related table in the annotation",
+                arg.span,
+            ),
+            SqlError::new_help(
+                "use a field of a table having a foreign key to this table, like `count(Table.field)`",
+                arg.span,
+            ),
+        ]),
+    }
+}
+
+/// Create the `Join` fetching the rows of the `related_table` referencing the `table`.
+/// The foreign key is the aggregated `field` if it references the `table`, otherwise the only
+/// foreign key of the `related_table` referencing the `table`.
+fn new_annotation_join(table: &SqlTable, related_table: &SqlTable, field: &str, field_type: &Type, position: Span, errors: &mut Vec<SqlError>) -> Option<Join> {
+    if get_primary_key_fields(table).is_empty() {
+        errors.push(no_primary_key(&table.name, position));
+        return None;
+    }
+
+    let foreign_key =
+        if get_related_table_name(field_type) == Some(&table.name) {
+            field.to_owned()
+        }
+        else {
+            let foreign_keys: Vec<_> = related_table.fields.iter()
+                .filter(|&(_, typ)| get_related_table_name(&typ.node) == Some(&table.name))
+                .map(|(field, _)| field.clone())
+                .collect();
+            match foreign_keys.len() {
+                0 => {
+                    errors.push(SqlError::new(
+                        &format!("no foreign key to the table `{}` found in the table `{}`", table.name, related_table.name),
+                        position,
+                    ));
+                    return None;
+                },
+                1 => foreign_keys[0].clone(),
+                _ => {
+                    errors.push(SqlError::new(
+                        &format!("many foreign keys to the table `{}` found in the table `{}`", table.name, related_table.name),
+                        position,
+                    ));
+                    errors.push(SqlError::new_help(
+                        "aggregate the foreign key field to choose which one to use, like `count(Table.foreign_key)`",
+                        position,
+                    ));
+                    return None;
+                },
+            }
+        };
+
+    // NOTE: A table annotated with its own rows needs an alias.
+    let alias =
+        if related_table.name == table.name {
+            related_table.name.clone() + "_" + &foreign_key
+        }
+        else {
+            related_table.name.clone()
+        };
+
+    let mut key_fields: Vec<_> = get_foreign_key_fields(related_table, &foreign_key, table).into_iter()
+        .map(|(foreign_key_field, primary_key_field)| (primary_key_field, foreign_key_field))
+        .collect();
+    // NOTE: The foreign key field references the last field of the primary key. The missing
+    // #[foreign_key] attribute of a foreign key referencing a compound primary key is reported by
+    // the lint.
+    let (primary_key_field, _) =
+        match key_fields.pop() {
+            Some(fields) => fields,
+            None => return None,
+        };
+
+    Some(Join {
+        alias: alias,
+        base_field: primary_key_field,
+        base_table: table.name.clone(),
+        joined_field: foreign_key,
+        joined_table: related_table.name.clone(),
+        key_fields: key_fields,
+        path: vec![],
+        typ: JoinType::Left,
+    })
+}
+
+/// Get the identifier and the type of a related table field from a field `expression` (like
+/// `Table.field`).
+/// The `join` to the related table is created from the first field.
+fn related_table_field(expression: &Expression, table: &SqlTable, join: &mut Option<Join>, errors: &mut Vec<SqlError>) -> Option<(Identifier, Type)> {
+    if let ExprField(ref related_expression, ref field) = expression.node {
+        if let ExprPath(None, ref path) = related_expression.node {
+            let related_table_name = path.segments[0].identifier.to_string();
+            let sql_tables = tables_singleton();
+            match sql_tables.get(&related_table_name) {
+                Some(related_table) => {
+                    let field_name = field.node.to_string();
+                    check_field(&field_name, field.span, related_table, errors);
+                    let field_type =
+                        match related_table.fields.get(&field_name) {
+                            Some(field_type) => field_type.node.clone(),
+                            None => return None,
+                        };
+                    if join.is_none() {
+                        *join = new_annotation_join(table, related_table, &field_name, &field_type, expression.span, errors);
+                    }
+                    match *join {
+                        Some(ref join) if join.joined_table == related_table_name =>
+                            Some((join.alias.clone() + "." + &field_name, field_operand_type(&field_type))),
+                        Some(_) => {
+                            errors.push(SqlError::new(
+                                "cannot aggregate the fields of many tables in the same annotation",
+                                expression.span,
+                            ));
+                            None
+                        },
+                        None => None,
+                    }
+                },
+                None => {
+                    unknown_table_error(&related_table_name, path.span, sql_tables, errors);
+                    None
+                },
+            }
+        }
+        else {
+            errors.push(SqlError::new(
+                "Expected table name", // TODO: improve this message.
+                related_expression.span,
+            ));
+            None
+        }
+    }
+    else {
+        errors.push(SqlError::new(
+            "Expected a field of a related table in the annotation",
+            expression.span,
+        ));
+        None
+    }
+}
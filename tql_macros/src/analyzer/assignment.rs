@@ -21,26 +21,71 @@ use syntax::ast::BinOp_;
 use syntax::ast::Expr_::{ExprAssign, ExprAssignOp};
 use syntax::codemap::Spanned;
 
-use ast::{Assignment, AssignementOperator, Expression, FilterValue};
+use ast::{Assignment, AssignementOperator, AssignmentValue, Expression, FilterValue};
 use error::{SqlError, SqlResult, res};
-use plugin::number_literal;
-use state::SqlTable;
-use super::{check_field, check_field_type, path_expr_to_identifier};
+use gen::ToSql;
+use plugin::{field_access_by_name, number_literal};
+use state::{SqlTable, get_field_type, get_foreign_key_fields};
+use super::{check_field, check_field_type, get_compound_key_related_table, mismatched_types, path_expr_to_identifier};
+use types::{Type, is_number};
 
 /// Analyze the types of the `Assignment`s.
 pub fn analyze_assignments_types(assignments: &[Assignment], table_name: &str, errors: &mut Vec<SqlError>) {
     for assignment in assignments {
-        check_field_type(table_name, &FilterValue::Identifier(assignment.identifier.clone()), &assignment.value, errors);
+        analyze_assignment_value_types(assignment, &assignment.value, table_name, errors);
+    }
+}
+
+/// Analyze the types of the `value` (or a part of it) assigned by the `assignment`.
+fn analyze_assignment_value_types(assignment: &Assignment, value: &AssignmentValue, table_name: &str, errors: &mut Vec<SqlError>) {
+    // NOTE: At this stage (type analysis), the fields exist, hence unwrap().
+    let field_type = get_field_type(table_name, &assignment.identifier).unwrap();
+    match *value {
+        AssignmentValue::Excluded(ref identifier) => {
+            let excluded_type = get_field_type(table_name, identifier).unwrap();
+            if field_type != excluded_type {
+                mismatched_types(field_type, &excluded_type, assignment.operator.span, errors);
+            }
+        },
+        AssignmentValue::Expression(ref value) => {
+            check_field_type(table_name, &FilterValue::Identifier(assignment.identifier.clone()), value, errors);
+        },
+        AssignmentValue::Operation(ref operand1, ref operator, ref operand2) => {
+            let operand_type =
+                match *field_type {
+                    Type::Nullable(ref typ) => &**typ,
+                    ref typ => typ,
+                };
+            if !is_number(operand_type) {
+                errors.push(SqlError::new_with_code(
+                    &format!("binary operation `{}` cannot be applied to type `{}`", operator.to_sql(), field_type),
+                    assignment.operator.span,
+                    "E0369",
+                ));
+            }
+            analyze_assignment_value_types(assignment, operand1, table_name, errors);
+            analyze_assignment_value_types(assignment, operand2, table_name, errors);
+        },
     }
 }
 
 /// Convert an `Expression` to an `Assignment`.
 pub fn argument_to_assignment(arg: &Expression, table: &SqlTable) -> SqlResult<Assignment> {
     fn assign_values(assignment: &mut Assignment, expr1: &Expression, expr2: &Expression, table: &SqlTable, errors: &mut Vec<SqlError>) {
-        assignment.value = expr2.clone();
+        assignment.value = AssignmentValue::Expression(expr2.clone());
         if let Some(identifier) = path_expr_to_identifier(expr1, errors) {
             assignment.identifier = identifier;
             check_field(&assignment.identifier, expr1.span, table, errors);
+            if let Some(&Type::ManyToMany(_)) = table.fields.get(&assignment.identifier).map(|typ| &typ.node) {
+                errors.push(SqlError::new(
+                    &format!("cannot assign the many-to-many field `{}`", assignment.identifier),
+                    expr1.span,
+                ));
+                errors.push(SqlError::new_help(
+                    &format!("use the add_{}() method", assignment.identifier),
+                    expr1.span,
+                ));
+            }
         }
     }
 
@@ -51,7 +96,7 @@ pub fn argument_to_assignment(arg: &Expression, table: &SqlTable) -> SqlResult<A
             node: AssignementOperator::Equal,
             span: arg.span,
         },
-        value: number_literal(0),
+        value: AssignmentValue::Expression(number_literal(0)),
     };
     match arg.node {
         ExprAssign(ref expr1, ref expr2) => {
@@ -74,6 +119,61 @@ pub fn argument_to_assignment(arg: &Expression, table: &SqlTable) -> SqlResult<A
     res(assignment, errors)
 }
 
+/// Expand the assignments of the foreign keys referencing a compound primary key: each field of
+/// the foreign key is assigned the corresponding field of the related object.
+/// For instance, `parent = node` is expanded to `tenant_id = node.tenant_id, parent = node.id`.
+pub fn expand_compound_foreign_keys(assignments: Vec<Assignment>, table: &SqlTable, errors: &mut Vec<SqlError>) -> Vec<Assignment> {
+    let assigned_fields: Vec<_> = assignments.iter()
+        .map(|assignment| assignment.identifier.clone())
+        .collect();
+    let mut expanded_assignments = vec![];
+
+    for assignment in assignments {
+        match get_compound_key_related_table(table, &assignment.identifier) {
+            Some(related_table) => {
+                let key_fields = get_foreign_key_fields(table, &assignment.identifier, related_table);
+                for (field, primary_key_field) in key_fields {
+                    if field != assignment.identifier && assigned_fields.contains(&field) {
+                        errors.push(SqlError::new(
+                            &format!("the field `{}` is already assigned by the foreign key `{}`", field, assignment.identifier),
+                            assignment.operator.span,
+                        ));
+                    }
+                    let value =
+                        match assignment.value {
+                            AssignmentValue::Excluded(ref identifier) if *identifier == assignment.identifier =>
+                                AssignmentValue::Excluded(field.clone()),
+                            AssignmentValue::Expression(ref expression) =>
+                                AssignmentValue::Expression(field_access_by_name(expression.clone(), expression.span, &primary_key_field)),
+                            _ => {
+                                errors.push(SqlError::new(
+                                    &format!("cannot assign this value to the foreign key `{}` which references the compound primary key of `{}`",
+                                        assignment.identifier,
+                                        related_table.name
+                                    ),
+                                    assignment.operator.span,
+                                ));
+                                errors.push(SqlError::new_help(
+                                    &format!("assign an object of `{}` or excluded::{}", related_table.name, assignment.identifier),
+                                    assignment.operator.span,
+                                ));
+                                break;
+                            },
+                        };
+                    expanded_assignments.push(Assignment {
+                        identifier: field,
+                        operator: assignment.operator.clone(),
+                        value: value,
+                    });
+                }
+            },
+            None => expanded_assignments.push(assignment),
+        }
+    }
+
+    expanded_assignments
+}
+
 /// Convert a `BinOp_` to an SQL `AssignmentOperator`.
 fn binop_to_assignment_operator(binop: BinOp_) -> AssignementOperator {
     match binop {
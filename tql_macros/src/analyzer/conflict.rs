@@ -0,0 +1,164 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+/// Analyzer for the on_conflict() method.
+
+use syntax::ast::Expr_::{ExprAddrOf, ExprBinary, ExprCall, ExprCast, ExprField, ExprIndex, ExprMethodCall, ExprParen, ExprPath, ExprTup, ExprTupField, ExprUnary, ExprVec};
+use syntax::codemap::Span;
+
+use ast::{Assignment, AssignmentValue, Expression, Identifier};
+use error::{SqlError, SqlResult, res};
+use state::{SqlTable, get_primary_key_fields, get_unique_field_lists};
+use super::{check_field, path_expr_to_identifier};
+use super::aggregate::binop_to_arithmetic_operator;
+use super::assignment::argument_to_assignment;
+
+/// Convert an argument of the update() method following on_conflict() to an `Assignment`.
+/// The `excluded::field` values refer to the values of the row proposed for insertion.
+pub fn argument_to_conflict_assignment(arg: &Expression, table: &SqlTable) -> SqlResult<Assignment> {
+    let mut errors = vec![];
+    let mut assignment = try!(argument_to_assignment(arg, table));
+
+    let value =
+        if let AssignmentValue::Expression(ref value) = assignment.value {
+            Some(expression_to_conflict_value(value, table, &mut errors))
+        }
+        else {
+            None
+        };
+
+    if let Some(value) = value {
+        assignment.value = value;
+    }
+
+    res(assignment, errors)
+}
+
+/// Convert an argument of the on_conflict() method to a field `Identifier`.
+pub fn argument_to_conflict_target(arg: &Expression, table: &SqlTable) -> SqlResult<Identifier> {
+    let mut errors = vec![];
+    let mut identifier = "".to_owned();
+    if let Some(field) = path_expr_to_identifier(arg, &mut errors) {
+        check_field(&field, arg.span, table, &mut errors);
+        identifier = field;
+    }
+    res(identifier, errors)
+}
+
+/// Check that the conflict target `fields` matches a unique key of the `table`.
+pub fn check_conflict_target(fields: &[Identifier], position: Span, table: &SqlTable, errors: &mut Vec<SqlError>) {
+    // NOTE: an empty target matches any constraint.
+    if fields.is_empty() {
+        return;
+    }
+
+    let primary_key = get_primary_key_fields(table);
+    let matches_primary_key = !primary_key.is_empty() && is_same_field_set(fields, &primary_key);
+    let matches_unique_key = get_unique_field_lists(table).iter()
+        .any(|unique_key| is_same_field_set(fields, unique_key));
+
+    if !matches_primary_key && !matches_unique_key {
+        errors.push(SqlError::new(
+            "there is no unique or exclusion constraint matching the on_conflict() specification",
+            position,
+        ));
+        if !primary_key.is_empty() {
+            errors.push(SqlError::new_help(
+                &format!("did you mean on_conflict({})?", primary_key.join(", ")),
+                position,
+            ));
+        }
+    }
+}
+
+/// Check if the `expression` contains an `excluded::field` value.
+fn contains_excluded_field(expression: &Expression) -> bool {
+    if excluded_field_name(expression).is_some() {
+        return true;
+    }
+
+    match expression.node {
+        ExprAddrOf(_, ref expr) | ExprCast(ref expr, _) | ExprField(ref expr, _) | ExprParen(ref expr) |
+            ExprTupField(ref expr, _) | ExprUnary(_, ref expr) => contains_excluded_field(expr),
+        ExprBinary(_, ref expr1, ref expr2) | ExprIndex(ref expr1, ref expr2) =>
+            contains_excluded_field(expr1) || contains_excluded_field(expr2),
+        ExprCall(ref function, ref arguments) =>
+            contains_excluded_field(function) || arguments.iter().any(contains_excluded_field),
+        ExprMethodCall(_, _, ref expressions) | ExprTup(ref expressions) | ExprVec(ref expressions) =>
+            expressions.iter().any(contains_excluded_field),
+        _ => false,
+    }
+}
+
+/// Get the name of the field if the `expression` is an `excluded::field` value.
+/// NOTE: `excluded` is a path segment so that it cannot refer to a local variable.
+fn excluded_field_name(expression: &Expression) -> Option<Identifier> {
+    if let ExprPath(None, ref path) = expression.node {
+        if !path.global && path.segments.len() == 2 && path.segments[0].identifier.to_string() == "excluded" {
+            return Some(path.segments[1].identifier.to_string());
+        }
+    }
+    None
+}
+
+/// Convert the `expression` assigned in the update() method following on_conflict() to an
+/// `AssignmentValue`.
+/// The arithmetic operations using an `excluded::field` value are converted to SQL.
+fn expression_to_conflict_value(expression: &Expression, table: &SqlTable, errors: &mut Vec<SqlError>) -> AssignmentValue {
+    if let Some(field) = excluded_field_name(expression) {
+        check_field(&field, expression.span, table, errors);
+        return AssignmentValue::Excluded(field);
+    }
+
+    if contains_excluded_field(expression) {
+        match expression.node {
+            ExprBinary(binop, ref expr1, ref expr2) => {
+                match binop_to_arithmetic_operator(binop.node) {
+                    Some(operator) => {
+                        let operand1 = expression_to_conflict_value(expr1, table, errors);
+                        let operand2 = expression_to_conflict_value(expr2, table, errors);
+                        return AssignmentValue::Operation(Box::new(operand1), operator, Box::new(operand2));
+                    },
+                    None => {
+                        errors.push(SqlError::new(
+                            "Expected arithmetic operation", // TODO: improve this message.
+                            binop.span,
+                        ));
+                    },
+                }
+            },
+            ExprParen(ref expr) => return expression_to_conflict_value(expr, table, errors),
+            _ => {
+                errors.push(SqlError::new(
+                    "cannot use an excluded::field value in this expression",
+                    expression.span,
+                ));
+                errors.push(SqlError::new_help(
+                    "only arithmetic operations can be applied to an excluded::field value",
+                    expression.span,
+                ));
+            },
+        }
+    }
+
+    AssignmentValue::Expression(expression.clone())
+}
+
+/// Check if `fields1` and `fields2` contain the same fields, regardless of their order.
+fn is_same_field_set(fields1: &[Identifier], fields2: &[Identifier]) -> bool {
+    fields1.iter().all(|field| fields2.contains(field)) && fields2.iter().all(|field| fields1.contains(field))
+}
@@ -0,0 +1,268 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+
+/// Analyzer for the create() method.
+
+use std::mem;
+
+use syntax::ast::Expr_::{ExprLit, ExprPath, ExprUnary};
+use syntax::ast::Lit_::{LitBool, LitFloatUnsuffixed, LitInt};
+use syntax::ast::LitIntType::UnsuffixedIntLit;
+use syntax::ast::Sign;
+use syntax::ast::UnOp::UnNeg;
+use syntax::codemap::{Span, Spanned};
+use syntax::parse::token::intern_and_get_ident;
+
+use ast::{CompoundForeignKey, Correlation, Expression, Filter, FilterExpression, FilterValue, Identifier, Membership, MembershipValues, MethodCall};
+use error::{ErrorType, SqlError, SqlResult, res};
+use gen::ToSql;
+use plugin::{literal_expr, negative_literal};
+use state::{SqlTable, SqlTables, get_foreign_key_fields};
+use super::{check_field, check_type, get_field_type_by_filter_value};
+use super::filter::{analyze_filter_types, expression_to_filter_expression};
+use super::subquery::check_column_type;
+use types::{Type, get_related_table_name};
+
+/// Check that the `filter` of a CHECK constraint only compares the fields with literals or other
+/// fields since a constraint cannot have parameters.
+/// The comparisons with another field are converted to `Correlation`s so that the field is
+/// written as a column name.
+fn check_constant_filter(filter: &mut FilterExpression, table: &SqlTable, position: Span, errors: &mut Vec<SqlError>) {
+    let field =
+        match *filter {
+            FilterExpression::Filter(ref mut comparison) => {
+                check_constant_filter_value(&mut comparison.operand1, position, errors);
+                let field = field_name(&comparison.operand2);
+                if field.is_none() {
+                    negate_literal(&mut comparison.operand2);
+                    match comparison.operand2.node {
+                        ExprLit(_) => (),
+                        _ => errors.push(SqlError::new(
+                            "expected a literal or a field in the #[check] attribute",
+                            comparison.operand2.span,
+                        )),
+                    }
+                }
+                field
+            },
+            FilterExpression::Filters(ref mut filters) => {
+                check_constant_filter(&mut filters.operand1, table, position, errors);
+                check_constant_filter(&mut filters.operand2, table, position, errors);
+                None
+            },
+            FilterExpression::NegFilter(ref mut filter) | FilterExpression::ParenFilter(ref mut filter) => {
+                check_constant_filter(filter, table, position, errors);
+                None
+            },
+            FilterExpression::NoFilters => None,
+            FilterExpression::FilterValue(ref mut filter_value) => {
+                check_constant_filter_value(&mut filter_value.node, position, errors);
+                None
+            },
+        };
+
+    if let Some(field) = field {
+        check_field(&field, position, table, errors);
+        if let FilterExpression::Filter(Filter { operand1, operator, operand2 }) = mem::replace(filter, FilterExpression::NoFilters) {
+            if let Some(typ) = table.fields.get(&field) {
+                let field_type = get_field_type_by_filter_value(&table.name, &operand1);
+                check_column_type(&field_type, &typ.node, operand2.span, errors);
+            }
+            *filter = FilterExpression::FilterValue(Spanned {
+                node: FilterValue::Correlation(Correlation {
+                    operand: box operand1,
+                    operator: operator,
+                    outer_field: field,
+                }),
+                span: operand2.span,
+            });
+        }
+    }
+}
+
+/// Check that the `filter_value` of a CHECK constraint only uses literals.
+fn check_constant_filter_value(filter_value: &mut FilterValue, position: Span, errors: &mut Vec<SqlError>) {
+    match *filter_value {
+        FilterValue::Correlation(_) | FilterValue::Identifier(_) => (),
+        FilterValue::Exists(_) | FilterValue::In(Membership { values: MembershipValues::Query(_), .. }) => {
+            errors.push(SqlError::new(
+                "cannot use a subquery in a check constraint",
+                position,
+            ));
+        },
+        FilterValue::In(Membership { ref mut values, .. }) => {
+            match *values {
+                MembershipValues::Array(ref mut expression) => check_literal(expression, errors),
+                MembershipValues::InclusiveRange(ref mut start, ref mut end) | MembershipValues::Range(ref mut start, ref mut end) => {
+                    check_literal(start, errors);
+                    check_literal(end, errors);
+                },
+                MembershipValues::List(ref mut expressions) => {
+                    for expression in expressions {
+                        check_literal(expression, errors);
+                    }
+                },
+                MembershipValues::Query(_) => (), // NOTE: An error is emitted above.
+            }
+        },
+        FilterValue::MethodCall(MethodCall { ref mut arguments, .. }) => {
+            for argument in arguments {
+                check_literal(argument, errors);
+            }
+        },
+    }
+}
+
+/// Check that the `value` of a `#[default]` attribute can be stored in a field of type
+/// `field_type` when it is a literal (a boolean or a number).
+/// The other values are SQL expressions (for instance, `now()`) and are not type-checked.
+pub fn check_default_type(value: &str, field_type: &Type, position: Span, errors: &mut Vec<SqlError>) {
+    if let Some(literal) = default_literal(value, position) {
+        let mut type_errors = vec![];
+        check_type(field_type, &literal, &mut type_errors);
+        remove_notes(&mut type_errors);
+        errors.append(&mut type_errors);
+    }
+}
+
+/// Check that the `expression` is a literal.
+fn check_literal(expression: &mut Expression, errors: &mut Vec<SqlError>) {
+    negate_literal(expression);
+    if let ExprLit(_) = expression.node {
+        return;
+    }
+    errors.push(SqlError::new(
+        "expected a literal in the #[check] attribute",
+        expression.span,
+    ));
+}
+
+/// Convert the `expression` of a `#[check]` attribute to the SQL expression of a CHECK constraint.
+/// The expression is analyzed like the argument of the filter() method.
+/// Since the expression is parsed from a string, the errors point to the attribute at `position`.
+pub fn check_to_sql(expression: &Expression, position: Span, table: &SqlTable) -> SqlResult<String> {
+    let mut errors = vec![];
+    let mut sql = String::new();
+
+    match expression_to_filter_expression(expression, table) {
+        Ok(mut filter) => {
+            // NOTE: The negative literals are converted before the type analysis so that they
+            // are type-checked.
+            check_constant_filter(&mut filter, table, position, &mut errors);
+            analyze_filter_types(&filter, &table.name, &mut errors);
+            sql = filter.to_sql();
+        },
+        Err(mut filter_errors) => errors.append(&mut filter_errors),
+    }
+
+    remove_notes(&mut errors);
+    for error in &mut errors {
+        error.position = position;
+    }
+
+    res(sql, errors)
+}
+
+/// Convert the `value` of a `#[default]` attribute to a literal if it is a boolean or a number.
+fn default_literal(value: &str, position: Span) -> Option<Expression> {
+    let number = value.trim_left_matches('-');
+    let literal =
+        match &*value.to_lowercase() {
+            "false" => LitBool(false),
+            "true" => LitBool(true),
+            _ =>
+                if let Ok(integer) = number.parse() {
+                    LitInt(integer, UnsuffixedIntLit(Sign::Plus))
+                }
+                else if number.chars().all(|character| character.is_digit(10) || character == '.') && number.parse::<f64>().is_ok() {
+                    LitFloatUnsuffixed(intern_and_get_ident(number))
+                }
+                else {
+                    return None;
+                },
+        };
+    if number.len() < value.len() {
+        negative_literal(&literal, position)
+    }
+    else {
+        Some(literal_expr(literal, position))
+    }
+}
+
+/// Get the name of the field if the `expression` is an identifier.
+fn field_name(expression: &Expression) -> Option<Identifier> {
+    if let ExprPath(None, ref path) = expression.node {
+        if path.segments.len() == 1 {
+            return Some(path.segments[0].identifier.to_string());
+        }
+    }
+    None
+}
+
+/// Get the foreign keys of the `table` referencing a compound primary key.
+pub fn get_compound_foreign_keys(table: &SqlTable, sql_tables: &SqlTables) -> Vec<CompoundForeignKey> {
+    table.fields.iter()
+        .filter_map(|(field, typ)| {
+            get_related_table_name(&typ.node)
+                .and_then(|related_table_name| sql_tables.get(related_table_name))
+                .and_then(|related_table| {
+                    let foreign_key_fields = get_foreign_key_fields(table, field, related_table);
+                    // NOTE: The missing #[foreign_key] attribute is reported by the lint.
+                    if related_table.primary_key.len() <= 1 || foreign_key_fields.is_empty() {
+                        None
+                    }
+                    else {
+                        let (fields, related_fields) = foreign_key_fields.into_iter().unzip();
+                        Some(CompoundForeignKey {
+                            fields: fields,
+                            related_fields: related_fields,
+                            related_table: related_table.name.clone(),
+                        })
+                    }
+                })
+        })
+        .collect()
+}
+
+/// Convert the `expression` to a negative literal if it is a negated number literal.
+fn negate_literal(expression: &mut Expression) {
+    let literal =
+        match expression.node {
+            ExprUnary(UnNeg, ref operand) =>
+                match operand.node {
+                    ExprLit(ref literal) => negative_literal(&literal.node, expression.span),
+                    _ => None,
+                },
+            _ => None,
+        };
+    if let Some(literal) = literal {
+        *expression = literal;
+    }
+}
+
+/// Remove the notes from the `errors`.
+/// Since the attributes are not in an expansion of sql!, the notes of the mismatched types are
+/// irrelevant.
+fn remove_notes(errors: &mut Vec<SqlError>) {
+    errors.retain(|error|
+        match error.kind {
+            ErrorType::Note => false,
+            _ => true,
+        }
+    );
+}
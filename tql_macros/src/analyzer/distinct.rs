@@ -0,0 +1,58 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+
+/// Analyzer for the distinct_on() method.
+
+use syntax::codemap::Span;
+
+use ast::{Identifier, Order};
+use error::SqlError;
+
+/// Check that the leading `order` fields match the distinct_on() `fields`.
+/// PostgreSQL requires that all the DISTINCT ON fields come before the other ORDER BY fields.
+pub fn check_distinct_on_order(fields: &[Identifier], order: &[Order], position: Span, errors: &mut Vec<SqlError>) {
+    let leading_count = order.iter()
+        .take_while(|order| fields.contains(order_field(order)))
+        .count();
+    if leading_count < order.len() {
+        let leading_fields: Vec<_> = order[..leading_count].iter()
+            .map(order_field)
+            .collect();
+        let missing_fields: Vec<_> = fields.iter()
+            .filter(|field| !leading_fields.contains(field))
+            .map(|field| format!("`{}`", field))
+            .collect();
+        if !missing_fields.is_empty() {
+            errors.push(SqlError::new(
+                "the leading fields of the sort() method must match the fields of the distinct_on() method",
+                position,
+            ));
+            errors.push(SqlError::new_help(
+                &format!("sort by {} first", missing_fields.join(", ")),
+                position,
+            ));
+        }
+    }
+}
+
+/// Get the field of an `order`.
+fn order_field(order: &Order) -> &Identifier {
+    match *order {
+        Order::Ascending(ref field) | Order::Descending(ref field) => field,
+    }
+}
@@ -18,15 +18,17 @@
 /// Analyzer for the filter() method.
 
 use syntax::ast::{BinOp_, Expr, Path, SpannedIdent};
-use syntax::ast::Expr_::{ExprBinary, ExprMethodCall, ExprParen, ExprPath, ExprUnary};
+use syntax::ast::Expr_::{ExprBinary, ExprCall, ExprIndex, ExprLit, ExprMethodCall, ExprParen, ExprPath, ExprRange, ExprUnary, ExprVec};
 use syntax::ast::UnOp;
 use syntax::codemap::{Span, Spanned};
 use syntax::ptr::P;
 
-use ast::{self, Expression, Filter, FilterExpression, Filters, FilterValue, LogicalOperator, RelationalOperator};
+use ast::{self, Expression, Filter, FilterExpression, Filters, FilterValue, LogicalOperator, Membership, MembershipValues, RelationalOperator};
 use error::{SqlError, SqlResult, res};
-use state::{SqlMethod, SqlMethodTypes, SqlTable, methods_singleton};
-use super::{check_field, check_field_type, check_type, check_type_filter_value, propose_similar_name};
+use plugin::field_access_by_name;
+use state::{SqlMethod, SqlMethodTypes, SqlTable, get_foreign_key_fields, methods_singleton};
+use super::{check_argument_count, check_field, check_field_type, check_type, check_type_filter_value, get_compound_key_related_table, get_field_type_by_filter_value, mismatched_types, propose_similar_name};
+use super::subquery::{argument_to_subquery, check_subquery_type, is_subquery};
 use types::Type;
 
 /// Analyze the types of the `FilterExpression`.
@@ -36,6 +38,7 @@ pub fn analyze_filter_types(filter: &FilterExpression, table_name: &str, errors:
     match *filter {
         FilterExpression::Filter(ref filter) => {
             check_field_type(table_name, &filter.operand1, &filter.operand2, errors);
+            analyze_filter_value_types(&filter.operand1, table_name, errors);
         },
         FilterExpression::Filters(ref filters) => {
             analyze_filter_types(&*filters.operand1, table_name, errors);
@@ -50,10 +53,35 @@ pub fn analyze_filter_types(filter: &FilterExpression, table_name: &str, errors:
         },
         FilterExpression::FilterValue(ref filter_value) => {
             check_type_filter_value(&Type::Bool, filter_value, table_name, errors);
+            analyze_filter_value_types(&filter_value.node, table_name, errors);
         },
     }
 }
 
+/// Analyze the types of the values in a `FilterValue::In`.
+fn analyze_filter_value_types(filter_value: &FilterValue, table_name: &str, errors: &mut Vec<SqlError>) {
+    if let FilterValue::In(Membership { ref operand, ref values }) = *filter_value {
+        let operand_type = get_field_type_by_filter_value(table_name, operand);
+        match *values {
+            // NOTE: The type of the array is checked by the lint and the type of the subquery is
+            // checked when it is analyzed.
+            MembershipValues::Array(_) | MembershipValues::Query(_) => (),
+            MembershipValues::InclusiveRange(ref start, ref end) | MembershipValues::Range(ref start, ref end) => {
+                if !is_integer_type(&operand_type) {
+                    mismatched_types("integer", &*operand_type, start.span, errors);
+                }
+                check_type(&operand_type, start, errors);
+                check_type(&operand_type, end, errors);
+            },
+            MembershipValues::List(ref expressions) => {
+                for expression in expressions {
+                    check_type(&operand_type, expression, errors);
+                }
+            },
+        }
+    }
+}
+
 /// Convert a Rust binary expression to a `FilterExpression`.
 fn binary_expression_to_filter_expression(expr1: &Expression, op: BinOp_, expr2: &Expression, table: &SqlTable) -> SqlResult<FilterExpression> {
     // TODO: accumulate the errors instead of stopping when the first one is encountered.
@@ -72,11 +100,21 @@ fn binary_expression_to_filter_expression(expr1: &Expression, op: BinOp_, expr2:
         }
         else if is_relational_operator(op) {
             if let FilterExpression::FilterValue(filter1) = filter1 {
-                FilterExpression::Filter(Filter {
-                    operand1: filter1.node,
-                    operator: binop_to_relational_operator(op),
-                    operand2: expr2.clone(),
-                })
+                let related_table =
+                    match filter1.node {
+                        FilterValue::Identifier(ref identifier) => get_compound_key_related_table(table, identifier),
+                        _ => None,
+                    };
+                match (filter1.node, related_table) {
+                    (FilterValue::Identifier(identifier), Some(related_table)) =>
+                        try!(compound_foreign_key_filter(&identifier, op, expr2, related_table, table)),
+                    (operand1, _) =>
+                        FilterExpression::Filter(Filter {
+                            operand1: operand1,
+                            operator: binop_to_relational_operator(op),
+                            operand2: expr2.clone(),
+                        }),
+                }
             }
             else {
                 dummy
@@ -143,6 +181,51 @@ fn check_method_arguments(arguments: &[Expression], argument_types: &[Type], err
     }
 }
 
+/// Convert the comparison of the foreign key `field` referencing the compound primary key of the
+/// `related_table` with the related object `value` to the comparisons of each field of the key.
+/// For instance, `parent == node` is converted to `tenant_id = node.tenant_id AND parent = node.id`.
+fn compound_foreign_key_filter(field: &str, op: BinOp_, value: &Expression, related_table: &SqlTable, table: &SqlTable) -> SqlResult<FilterExpression> {
+    let mut errors = vec![];
+    if op != BinOp_::BiEq && op != BinOp_::BiNe {
+        errors.push(SqlError::new(
+            &format!("cannot compare the foreign key `{}` which references the compound primary key of `{}` with this operator", field, related_table.name),
+            value.span,
+        ));
+        errors.push(SqlError::new_help(
+            "use == or !=",
+            value.span,
+        ));
+    }
+
+    let filters = get_foreign_key_fields(table, field, related_table).into_iter()
+        .map(|(foreign_key_field, primary_key_field)| FilterExpression::Filter(Filter {
+            operand1: FilterValue::Identifier(foreign_key_field),
+            operator: RelationalOperator::Equal,
+            operand2: field_access_by_name(value.clone(), value.span, &primary_key_field),
+        }))
+        .fold(None, |filters, filter| {
+            match filters {
+                Some(filters) => Some(FilterExpression::Filters(Filters {
+                    operand1: box filters,
+                    operator: LogicalOperator::And,
+                    operand2: box filter,
+                })),
+                None => Some(filter),
+            }
+        })
+        // NOTE: The missing #[foreign_key] attribute is reported by the lint.
+        .unwrap_or(FilterExpression::NoFilters);
+
+    let filter =
+        if op == BinOp_::BiNe {
+            FilterExpression::NegFilter(box FilterExpression::ParenFilter(box filters))
+        }
+        else {
+            filters
+        };
+    res(filter, errors)
+}
+
 /// Convert a Rust expression to a `FilterExpression`.
 pub fn expression_to_filter_expression(arg: &P<Expr>, table: &SqlTable) -> SqlResult<FilterExpression> {
     let mut errors = vec![];
@@ -152,9 +235,22 @@ pub fn expression_to_filter_expression(arg: &P<Expr>, table: &SqlTable) -> SqlRe
             ExprBinary(Spanned { node: op, .. }, ref expr1, ref expr2) => {
                 try!(binary_expression_to_filter_expression(expr1, op, expr2, table))
             },
+            ExprCall(ref function, ref arguments) if is_exists_function(function) => {
+                FilterExpression::FilterValue(Spanned {
+                    node: exists_call_to_filter_value(arguments, arg.span, table, &mut errors),
+                    span: arg.span,
+                })
+            },
             ExprMethodCall(identifier, _, ref exprs) => {
+                let node =
+                    if identifier.node.name.to_string() == "in" {
+                        in_method_call_to_filter_value(exprs, arg.span, table, &mut errors)
+                    }
+                    else {
+                        method_call_expression_to_filter_expression(identifier, &exprs, table, &mut errors)
+                    };
                 FilterExpression::FilterValue(Spanned {
-                    node: method_call_expression_to_filter_expression(identifier, &exprs, table, &mut errors),
+                    node: node,
                     span: arg.span,
                 })
             },
@@ -186,6 +282,23 @@ pub fn expression_to_filter_expression(arg: &P<Expr>, table: &SqlTable) -> SqlRe
     res(filter, errors)
 }
 
+/// Convert an `exists()` function call to a `FilterValue::Exists`.
+fn exists_call_to_filter_value(arguments: &[Expression], position: Span, table: &SqlTable, errors: &mut Vec<SqlError>) -> FilterValue {
+    // TODO: return errors instead of dummy.
+    let dummy = FilterValue::Identifier("".to_owned());
+    if !check_argument_count(arguments, 1, position, errors) {
+        return dummy;
+    }
+
+    match argument_to_subquery(&arguments[0], table) {
+        Ok(query) => FilterValue::Exists(box query),
+        Err(mut subquery_errors) => {
+            errors.append(&mut subquery_errors);
+            dummy
+        },
+    }
+}
+
 /// Get an SQL method and arguments by type and name.
 fn get_method<'a>(object_type: &'a Spanned<Type>, exprs: &[Expression], method_name: &str, identifier: SpannedIdent, errors: &mut Vec<SqlError>) -> Option<(&'a SqlMethodTypes, Vec<Expression>)> {
     let methods = methods_singleton();
@@ -217,6 +330,91 @@ fn get_method<'a>(object_type: &'a Spanned<Type>, exprs: &[Expression], method_n
     }
 }
 
+/// Convert an `in()` method call to a `FilterValue::In`.
+fn in_method_call_to_filter_value(exprs: &[Expression], position: Span, table: &SqlTable, errors: &mut Vec<SqlError>) -> FilterValue {
+    // TODO: return errors instead of dummy.
+    let dummy = FilterValue::Identifier("".to_owned());
+    let operand =
+        match exprs[0].node {
+            ExprMethodCall(identifier, _, ref exprs) => {
+                method_call_expression_to_filter_expression(identifier, exprs, table, errors)
+            },
+            ExprPath(None, ref path) => {
+                let identifier = path.segments[0].identifier.to_string();
+                check_field(&identifier, path.span, table, errors);
+                FilterValue::Identifier(identifier)
+            },
+            _ => {
+                errors.push(SqlError::new(
+                    "expected identifier", // TODO: improve this message.
+                    exprs[0].span,
+                ));
+                return dummy;
+            },
+        };
+
+    if !check_argument_count(&exprs[1..], 1, position, errors) {
+        return dummy;
+    }
+
+    let values =
+        match exprs[1].node {
+            ExprVec(ref expressions) => {
+                if expressions.is_empty() {
+                    errors.push(SqlError::new(
+                        "expected at least one value in the in() method",
+                        exprs[1].span,
+                    ));
+                }
+                MembershipValues::List(expressions.clone())
+            },
+            ExprRange(Some(ref start), Some(ref end)) => {
+                MembershipValues::Range(start.clone(), end.clone())
+            },
+            ExprRange(_, _) => {
+                errors.push(SqlError::new(
+                    "expected range with a start and an end",
+                    exprs[1].span,
+                ));
+                return dummy;
+            },
+            ExprLit(_) => {
+                errors.push(SqlError::new(
+                    "expected array, range, slice or Vec expression", // TODO: improve this message.
+                    exprs[1].span,
+                ));
+                return dummy;
+            },
+            ExprIndex(..) | ExprMethodCall(..) if is_subquery(&exprs[1]) => {
+                match argument_to_subquery(&exprs[1], table) {
+                    Ok(query) => {
+                        // NOTE: The type of an unknown operand cannot be checked.
+                        let known_operand =
+                            match operand {
+                                FilterValue::Identifier(ref identifier) => table.fields.contains_key(identifier),
+                                _ => true,
+                            };
+                        if known_operand {
+                            let operand_type = get_field_type_by_filter_value(&table.name, &operand);
+                            check_subquery_type(&operand_type, &query, exprs[1].span, errors);
+                        }
+                        MembershipValues::Query(box query)
+                    },
+                    Err(mut subquery_errors) => {
+                        errors.append(&mut subquery_errors);
+                        return dummy;
+                    },
+                }
+            },
+            _ => MembershipValues::Array(exprs[1].clone()),
+        };
+
+    FilterValue::In(Membership {
+        operand: box operand,
+        values: values,
+    })
+}
+
 /// Check if a `BinOp_` is a `LogicalOperator`.
 pub fn is_logical_operator(binop: BinOp_) -> bool {
     match binop {
@@ -225,6 +423,25 @@ pub fn is_logical_operator(binop: BinOp_) -> bool {
     }
 }
 
+/// Check if the `function` is the `exists()` function.
+fn is_exists_function(function: &Expression) -> bool {
+    if let ExprPath(None, ref path) = function.node {
+        path.segments.len() == 1 && path.segments[0].identifier.to_string() == "exists"
+    }
+    else {
+        false
+    }
+}
+
+/// Check if a `Type` is an integer type (that can be used in a range).
+fn is_integer_type(typ: &Type) -> bool {
+    match *typ {
+        Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::Serial => true,
+        Type::Nullable(ref typ) => is_integer_type(typ),
+        _ => false,
+    }
+}
+
 /// Check if a `BinOp_` is a `RelationalOperator`.
 pub fn is_relational_operator(binop: BinOp_) -> bool {
     match binop {
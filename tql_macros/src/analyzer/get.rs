@@ -18,33 +18,94 @@
 /// Analyzer for the get() method.
 
 use syntax::ast::Expr;
-use syntax::ast::Expr_::{ExprLit, ExprPath};
+use syntax::ast::Expr_::{ExprField, ExprIndex, ExprLit, ExprPath, ExprTup, ExprTupField};
 use syntax::ptr::P;
 
-use ast::{Filter, FilterExpression, FilterValue, Limit, RelationalOperator};
-use error::{SqlResult, res};
-use plugin::number_literal;
-use state::{SqlTable, get_primary_key_field};
+use ast::{Filter, Filters, FilterExpression, FilterValue, Limit, LogicalOperator, RelationalOperator, is_unique_filter};
+use error::{SqlError, SqlResult, res};
+use plugin::{number_literal, tuple_field_access};
+use state::{SqlTable, get_primary_key_fields};
 use super::no_primary_key;
 use super::filter::expression_to_filter_expression;
 
 /// Convert an expression from a `get()` method to a FilterExpression and a Limit.
+/// The primary key value is a tuple when the primary key is compound.
 pub fn get_expression_to_filter_expression(arg: &P<Expr>, table: &SqlTable) -> SqlResult<(FilterExpression, Limit)> {
-    let primary_key_field = get_primary_key_field(table);
-    match primary_key_field {
-        Some(primary_key_field) =>
-            match arg.node {
-                ExprLit(_) | ExprPath(_, _) => {
-                    let filter = FilterExpression::Filter(Filter {
-                        operand1: FilterValue::Identifier(primary_key_field),
-                        operator: RelationalOperator::Equal,
-                        operand2: arg.clone(),
-                    });
-                    res((filter, Limit::NoLimit), vec![])
-                },
-                _ => expression_to_filter_expression(arg, table)
-                        .and_then(|filter| Ok((filter, Limit::Index(number_literal(0))))),
-            },
-        None => Err(vec![no_primary_key(&table.name, table.position)]),
+    let primary_key_fields = get_primary_key_fields(table);
+    if primary_key_fields.is_empty() {
+        return Err(vec![no_primary_key(&table.name, table.position)]);
     }
+
+    match arg.node {
+        ExprLit(_) if primary_key_fields.len() > 1 => {
+            Err(vec![
+                SqlError::new(
+                    &format!("expected a tuple of {} values for the compound primary key of `{}`", primary_key_fields.len(), table.name),
+                    arg.span,
+                ),
+                SqlError::new_help(
+                    &format!("use a tuple, like get(({}))", primary_key_fields.join(", ")),
+                    arg.span,
+                ),
+            ])
+        },
+        ExprField(_, _) | ExprIndex(_, _) | ExprPath(_, _) | ExprTupField(_, _) if primary_key_fields.len() > 1 => {
+            // NOTE: The value is a tuple (its type is checked by the lint): each of its fields is
+            // compared to a field of the primary key.
+            let values: Vec<_> = (0..primary_key_fields.len())
+                .map(|index| tuple_field_access(arg.clone(), arg.span, index))
+                .collect();
+            res((primary_key_filters(primary_key_fields, &values), Limit::NoLimit), vec![])
+        },
+        ExprLit(_) | ExprPath(_, _) => {
+            let filter = primary_key_filter(primary_key_fields[0].clone(), arg);
+            res((filter, Limit::NoLimit), vec![])
+        },
+        ExprTup(ref values) => {
+            if values.len() != primary_key_fields.len() {
+                return Err(vec![SqlError::new(
+                    &format!("expected a value for each field of the primary key ({}), found {} values", primary_key_fields.join(", "), values.len()),
+                    arg.span,
+                )]);
+            }
+            res((primary_key_filters(primary_key_fields, values), Limit::NoLimit), vec![])
+        },
+        _ => expression_to_filter_expression(arg, table)
+                .and_then(|filter| {
+                    // NOTE: A filter on a unique key matches at most one row, so no LIMIT is
+                    // needed.
+                    let limit =
+                        if is_unique_filter(&filter, table) {
+                            Limit::NoLimit
+                        }
+                        else {
+                            Limit::Index(number_literal(0))
+                        };
+                    Ok((filter, limit))
+                }),
+    }
+}
+
+/// Create the filter comparing the primary key `field` to the `value`.
+fn primary_key_filter(field: String, value: &P<Expr>) -> FilterExpression {
+    FilterExpression::Filter(Filter {
+        operand1: FilterValue::Identifier(field),
+        operator: RelationalOperator::Equal,
+        operand2: value.clone(),
+    })
+}
+
+/// Create the filters comparing each field of the primary key `fields` to its value in `values`.
+fn primary_key_filters(fields: Vec<String>, values: &[P<Expr>]) -> FilterExpression {
+    let mut filters = fields.into_iter().zip(values.iter())
+        .map(|(field, value)| primary_key_filter(field, value));
+    // NOTE: There is at least one field in the primary key, hence unwrap().
+    let first_filter = filters.next().unwrap();
+    filters.fold(first_filter, |filter, next_filter| {
+        FilterExpression::Filters(Filters {
+            operand1: box filter,
+            operator: LogicalOperator::And,
+            operand2: box next_filter,
+        })
+    })
 }
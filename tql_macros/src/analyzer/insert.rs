@@ -19,15 +19,106 @@
 
 use std::collections::HashSet;
 
+use syntax::ast::Expr_::{ExprAssign, ExprTup};
 use syntax::codemap::{Span, Spanned};
 
-use ast::{Assignment, AssignementOperator};
-use error::SqlError;
-use state::{SqlTable, get_primary_key_field};
+use ast::{Assignment, AssignementOperator, AssignmentValue, Expression};
+use error::{SqlError, SqlResult, res};
+use plugin::{deref, field_access_by_name, tuple_field_access, variable};
+use state::{SqlTable, get_generated_primary_key_field};
+use super::{check_field, get_compound_key_related_table, path_expr_to_identifier};
+use super::assignment::expand_compound_foreign_keys;
 use types::Type;
 
+/// Convert the argument of the insert_many() method to the rows expression and the assignments to
+/// do for each row.
+/// The argument is either an iterator of table structs (`rows`) or an iterator of tuples with the
+/// fields to assign (`(field1, field2) = rows`).
+pub fn argument_to_rows(arg: &Expression, table: &SqlTable) -> SqlResult<(Expression, Vec<Assignment>)> {
+    let mut errors = vec![];
+    let mut assignments = vec![];
+    let row = variable("row", arg.span);
+
+    let rows =
+        if let ExprAssign(ref fields, ref rows) = arg.node {
+            let field_exprs =
+                if let ExprTup(ref exprs) = fields.node {
+                    exprs.clone()
+                }
+                else {
+                    vec![fields.clone()]
+                };
+            let is_tuple = field_exprs.len() > 1;
+            for (index, field) in field_exprs.iter().enumerate() {
+                if let Some(identifier) = path_expr_to_identifier(field, &mut errors) {
+                    check_field(&identifier, field.span, table, &mut errors);
+                    let value =
+                        if is_tuple {
+                            tuple_field_access(row.clone(), field.span, index)
+                        }
+                        else {
+                            deref(row.clone(), field.span)
+                        };
+                    assignments.push(Assignment {
+                        identifier: identifier,
+                        operator: Spanned {
+                            node: AssignementOperator::Equal,
+                            span: field.span,
+                        },
+                        value: AssignmentValue::Expression(value),
+                    });
+                }
+            }
+            assignments = expand_compound_foreign_keys(assignments, table, &mut errors);
+            check_insert_arguments(&assignments, arg.span, table, &mut errors);
+            rows.clone()
+        }
+        else {
+            let primary_key = get_generated_primary_key_field(table);
+            for (field, typ) in &table.fields {
+                match typ.node {
+                    Type::UnsupportedType(_) => (),
+                    _ if Some(field) == primary_key.as_ref() => (),
+                    // NOTE: The related object of a ForeignKey field is optional in a struct, so
+                    // it cannot be split into the fields of a compound primary key.
+                    _ if get_compound_key_related_table(table, field).is_some() => {
+                        errors.push(SqlError::new(
+                            &format!("cannot insert the rows of `{}` since its foreign key `{}` references a compound primary key", table.name, field),
+                            arg.span,
+                        ));
+                        errors.push(SqlError::new_help(
+                            &format!("specify the fields to insert, like insert_many((field1, {}) = rows)", field),
+                            arg.span,
+                        ));
+                    },
+                    _ => {
+                        assignments.push(Assignment {
+                            identifier: field.clone(),
+                            operator: Spanned {
+                                node: AssignementOperator::Equal,
+                                span: arg.span,
+                            },
+                            value: AssignmentValue::Expression(field_access_by_name(row.clone(), arg.span, field)),
+                        });
+                    },
+                }
+            }
+            arg.clone()
+        };
+
+    if assignments.is_empty() && errors.is_empty() {
+        errors.push(SqlError::new(
+            &format!("no field to insert in table `{}`", table.name),
+            arg.span,
+        ));
+    }
+
+    res((rows, assignments), errors)
+}
+
 /// Check that the method call contains all the fields from the `table` and that all assignments
 /// does not use an operation (e.g. +=).
+/// A field with a default value can be omitted since the database fills it.
 pub fn check_insert_arguments(assignments: &[Assignment], position: Span, table: &SqlTable, errors: &mut Vec<SqlError>) {
     let mut fields = HashSet::new();
     let mut missing_fields: Vec<&str> = vec![];
@@ -40,15 +131,15 @@ pub fn check_insert_arguments(assignments: &[Assignment], position: Span, table:
             errors.push(SqlError::new(&format!("expected = but got {}", *operator), assignment.operator.span));
         }
     }
-    let primary_key = get_primary_key_field(&table);
+    let primary_key = get_generated_primary_key_field(&table);
 
     for field in table.fields.keys() {
-        if !fields.contains(field) && Some(field) != primary_key.as_ref() {
-            if let Some(&Spanned { node: Type::Nullable(_), .. }) = table.fields.get(field) {
-                // Do not err about missing nullable field.
-            }
-            else {
-                missing_fields.push(&field);
+        if !fields.contains(field) && Some(field) != primary_key.as_ref() && !table.defaults.contains_key(field) {
+            match table.fields.get(field) {
+                // Do not err about missing nullable field or many-to-many field (which is not a
+                // column).
+                Some(&Spanned { node: Type::Nullable(_), .. }) | Some(&Spanned { node: Type::ManyToMany(_), .. }) => (),
+                _ => missing_fields.push(&field),
             }
         }
     }
@@ -17,46 +17,197 @@
 
 /// Analyzer for the join() method.
 
-use syntax::codemap::Spanned;
+use syntax::ast::Expr_::ExprField;
+use syntax::codemap::{Span, Spanned};
 
-use ast::{Expression, Join};
-use error::{SqlResult, res};
-use state::{SqlTable, get_primary_key_field, tables_singleton};
+use ast::{Expression, Identifier, Join, JoinType};
+use error::{SqlError, SqlResult, res};
+use state::{SqlTable, get_foreign_key_fields, tables_singleton};
 use super::{check_field, mismatched_types, no_primary_key, path_expr_to_identifier};
-use types::Type;
+use types::{Type, get_related_table_name};
 
-/// Convert an `Expression` to a `Join`
-pub fn argument_to_join(arg: &Expression, table: &SqlTable) -> SqlResult<Join> {
-    let mut errors = vec![];
-    let mut join = Join::default();
-
-    if let Some(identifier) = path_expr_to_identifier(arg, &mut errors) {
-        check_field(&identifier, arg.span, table, &mut errors);
-        match table.fields.get(&identifier) {
-            Some(&Spanned { node: ref field_type, .. }) => {
-                if let Type::Custom(ref related_table_name) = *field_type {
-                    let sql_tables = tables_singleton();
-                    if let Some(related_table) = sql_tables.get(related_table_name) {
-                        match get_primary_key_field(related_table) {
-                            Some(primary_key_field) =>
-                                join = Join {
-                                    base_field: identifier,
-                                    base_table: table.name.clone(),
-                                    joined_field: primary_key_field,
-                                    joined_table: related_table_name.clone(),
-                                },
-                            None => errors.push(no_primary_key(related_table_name, related_table.position)),
-                        }
-                    }
-                    // NOTE: if the field type is not an SQL table, an error is thrown by the
-                    // linter.
+/// Give an alias to the joined tables that are already in the query (because a table is joined
+/// twice or a table is joined with itself).
+/// Also set the base table of the nested joins to the alias of their parent join.
+pub fn add_join_aliases(joins: &mut [Join], table: &SqlTable) {
+    let mut table_names = vec![table.name.clone()];
+    for index in 0..joins.len() {
+        // NOTE: A join without path (to the related rows of an annotation) already has its alias.
+        if joins[index].path.is_empty() {
+            table_names.push(joins[index].alias.clone());
+            continue;
+        }
+        let base_table = {
+            let path = &joins[index].path;
+            let parent_path = &path[..path.len() - 1];
+            joins[..index].iter()
+                .find(|join| join.path[..] == *parent_path)
+                .map_or(table.name.clone(), |join| join.alias.clone())
+        };
+        let join = &mut joins[index];
+        join.base_table = base_table;
+        join.alias =
+            if table_names.contains(&join.joined_table) {
+                new_alias(join.base_table.clone() + "_" + &join.base_field, &table_names)
+            }
+            else {
+                join.joined_table.clone()
+            };
+        table_names.push(join.alias.clone());
+    }
+}
+
+/// Add the `new_joins` to the `joins` unless they are already there.
+/// The type of the last join of a path is overridden when a `join_type` is specified.
+/// An INNER JOIN type overrides every join of the path since the rows without the last related
+/// row are dropped anyway.
+pub fn add_joins(joins: &mut Vec<Join>, new_joins: Vec<Join>, join_type: Option<JoinType>) {
+    let count = new_joins.len();
+    for (index, mut new_join) in new_joins.into_iter().enumerate() {
+        let join_type =
+            match join_type {
+                Some(JoinType::Inner) => join_type.clone(),
+                _ if index + 1 == count => join_type.clone(),
+                _ => None,
+            };
+        match joins.iter().position(|join| join.path == new_join.path) {
+            Some(position) => {
+                if let Some(join_type) = join_type {
+                    joins[position].typ = join_type;
                 }
-                else {
-                    mismatched_types("ForeignKey<_>", field_type, arg.span, &mut errors);
+            },
+            None => {
+                if let Some(join_type) = join_type {
+                    new_join.typ = join_type;
                 }
+                joins.push(new_join);
             },
-            None => (), // NOTE: This case is handled by the check_field() call above.
         }
     }
-    res(join, errors)
+    left_join_nested_joins(joins);
+}
+
+/// Convert an `Expression` to the `Join`s needed to fetch the related object.
+/// A field path like `post.author` gives a `Join` for each foreign key.
+/// The join is a LEFT JOIN when the foreign key is nullable so that the rows without a related
+/// row are not dropped. The following joins of the path are then also LEFT JOINs.
+pub fn argument_to_join(arg: &Expression, table: &SqlTable) -> SqlResult<Vec<Join>> {
+    let mut errors = vec![];
+    let mut joins = vec![];
+
+    if let Some(fields) = expression_to_field_path(arg, &mut errors) {
+        let sql_tables = tables_singleton();
+        let mut current_table = table;
+        let mut path = vec![];
+        let mut left_joined = false;
+        for (identifier, position) in fields {
+            check_field(&identifier, position, current_table, &mut errors);
+            let related_table =
+                match current_table.fields.get(&identifier) {
+                    Some(&Spanned { node: ref field_type, .. }) => {
+                        if let Some(related_table_name) = get_related_table_name(field_type) {
+                            if let Type::Nullable(_) = *field_type {
+                                left_joined = true;
+                            }
+                            let join_type =
+                                if left_joined {
+                                    JoinType::Left
+                                }
+                                else {
+                                    JoinType::Inner
+                                };
+                            path.push(identifier.clone());
+                            match sql_tables.get(related_table_name) {
+                                Some(related_table) => {
+                                    let mut key_fields = get_foreign_key_fields(current_table, &identifier, related_table);
+                                    // NOTE: The foreign key field references the last field of the
+                                    // primary key.
+                                    match key_fields.pop() {
+                                        Some((_, primary_key_field)) =>
+                                            joins.push(Join {
+                                                alias: related_table_name.clone(),
+                                                base_field: identifier,
+                                                base_table: current_table.name.clone(),
+                                                joined_field: primary_key_field,
+                                                joined_table: related_table_name.clone(),
+                                                key_fields: key_fields,
+                                                path: path.clone(),
+                                                typ: join_type,
+                                            }),
+                                        // NOTE: The missing #[foreign_key] attribute of a foreign key
+                                        // referencing a compound primary key is reported by the lint.
+                                        None if related_table.primary_key.len() > 1 => (),
+                                        None => errors.push(no_primary_key(related_table_name, related_table.position)),
+                                    }
+                                    Some(related_table)
+                                },
+                                // NOTE: if the field type is not an SQL table, an error is thrown
+                                // by the linter.
+                                None => None,
+                            }
+                        }
+                        else {
+                            mismatched_types("ForeignKey<_>", field_type, position, &mut errors);
+                            None
+                        }
+                    },
+                    None => None, // NOTE: This case is handled by the check_field() call above.
+                };
+            match related_table {
+                Some(related_table) => current_table = related_table,
+                None => break,
+            }
+        }
+    }
+    res(joins, errors)
+}
+
+/// Convert a field path `Expression` (like `post.author`) to the list of fields.
+fn expression_to_field_path(expression: &Expression, errors: &mut Vec<SqlError>) -> Option<Vec<(Identifier, Span)>> {
+    if let ExprField(ref expr, ref field) = expression.node {
+        expression_to_field_path(expr, errors).map(|mut fields| {
+            fields.push((field.node.to_string(), field.span));
+            fields
+        })
+    }
+    else {
+        path_expr_to_identifier(expression, errors).map(|identifier| vec![(identifier, expression.span)])
+    }
+}
+
+/// Check if the `join` is a LEFT JOIN.
+fn is_left_join(join: &Join) -> bool {
+    match join.typ {
+        JoinType::Left => true,
+        JoinType::Inner => false,
+    }
+}
+
+/// Change the type of the joins nested in a LEFT JOIN to LEFT JOIN.
+/// Otherwise, the rows without the related row of the LEFT JOIN would be dropped.
+fn left_join_nested_joins(joins: &mut [Join]) {
+    // NOTE: A join always comes after the join of its parent path.
+    for index in 0..joins.len() {
+        let parent_left_joined = {
+            let path = &joins[index].path;
+            !path.is_empty() && joins[..index].iter()
+                .any(|join| join.path[..] == path[..path.len() - 1] && is_left_join(join))
+        };
+        if parent_left_joined {
+            joins[index].typ = JoinType::Left;
+        }
+    }
+}
+
+/// Create an alias from the `name` which is neither a table name nor one of the `table_names`
+/// already in the query: a number is appended to the `name` if needed.
+fn new_alias(name: String, table_names: &[String]) -> String {
+    let sql_tables = tables_singleton();
+    let mut alias = name.clone();
+    let mut index = 2;
+    while table_names.contains(&alias) || sql_tables.contains_key(&alias) {
+        alias = format!("{}_{}", name, index);
+        index += 1;
+    }
+    alias
 }
@@ -0,0 +1,87 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+/// Analyzer for the many-to-many relations.
+
+use syntax::ast::Expr_::ExprPath;
+
+use ast::{Expression, JunctionTable, ManyToManyJoin};
+use error::{SqlResult, res};
+use state::{SqlTable, SqlTables, get_primary_key_field, tables_singleton};
+use super::{get_query_fields, no_primary_key, path_expr_to_identifier};
+use types::Type;
+
+/// Convert an `Expression` to a `ManyToManyJoin`.
+pub fn argument_to_many_to_many_join(arg: &Expression, table: &SqlTable) -> SqlResult<ManyToManyJoin> {
+    let mut errors = vec![];
+    let mut join = ManyToManyJoin::default();
+
+    if let Some(field) = path_expr_to_identifier(arg, &mut errors) {
+        if let Some(&Type::ManyToMany(ref related_table_name)) = table.fields.get(&field).map(|typ| &typ.node) {
+            let sql_tables = tables_singleton();
+            if get_primary_key_field(table).is_none() {
+                errors.push(no_primary_key(&table.name, table.position));
+            }
+            if let Some(related_table) = sql_tables.get(related_table_name) {
+                match get_primary_key_field(related_table) {
+                    Some(_) => {
+                        join = ManyToManyJoin {
+                            fields: get_query_fields(related_table, &[], &[], sql_tables),
+                            junction_table: JunctionTable {
+                                base_table: table.name.clone(),
+                                field: field,
+                                related_table: related_table_name.clone(),
+                            },
+                        };
+                    },
+                    None => errors.push(no_primary_key(related_table_name, related_table.position)),
+                }
+            }
+            // NOTE: if the field type is not an SQL table, an error is thrown by the linter.
+        }
+    }
+
+    res(join, errors)
+}
+
+/// Get the junction tables of the `ManyToMany` fields of the `table`.
+pub fn get_junction_tables(table: &SqlTable, sql_tables: &SqlTables) -> Vec<JunctionTable> {
+    table.fields.iter()
+        .filter_map(|(field, typ)| {
+            match typ.node {
+                Type::ManyToMany(ref related_table_name) if sql_tables.contains_key(related_table_name) =>
+                    Some(JunctionTable {
+                        base_table: table.name.clone(),
+                        field: field.clone(),
+                        related_table: related_table_name.clone(),
+                    }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Check if the `expression` is a `ManyToMany` field of the `table`.
+pub fn is_many_to_many_field(expression: &Expression, table: &SqlTable) -> bool {
+    if let ExprPath(_, ref path) = expression.node {
+        let field = path.segments[0].identifier.to_string();
+        if let Some(&Type::ManyToMany(_)) = table.fields.get(&field).map(|typ| &typ.node) {
+            return true;
+        }
+    }
+    false
+}
@@ -31,38 +31,62 @@ use syntax::codemap::{Span, Spanned};
 use syntax::ptr::P;
 
 mod aggregate;
+mod annotate;
 mod assignment;
+mod conflict;
+mod create;
+mod distinct;
 mod filter;
 mod get;
 mod insert;
 mod join;
 mod limit;
+mod many_to_many;
+mod prefetch;
+mod projection;
+mod returning;
 mod sort;
+mod subquery;
+mod window;
 
-use ast::{self, Aggregate, AggregateFilterExpression, Assignment, Expression, FieldList, FilterExpression, FilterValue, Groups, Identifier, Join, Limit, Order, Query, TypedField};
+use ast::{self, Aggregate, AggregateFilterExpression, Assignment, CompoundForeignKey, ConflictAction, Distinct, Expression, FieldList, FilterExpression, FilterValue, Groups, Identifier, Join, JoinType, JunctionTable, Limit, ManyToManyJoin, OnConflict, Order, Prefetch, Query, Returning, TableIndex, TypedField, Window};
 use error::{SqlError, SqlResult, res};
 use gen::ToSql;
 use parser::{MethodCall, MethodCalls};
 use plugin::number_literal;
 use self::aggregate::{argument_to_aggregate, argument_to_group, expression_to_aggregate_filter_expression};
-use self::assignment::{analyze_assignments_types, argument_to_assignment};
+use self::annotate::{Annotation, argument_to_annotation};
+use self::assignment::{analyze_assignments_types, argument_to_assignment, expand_compound_foreign_keys};
+use self::conflict::{argument_to_conflict_assignment, argument_to_conflict_target, check_conflict_target};
+use self::create::get_compound_foreign_keys;
+pub use self::create::{check_default_type, check_to_sql};
+use self::distinct::check_distinct_on_order;
 use self::filter::{analyze_filter_types, expression_to_filter_expression};
 use self::get::get_expression_to_filter_expression;
-use self::insert::check_insert_arguments;
-use self::join::argument_to_join;
+use self::insert::{argument_to_rows, check_insert_arguments};
+use self::join::{add_join_aliases, add_joins, argument_to_join};
 use self::limit::{analyze_limit_types, argument_to_limit};
+use self::many_to_many::{argument_to_many_to_many_join, get_junction_tables, is_many_to_many_field};
+use self::prefetch::argument_to_prefetch;
+use self::projection::{argument_to_deferred_field, argument_to_projection_field, check_deferred_fields, only_to_deferred_fields};
+use self::returning::argument_to_returning_field;
 use self::sort::argument_to_order;
-use state::{SqlTable, SqlTables, get_field_type, methods_singleton, tables_singleton};
+use state::{SqlTable, SqlTables, get_field_type, get_primary_key_fields, methods_singleton, tables_singleton};
 use string::{find_near, plural_verb};
-use types::Type;
+use types::{Type, get_related_table_name, primary_key_type_to_sql};
 
 /// The type of the SQL query.
 enum SqlQueryType {
     Aggregate,
+    Annotate,
+    Count,
+    CreateJunctionTables,
     CreateTable,
     Delete,
     Drop,
+    Exists,
     Insert,
+    InsertMany,
     Select,
     Update,
 
@@ -83,18 +107,40 @@ struct QueryData {
     aggregate_filter: AggregateFilterExpression,
     aggregates: Vec<Aggregate>,
     groups: Groups,
-    // Aggregate, Delete, Select, Update
+    // Aggregate, Count, Delete, Exists, Select, Update
     filter: FilterExpression,
-    // Aggregate / Select
+    // Aggregate / Annotate / Select
+    distinct: Distinct,
+    // Aggregate / Annotate / Count / Exists / Select
     joins: Vec<Join>,
+    // Annotate
+    windows: Vec<Window>,
     // Create
+    checks: Vec<String>,
     fields_to_create: Vec<TypedField>,
+    foreign_keys: Vec<CompoundForeignKey>,
+    indexes: Vec<TableIndex>,
+    primary_key: FieldList,
+    unique_keys: Vec<FieldList>,
+    // Create / Drop
+    junction_tables: Vec<JunctionTable>,
+    // Delete / Update
+    // NOTE: An empty list means that all the fields are returned.
+    returning: Option<FieldList>,
     // Insert / Update
     assignments: Vec<Assignment>,
+    // Insert
+    conflict_action: Option<ConflictAction>,
+    conflict_target: Option<Vec<Identifier>>,
+    // InsertMany
+    rows: Option<Expression>,
     // Select
+    deferred_fields: FieldList,
     fields: FieldList,
     limit: Limit,
+    many_to_many: Vec<ManyToManyJoin>,
     order: Vec<Order>,
+    prefetch: Option<Prefetch>,
     // All
     query_type: SqlQueryType,
 }
@@ -121,7 +167,8 @@ pub fn analyze(method_calls: MethodCalls, sql_tables: &SqlTables) -> SqlResult<Q
         match table {
             Some(table) => {
                 let mut query_data = try!(process_methods(&calls, table, &mut delete_position));
-                let fields = get_query_fields(table, &query_data.joins, sql_tables);
+                add_join_aliases(&mut query_data.joins, table);
+                let fields = get_query_fields(table, &query_data.joins, &query_data.deferred_fields, sql_tables);
                 query_data.fields = fields;
                 query_data
 
@@ -143,19 +190,31 @@ pub fn analyze_types(query: Query) -> SqlResult<Query> {
         Query::Aggregate { ref filter, ref table, .. } => {
             analyze_filter_types(filter, &table, &mut errors);
         },
-        Query::CreateTable { .. } => (), // Nothing to analyze.
-        Query::Delete { ref filter, ref table } => {
+        Query::Count { ref filter, ref table, .. } => {
+            analyze_filter_types(filter, &table, &mut errors);
+        },
+        Query::CreateJunctionTables { .. } | Query::CreateTable { .. } => (), // Nothing to analyze.
+        Query::Delete { ref filter, ref table, .. } => {
             analyze_filter_types(filter, &table, &mut errors);
         },
         Query::Drop { .. } => (), // Nothing to analyze.
-        Query::Insert { ref assignments, ref table } => {
+        Query::Exists { ref filter, ref table, .. } => {
+            analyze_filter_types(filter, &table, &mut errors);
+        },
+        Query::Insert { ref assignments, ref on_conflict, ref table } => {
+            analyze_assignments_types(assignments, &table, &mut errors);
+            if let Some(OnConflict { action: ConflictAction::Update(ref assignments), .. }) = *on_conflict {
+                analyze_assignments_types(assignments, &table, &mut errors);
+            }
+        },
+        Query::InsertMany { ref assignments, ref table, .. } => {
             analyze_assignments_types(assignments, &table, &mut errors);
         },
         Query::Select { ref filter, ref limit, ref table, .. } => {
             analyze_filter_types(filter, &table, &mut errors);
             analyze_limit_types(limit, &mut errors);
         },
-        Query::Update { ref assignments, ref filter, ref table } => {
+        Query::Update { ref assignments, ref filter, ref table, .. } => {
             analyze_filter_types(filter, &table, &mut errors);
             analyze_assignments_types(assignments, &table, &mut errors);
         },
@@ -180,6 +239,20 @@ fn check_argument_count(arguments: &[Expression], expected_count: usize, positio
     }
 }
 
+/// Check that the method `calls` selecting the distinct values (with values() and distinct()) only
+/// contain methods that apply to these values.
+fn check_distinct_values_methods(calls: &[MethodCall], errors: &mut Vec<SqlError>) {
+    let invalid_methods = ["defer", "distinct_on", "get", "limit", "only", "prefetch", "sort"];
+    for call in calls.iter().filter(|call| invalid_methods.contains(&&*call.name)) {
+        errors.push(SqlError::new(
+            &format!("cannot call the {method}() method with the values() and distinct() methods",
+                method = call.name
+            ),
+            call.position,
+        ));
+    }
+}
+
 /// Check that `Delete` `Query` contains a filter.
 fn check_delete_without_filters(query: &Query, delete_position: Option<Span>, errors: &mut Vec<SqlError>) {
     if let Query::Delete { ref filter, .. } = *query {
@@ -210,7 +283,7 @@ pub fn check_field(identifier: &str, position: Span, table: &SqlTable, errors: &
 /// Check if the type of `identifier` matches the type of the `value` expression.
 fn check_field_type(table_name: &str, filter_value: &FilterValue, value: &Expression, errors: &mut Vec<SqlError>) {
     let field_type = get_field_type_by_filter_value(table_name, filter_value);
-    check_type(field_type, value, errors);
+    check_type(&field_type, value, errors);
 }
 
 /// Check if the method calls sequence is valid.
@@ -218,13 +291,18 @@ fn check_field_type(table_name: &str, filter_value: &FilterValue, value: &Expres
 fn check_method_calls_validity(method_calls: &MethodCalls, errors: &mut Vec<SqlError>) {
     let method_map =
         hashmap!{
-            "aggregate" => vec!["filter", "join", "values"],
-            "all" => vec!["filter", "get", "join", "limit", "sort"],
+            "aggregate" => vec!["filter", "inner_join", "join", "left_join", "values"],
+            "all" => vec!["defer", "distinct", "distinct_on", "filter", "get", "inner_join", "join", "left_join", "limit", "only", "prefetch", "sort", "values"],
+            "annotate" => vec!["filter"],
+            "count" => vec!["filter", "inner_join", "join", "left_join"],
             "create" => vec![],
-            "delete" => vec!["filter", "get"],
+            "create_junction_tables" => vec![],
+            "delete" => vec!["filter", "get", "returning"],
             "drop" => vec![],
-            "insert" => vec![],
-            "update" => vec!["filter", "get"],
+            "exists" => vec!["filter", "inner_join", "join", "left_join"],
+            "insert" => vec!["ignore", "on_conflict", "update"],
+            "insert_many" => vec![],
+            "update" => vec!["filter", "get", "returning"],
         };
 
     let main_method = method_calls.calls.iter()
@@ -297,6 +375,16 @@ fn check_no_arguments(method_call: &MethodCall, errors: &mut Vec<SqlError>) {
     }
 }
 
+/// Check that the on_conflict() method was called before the `method_call`.
+fn check_on_conflict_called(query_data: &QueryData, method_call: &MethodCall, errors: &mut Vec<SqlError>) {
+    if query_data.conflict_target.is_none() {
+        errors.push(SqlError::new(
+            &format!("the {}() method must be preceded by on_conflict() when called with insert()", method_call.name),
+            method_call.position,
+        ));
+    }
+}
+
 /// Check if the `field_type` is compatible with the `expression`'s type.
 pub fn check_type(field_type: &Type, expression: &Expression, errors: &mut Vec<SqlError>) {
     if field_type != expression {
@@ -309,7 +397,7 @@ pub fn check_type(field_type: &Type, expression: &Expression, errors: &mut Vec<S
 fn check_type_filter_value(expected_type: &Type, filter_value: &Spanned<FilterValue>, table_name: &str, errors: &mut Vec<SqlError>) {
     let field_type = get_field_type_by_filter_value(table_name, &filter_value.node);
     if *field_type != *expected_type {
-        mismatched_types(expected_type, &field_type, filter_value.span, errors);
+        mismatched_types(expected_type, &*field_type, filter_value.span, errors);
     }
 }
 
@@ -329,12 +417,30 @@ fn convert_arguments<F, Type>(arguments: &[P<Expr>], table: &SqlTable, convert_a
     res(items, errors)
 }
 
+/// Get the related table of the foreign key `field` of the `table` if it references a compound
+/// primary key.
+fn get_compound_key_related_table(table: &SqlTable, field: &str) -> Option<&'static SqlTable> {
+    let sql_tables: &'static SqlTables = tables_singleton();
+    table.fields.get(field)
+        .and_then(|typ| get_related_table_name(&typ.node))
+        .and_then(|related_table_name| sql_tables.get(related_table_name))
+        .and_then(|related_table| {
+            if related_table.primary_key.len() > 1 {
+                Some(related_table)
+            }
+            else {
+                None
+            }
+        })
+}
+
 /// Get the type of the field if it exists from an `FilterValue`.
-fn get_field_type_by_filter_value<'a>(table_name: &'a str, filter_value: &FilterValue) -> &'a Type {
+pub fn get_field_type_by_filter_value<'a>(table_name: &'a str, filter_value: &FilterValue) -> Cow<'a, Type> {
     // NOTE: At this stage (type analysis), the field exists, hence unwrap().
     match *filter_value {
+        FilterValue::Correlation(_) | FilterValue::Exists(_) | FilterValue::In(_) => Cow::Owned(Type::Bool),
         FilterValue::Identifier(ref identifier) => {
-            get_field_type(table_name, identifier).unwrap()
+            Cow::Borrowed(get_field_type(table_name, identifier).unwrap())
         },
         FilterValue::MethodCall(ast::MethodCall { ref method_name, ref object_name, .. }) => {
             let tables = tables_singleton();
@@ -350,7 +456,7 @@ fn get_field_type_by_filter_value<'a>(table_name: &'a str, filter_value: &Filter
                 };
             let type_methods = methods.get(&typ).unwrap();
             let method = type_methods.get(method_name).unwrap();
-            &method.return_type
+            Cow::Borrowed(&method.return_type)
         },
     }
 }
@@ -360,14 +466,29 @@ fn get_methods() -> Vec<String> {
     vec![
         "aggregate".to_owned(),
         "all".to_owned(),
+        "annotate".to_owned(),
+        "count".to_owned(),
         "create".to_owned(),
+        "create_junction_tables".to_owned(),
+        "defer".to_owned(),
         "delete".to_owned(),
+        "distinct".to_owned(),
+        "distinct_on".to_owned(),
         "drop".to_owned(),
+        "exists".to_owned(),
         "filter".to_owned(),
         "get".to_owned(),
+        "ignore".to_owned(),
+        "inner_join".to_owned(),
         "insert".to_owned(),
+        "insert_many".to_owned(),
         "join".to_owned(),
+        "left_join".to_owned(),
         "limit".to_owned(),
+        "on_conflict".to_owned(),
+        "only".to_owned(),
+        "prefetch".to_owned(),
+        "returning".to_owned(),
         "sort".to_owned(),
         "update".to_owned(),
         "values".to_owned(),
@@ -375,36 +496,44 @@ fn get_methods() -> Vec<String> {
 }
 
 /// Get the query field fully qualified names.
-fn get_query_fields(table: &SqlTable, joins: &[Join], sql_tables: &SqlTables) -> Vec<Identifier> {
+/// The `deferred_fields` of the `table` are not included.
+fn get_query_fields(table: &SqlTable, joins: &[Join], deferred_fields: &[Identifier], sql_tables: &SqlTables) -> Vec<Identifier> {
     let mut fields = vec![];
+    add_table_fields(table, &table.name, &[], joins, deferred_fields, sql_tables, &mut fields);
+    fields
+}
+
+/// Add the fully qualified names of the fields of the `table` referred to by `alias` to `fields`.
+/// The fields of the tables joined from this `table` are added recursively.
+fn add_table_fields(table: &SqlTable, alias: &str, path: &[Identifier], joins: &[Join], deferred_fields: &[Identifier], sql_tables: &SqlTables, fields: &mut Vec<Identifier>) {
     for (field, typ) in &table.fields {
         match typ.node {
-            // TODO: pay attention to name conflicts (join on same table twice).
-            Type::Custom(ref foreign_table) => {
-                let table_name = foreign_table;
+            Type::Custom(ref foreign_table) | Type::Nullable(box Type::Custom(ref foreign_table)) => {
                 if let Some(foreign_table) = sql_tables.get(foreign_table) {
-                    if has_joins(&joins, &field) {
-                        for (field, typ) in &foreign_table.fields {
-                            match typ.node {
-                                Type::Custom(_) | Type::UnsupportedType(_) => (), // NOTE: Do not add foreign key recursively.
-                                _ => {
-                                    fields.push(table_name.clone() + "." + &field);
-                                },
-                            }
+                    let mut field_path = path.to_vec();
+                    field_path.push(field.clone());
+                    if let Some(join) = joins.iter().find(|join| join.path == field_path) {
+                        // NOTE: The primary key of a joined table tells whether there is a related
+                        // row: when it is a foreign key (which is not a fetched field), it is
+                        // fetched before the other fields of this table.
+                        if let Some(&Type::Custom(_)) = foreign_table.fields.get(&join.joined_field).map(|typ| &typ.node) {
+                            fields.push(join.alias.clone() + "." + &join.joined_field);
                         }
+                        add_table_fields(foreign_table, &join.alias, &field_path, joins, &[], sql_tables, fields);
                     }
                 }
                 // TODO: Check if the foreign table exists instead of doing this in the lint plugin
                 // (it is needed here because the related fields need to be included in the query.)
                 // Not sure about this. I think it is ok like this.
             },
-            Type::UnsupportedType(_) => (),
+            // NOTE: A many-to-many field is fetched with a second query.
+            Type::ManyToMany(_) | Type::UnsupportedType(_) => (),
+            _ if deferred_fields.contains(field) => (),
             _ => {
-                fields.push(table.name.to_owned() + "." + &field);
+                fields.push(alias.to_owned() + "." + &field);
             },
         }
     }
-    fields
 }
 
 /// Get the string representation of an literal `Expression` type.
@@ -442,13 +571,6 @@ fn get_type(expression: &Expression) -> &str {
     }
 }
 
-/// Check if there is a join in `joins` on a field named `name`.
-pub fn has_joins(joins: &[Join], name: &str) -> bool {
-    joins.iter()
-        .map(|join| &join.base_field)
-        .any(|field_name| field_name == name)
-}
-
 /// Add a mismatched types error to `errors`.
 fn mismatched_types<S: Display, T: Display>(expected_type: S, actual_type: &T, position: Span, errors: &mut Vec<SqlError>) {
     errors.push(SqlError::new_with_code(
@@ -466,54 +588,121 @@ fn mismatched_types<S: Display, T: Display>(expected_type: S, actual_type: &T, p
 }
 
 /// Create a new query from all the data gathered by the method calls.
-fn new_query(QueryData { fields, filter, joins, limit, order, assignments, fields_to_create, aggregates, groups, aggregate_filter, query_type }: QueryData, table_name: String) -> Query {
+fn new_query(QueryData { checks, fields, filter, joins, junction_tables, limit, many_to_many, order, prefetch, assignments, conflict_action, conflict_target, rows, fields_to_create, foreign_keys, indexes, primary_key, returning, aggregates, groups, aggregate_filter, windows, distinct, query_type, unique_keys, .. }: QueryData, table_name: String) -> Query {
     match query_type {
         SqlQueryType::Aggregate =>
             Query::Aggregate {
                 aggregates: aggregates,
                 aggregate_filter: aggregate_filter,
+                distinct: distinct,
+                fields: vec![],
+                filter: filter,
+                groups: groups,
+                joins: joins,
+                table: table_name,
+                windows: vec![],
+            },
+        SqlQueryType::Annotate =>
+            Query::Aggregate {
+                aggregates: aggregates,
+                aggregate_filter: aggregate_filter,
+                distinct: Distinct::NoDistinct,
+                fields: fields,
                 filter: filter,
                 groups: groups,
                 joins: joins,
                 table: table_name,
+                windows: windows,
+            },
+        SqlQueryType::Count =>
+            Query::Count {
+                filter: filter,
+                joins: joins,
+                table: table_name,
+            },
+        SqlQueryType::CreateJunctionTables =>
+            Query::CreateJunctionTables {
+                junction_tables: junction_tables,
+                table: table_name,
             },
         SqlQueryType::CreateTable =>
             Query::CreateTable {
+                checks: checks,
                 fields: fields_to_create,
+                foreign_keys: foreign_keys,
+                indexes: indexes,
+                primary_key: primary_key,
                 table: table_name,
+                unique_keys: unique_keys,
             },
         SqlQueryType::Delete =>
             Query::Delete {
                 filter: filter,
+                returning: new_returning(returning, fields),
                 table: table_name,
             },
         SqlQueryType::Drop =>
             Query::Drop {
+                junction_tables: junction_tables,
+                table: table_name,
+            },
+        SqlQueryType::Exists =>
+            Query::Exists {
+                filter: filter,
+                joins: joins,
                 table: table_name,
             },
         SqlQueryType::Insert =>
             Query::Insert {
                 assignments: assignments,
+                on_conflict: conflict_action.map(|action| OnConflict {
+                    action: action,
+                    fields: conflict_target.unwrap_or(vec![]),
+                }),
+                table: table_name,
+            },
+        SqlQueryType::InsertMany =>
+            Query::InsertMany {
+                assignments: assignments,
+                // NOTE: The rows are always set when there are no errors, hence unwrap().
+                rows: rows.unwrap(),
                 table: table_name,
             },
         SqlQueryType::Select =>
             Query::Select {
+                distinct: distinct,
                 fields: fields,
                 filter: filter,
                 joins: joins,
                 limit: limit,
+                many_to_many: many_to_many,
                 order: order,
+                prefetch: prefetch,
                 table: table_name,
             },
         SqlQueryType::Update =>
             Query::Update {
                 assignments: assignments,
                 filter: filter,
+                returning: new_returning(returning, fields),
                 table: table_name,
             },
     }
 }
 
+/// Create the RETURNING clause from the `returning` fields.
+/// All the query `fields` are returned when the `returning` fields are empty.
+fn new_returning(returning: Option<FieldList>, fields: FieldList) -> Option<Returning> {
+    returning.map(|returning_fields| {
+        if returning_fields.is_empty() {
+            Returning::Table(fields)
+        }
+        else {
+            Returning::Fields(returning_fields)
+        }
+    })
+}
+
 /// Create an error about a table not having a primary key.
 pub fn no_primary_key(table_name: &str, position: Span) -> SqlError {
     SqlError::new(
@@ -545,6 +734,9 @@ fn path_expr_to_identifier(expression: &Expression, errors: &mut Vec<SqlError>)
 fn process_methods(calls: &[MethodCall], table: &SqlTable, delete_position: &mut Option<Span>) -> SqlResult<QueryData> {
     let mut errors = vec![];
     let mut query_data = QueryData::default();
+    let mut on_conflict_position = None;
+    let mut sort_position = None;
+    let mut values_position = None;
 
     for method_call in calls {
         match &method_call.name[..] {
@@ -557,24 +749,134 @@ fn process_methods(calls: &[MethodCall], table: &SqlTable, delete_position: &mut
             "all" => {
                 check_no_arguments(&method_call, &mut errors);
             },
+            "annotate" => {
+                try(convert_arguments(&method_call.arguments, table, argument_to_annotation), &mut errors, |annotations| {
+                    for annotation in annotations {
+                        match annotation {
+                            Annotation::Aggregate(aggregate, join) => {
+                                if query_data.joins.iter().all(|existing_join| existing_join.joined_table != join.joined_table) {
+                                    query_data.joins.push(join);
+                                }
+                                query_data.aggregates.push(aggregate);
+                            },
+                            Annotation::Window(window) => query_data.windows.push(window),
+                        }
+                    }
+                });
+                if query_data.joins.len() > 1 {
+                    errors.push(SqlError::new(
+                        "cannot annotate with aggregates over many tables",
+                        method_call.position,
+                    ));
+                }
+                // NOTE: The annotated objects are grouped by primary key (only needed when the
+                // rows of a related table are aggregated).
+                if !query_data.aggregates.is_empty() {
+                    query_data.groups = get_primary_key_fields(table).iter()
+                        .map(|primary_key_field| table.name.clone() + "." + primary_key_field)
+                        .collect();
+                }
+                query_data.query_type = SqlQueryType::Annotate;
+            },
+            "count" => {
+                check_no_arguments(&method_call, &mut errors);
+                query_data.query_type = SqlQueryType::Count;
+            },
             "create" => {
                 check_no_arguments(&method_call, &mut errors);
                 query_data.query_type = SqlQueryType::CreateTable;
                 for (field, typ) in &table.fields {
+                    // NOTE: A many-to-many field is stored in a junction table created by
+                    // create_junction_tables().
+                    if let Type::ManyToMany(_) = typ.node {
+                        continue;
+                    }
+                    let sql_type =
+                        if table.primary_key.len() == 1 && table.primary_key[0] == *field {
+                            primary_key_type_to_sql(&typ.node)
+                        }
+                        // NOTE: The PrimaryKey fields of a compound primary key are only part of the
+                        // PRIMARY KEY constraint.
+                        else if typ.node == Type::Serial && table.primary_key.len() > 1 {
+                            "SERIAL NOT NULL".to_owned()
+                        }
+                        else {
+                            typ.node.to_sql()
+                        };
+                    let sql_type =
+                        match table.defaults.get(field) {
+                            Some(default) => sql_type + " DEFAULT " + default,
+                            None => sql_type,
+                        };
                     query_data.fields_to_create.push(TypedField {
                         identifier: field.clone(),
-                        typ: typ.node.to_sql(),
+                        typ: sql_type,
                     });
                 }
+                query_data.foreign_keys = get_compound_foreign_keys(table, tables_singleton());
+                query_data.indexes = table.indexes.iter()
+                    .map(|index| TableIndex {
+                        fields: index.fields.clone(),
+                        method: index.method.clone(),
+                        name: index.name.clone(),
+                        table: table.name.clone(),
+                        unique: index.unique,
+                    })
+                    .collect();
+                // NOTE: A primary key with a single field is declared in its column.
+                if table.primary_key.len() > 1 {
+                    query_data.primary_key = table.primary_key.clone();
+                }
+                query_data.unique_keys = table.unique_keys.clone();
+                query_data.checks = table.checks.clone();
+            },
+            "create_junction_tables" => {
+                check_no_arguments(&method_call, &mut errors);
+                query_data.query_type = SqlQueryType::CreateJunctionTables;
+                query_data.junction_tables = get_junction_tables(table, tables_singleton());
+                if query_data.junction_tables.is_empty() {
+                    errors.push(SqlError::new(
+                        &format!("the table `{}` has no ManyToMany field", table.name),
+                        method_call.position,
+                    ));
+                }
+            },
+            "defer" => {
+                let mut fields = vec![];
+                try(convert_arguments(&method_call.arguments, table, argument_to_deferred_field), &mut errors, |new_fields| {
+                    fields = new_fields;
+                });
+                check_deferred_fields(&fields, method_call.position, table, &mut errors);
+                query_data.deferred_fields.append(&mut fields);
             },
             "delete" => {
                 check_no_arguments(&method_call, &mut errors);
                 query_data.query_type = SqlQueryType::Delete;
                 *delete_position = Some(method_call.position);
             },
+            "distinct" => {
+                check_no_arguments(&method_call, &mut errors);
+                query_data.distinct = Distinct::Distinct;
+            },
+            "distinct_on" => {
+                if method_call.arguments.is_empty() {
+                    errors.push(SqlError::new(
+                        "expected at least one field in the distinct_on() method",
+                        method_call.position,
+                    ));
+                }
+                try(convert_arguments(&method_call.arguments, table, argument_to_group), &mut errors, |fields| {
+                    query_data.distinct = Distinct::DistinctOn(fields);
+                });
+            },
             "drop" => {
                 check_no_arguments(&method_call, &mut errors);
                 query_data.query_type = SqlQueryType::Drop;
+                query_data.junction_tables = get_junction_tables(table, tables_singleton());
+            },
+            "exists" => {
+                check_no_arguments(&method_call, &mut errors);
+                query_data.query_type = SqlQueryType::Exists;
             },
             "filter" => {
                 if query_data.aggregates.is_empty() {
@@ -603,9 +905,14 @@ fn process_methods(calls: &[MethodCall], table: &SqlTable, delete_position: &mut
                     });
                 }
             },
+            "ignore" => {
+                check_no_arguments(&method_call, &mut errors);
+                check_on_conflict_called(&query_data, method_call, &mut errors);
+                query_data.conflict_action = Some(ConflictAction::Ignore);
+            },
             "insert" => {
                 try(convert_arguments(&method_call.arguments, table, argument_to_assignment), &mut errors, |assigns| {
-                    query_data.assignments = assigns;
+                    query_data.assignments = expand_compound_foreign_keys(assigns, table, &mut errors);
                 });
                 if !query_data.assignments.is_empty() {
                     // TODO: check even if there are errors in the assignation types.
@@ -613,9 +920,34 @@ fn process_methods(calls: &[MethodCall], table: &SqlTable, delete_position: &mut
                 }
                 query_data.query_type = SqlQueryType::Insert;
             },
-            "join" => {
-                try(convert_arguments(&method_call.arguments, table, argument_to_join), &mut errors, |mut new_joins| {
-                    query_data.joins.append(&mut new_joins);
+            "insert_many" => {
+                if check_argument_count(&method_call.arguments, 1, method_call.position, &mut errors) {
+                    try(argument_to_rows(&method_call.arguments[0], table), &mut errors, |(rows, assigns)| {
+                        query_data.rows = Some(rows);
+                        query_data.assignments = assigns;
+                    });
+                }
+                query_data.query_type = SqlQueryType::InsertMany;
+            },
+            "inner_join" | "join" | "left_join" => {
+                // NOTE: The related objects of a many-to-many field are fetched with a second query.
+                let (many_to_many_arguments, arguments): (Vec<_>, Vec<_>) = method_call.arguments.iter()
+                    .cloned()
+                    .partition(|argument| is_many_to_many_field(argument, table));
+                try(convert_arguments(&many_to_many_arguments, table, argument_to_many_to_many_join), &mut errors, |mut joins| {
+                    query_data.many_to_many.append(&mut joins);
+                });
+                try(convert_arguments(&arguments, table, argument_to_join), &mut errors, |new_joins| {
+                    // NOTE: join() uses the join type chosen from the foreign key nullability.
+                    let join_type =
+                        match &method_call.name[..] {
+                            "inner_join" => Some(JoinType::Inner),
+                            "left_join" => Some(JoinType::Left),
+                            _ => None,
+                        };
+                    for new_joins in new_joins {
+                        add_joins(&mut query_data.joins, new_joins, join_type.clone());
+                    }
                 });
             },
             "limit" => {
@@ -623,25 +955,126 @@ fn process_methods(calls: &[MethodCall], table: &SqlTable, delete_position: &mut
                     query_data.limit = new_limit;
                 });
             },
+            "on_conflict" => {
+                let mut fields = vec![];
+                try(convert_arguments(&method_call.arguments, table, argument_to_conflict_target), &mut errors, |targets| {
+                    fields = targets;
+                });
+                check_conflict_target(&fields, method_call.position, table, &mut errors);
+                query_data.conflict_target = Some(fields);
+                on_conflict_position = Some(method_call.position);
+            },
+            "only" => {
+                if method_call.arguments.is_empty() {
+                    errors.push(SqlError::new(
+                        "expected at least one field in the only() method",
+                        method_call.position,
+                    ));
+                }
+                let mut deferred_fields = vec![];
+                try(convert_arguments(&method_call.arguments, table, argument_to_projection_field), &mut errors, |fields| {
+                    deferred_fields = only_to_deferred_fields(&fields, table);
+                });
+                check_deferred_fields(&deferred_fields, method_call.position, table, &mut errors);
+                query_data.deferred_fields.append(&mut deferred_fields);
+            },
+            "prefetch" => {
+                if check_argument_count(&method_call.arguments, 1, method_call.position, &mut errors) {
+                    let argument = &method_call.arguments[0];
+                    if is_many_to_many_field(argument, table) {
+                        try(argument_to_many_to_many_join(argument, table), &mut errors, |join| {
+                            query_data.many_to_many.push(join);
+                        });
+                    }
+                    else if query_data.prefetch.is_some() {
+                        errors.push(SqlError::new(
+                            "cannot prefetch the related rows of more than one foreign key field",
+                            method_call.position,
+                        ));
+                    }
+                    else {
+                        try(argument_to_prefetch(argument, table), &mut errors, |prefetch| {
+                            query_data.prefetch = Some(prefetch);
+                        });
+                    }
+                }
+            },
+            "returning" => {
+                try(convert_arguments(&method_call.arguments, table, argument_to_returning_field), &mut errors, |fields| {
+                    query_data.returning = Some(fields);
+                });
+            },
             "sort" => {
                 try(convert_arguments(&method_call.arguments, table, argument_to_order), &mut errors, |new_order| {
                     query_data.order = new_order;
                 });
+                sort_position = Some(method_call.position);
             },
             "update" => {
-                try(convert_arguments(&method_call.arguments, table, argument_to_assignment), &mut errors, |assigns| {
-                    query_data.assignments = assigns;
-                });
-                query_data.query_type = SqlQueryType::Update;
+                if let SqlQueryType::Insert = query_data.query_type {
+                    // The update() method is the action to do on conflict when called after
+                    // insert().
+                    check_on_conflict_called(&query_data, method_call, &mut errors);
+                    if let Some(ref fields) = query_data.conflict_target {
+                        if fields.is_empty() {
+                            errors.push(SqlError::new(
+                                "the on_conflict() method requires the conflicting fields when followed by update()",
+                                on_conflict_position.unwrap_or(method_call.position),
+                            ));
+                        }
+                    }
+                    try(convert_arguments(&method_call.arguments, table, argument_to_conflict_assignment), &mut errors, |assigns| {
+                        query_data.conflict_action = Some(ConflictAction::Update(expand_compound_foreign_keys(assigns, table, &mut errors)));
+                    });
+                }
+                else {
+                    try(convert_arguments(&method_call.arguments, table, argument_to_assignment), &mut errors, |assigns| {
+                        query_data.assignments = expand_compound_foreign_keys(assigns, table, &mut errors);
+                    });
+                    query_data.query_type = SqlQueryType::Update;
+                }
             },
             "values" => {
                 try(convert_arguments(&method_call.arguments, table, argument_to_group), &mut errors, |new_groups| {
                     query_data.groups = new_groups;
                 });
+                values_position = Some(method_call.position);
             },
             _ => (), // NOTE: Nothing to do since check_methods() check for unknown method.
         }
     }
+
+    if let Distinct::DistinctOn(ref fields) = query_data.distinct {
+        if let Some(position) = sort_position {
+            check_distinct_on_order(fields, &query_data.order, position, &mut errors);
+        }
+    }
+
+    // NOTE: Without the aggregate() method, values() selects the distinct values.
+    if let Some(position) = values_position {
+        if let SqlQueryType::Select = query_data.query_type {
+            if let Distinct::Distinct = query_data.distinct {
+                check_distinct_values_methods(calls, &mut errors);
+                query_data.query_type = SqlQueryType::Aggregate;
+            }
+            else {
+                errors.push(SqlError::new(
+                    "the values() method must be called with either the aggregate() or the distinct() method",
+                    position,
+                ));
+            }
+        }
+    }
+
+    if let Some(position) = on_conflict_position {
+        if query_data.conflict_action.is_none() && errors.is_empty() {
+            errors.push(SqlError::new(
+                "the on_conflict() method must be followed by either ignore() or update()",
+                position,
+            ));
+        }
+    }
+
     res(query_data, errors)
 }
 
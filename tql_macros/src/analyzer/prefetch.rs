@@ -0,0 +1,77 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+/// Analyzer for the prefetch() method.
+
+use syntax::ast::Expr_::ExprField;
+
+use ast::{Expression, Prefetch};
+use error::{SqlError, SqlResult, res};
+use state::{SqlTable, get_primary_key_field, tables_singleton};
+use super::{check_field, get_query_fields, mismatched_types, no_primary_key, path_expr_to_identifier, unknown_table_error};
+use types::get_related_table_name;
+
+/// Convert an `Expression` to a `Prefetch`.
+/// The argument is the foreign key field of another table referring to the main `table` (for
+/// instance, `Comment.post`).
+pub fn argument_to_prefetch(arg: &Expression, table: &SqlTable) -> SqlResult<Prefetch> {
+    let mut errors = vec![];
+    let mut prefetch = Prefetch::default();
+
+    if let ExprField(ref expr, ref field) = arg.node {
+        if let Some(related_table_name) = path_expr_to_identifier(expr, &mut errors) {
+            let sql_tables = tables_singleton();
+            match sql_tables.get(&related_table_name) {
+                Some(related_table) => {
+                    let field_name = field.node.to_string();
+                    check_field(&field_name, field.span, related_table, &mut errors);
+                    if let Some(field_type) = related_table.fields.get(&field_name) {
+                        if get_related_table_name(&field_type.node) != Some(&table.name) {
+                            mismatched_types(format!("ForeignKey<{}>", table.name), &field_type.node, field.span, &mut errors);
+                        }
+                    }
+                    // NOTE: The related rows are fetched by the primary key of the objects.
+                    if table.primary_key.len() > 1 {
+                        errors.push(SqlError::new(
+                            &format!("cannot prefetch the related rows of the table `{}` which has a compound primary key", table.name),
+                            arg.span,
+                        ));
+                    }
+                    match get_primary_key_field(table) {
+                        Some(_) => {
+                            prefetch = Prefetch {
+                                field: field_name,
+                                fields: get_query_fields(related_table, &[], &[], sql_tables),
+                                table: related_table_name,
+                            };
+                        },
+                        None => errors.push(no_primary_key(&table.name, table.position)),
+                    }
+                },
+                None => unknown_table_error(&related_table_name, expr.span, sql_tables, &mut errors),
+            }
+        }
+    }
+    else {
+        errors.push(SqlError::new(
+            "expected a foreign key field of another table (for instance, Table.field)",
+            arg.span,
+        ));
+    }
+
+    res(prefetch, errors)
+}
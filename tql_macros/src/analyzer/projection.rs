@@ -0,0 +1,94 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+/// Analyzer for the only() and defer() methods.
+
+use syntax::codemap::Span;
+
+use ast::{Expression, FieldList, Identifier};
+use error::{SqlError, SqlResult, res};
+use state::{SqlTable, get_primary_key_fields};
+use super::{check_field, path_expr_to_identifier};
+use types::{Type, get_related_table_name, has_default};
+
+/// Convert an argument of the defer() method to the `Identifier` of a field to defer.
+/// The primary key is always fetched since it identifies the object.
+pub fn argument_to_deferred_field(arg: &Expression, table: &SqlTable) -> SqlResult<Identifier> {
+    let field = try!(argument_to_projection_field(arg, table));
+    let mut errors = vec![];
+    if get_primary_key_fields(table).contains(&field) {
+        errors.push(SqlError::new(
+            &format!("cannot defer the primary key field `{}`", field),
+            arg.span,
+        ));
+    }
+    res(field, errors)
+}
+
+/// Convert an `Expression` to the `Identifier` of a field to fetch or to defer.
+pub fn argument_to_projection_field(arg: &Expression, table: &SqlTable) -> SqlResult<Identifier> {
+    let mut errors = vec![];
+    let mut identifier = "".to_owned();
+    if let Some(field) = path_expr_to_identifier(arg, &mut errors) {
+        check_field(&field, arg.span, table, &mut errors);
+        if let Some(_) = table.fields.get(&field).and_then(|typ| get_related_table_name(&typ.node)) {
+            errors.push(SqlError::new(
+                &format!("cannot select the foreign key field `{}` with only() or defer()", field),
+                arg.span,
+            ));
+            errors.push(SqlError::new_help(
+                "use join() to fetch the related object",
+                arg.span,
+            ));
+        }
+        identifier = field;
+    }
+    res(identifier, errors)
+}
+
+/// Check that the deferred `fields` can get a default value.
+pub fn check_deferred_fields(fields: &[Identifier], position: Span, table: &SqlTable, errors: &mut Vec<SqlError>) {
+    for field in fields {
+        if let Some(typ) = table.fields.get(field) {
+            if !has_default(&typ.node) {
+                errors.push(SqlError::new(
+                    &format!("the field `{field}` cannot be deferred because its type `{typ}` does not implement Default",
+                        field = field,
+                        typ = typ.node
+                    ),
+                    position,
+                ));
+            }
+        }
+    }
+}
+
+/// Get the fields that are not fetched when only the `fields` are fetched.
+/// The primary key is always fetched since it identifies the object.
+pub fn only_to_deferred_fields(fields: &[Identifier], table: &SqlTable) -> FieldList {
+    let primary_key = get_primary_key_fields(table);
+    table.fields.iter()
+        .filter(|&(field, typ)| {
+            match typ.node {
+                // NOTE: Foreign keys and many-to-many fields are fetched with join().
+                Type::Custom(_) | Type::ManyToMany(_) | Type::Nullable(box Type::Custom(_)) | Type::UnsupportedType(_) => false,
+                _ => !fields.contains(field) && !primary_key.contains(field),
+            }
+        })
+        .map(|(field, _)| field.clone())
+        .collect()
+}
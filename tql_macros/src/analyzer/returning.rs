@@ -0,0 +1,60 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+/// Analyzer for the returning() method.
+
+use ast::{Expression, Identifier};
+use error::{SqlError, SqlResult, res};
+use state::SqlTable;
+use super::{check_field, path_expr_to_identifier};
+use types::Type;
+
+/// Convert an `Expression` to a field `Identifier` to return.
+pub fn argument_to_returning_field(arg: &Expression, table: &SqlTable) -> SqlResult<Identifier> {
+    let mut errors = vec![];
+    let mut identifier = "".to_owned();
+    if let Some(field) = path_expr_to_identifier(arg, &mut errors) {
+        match table.fields.get(&field) {
+            Some(typ) => {
+                match typ.node {
+                    Type::Custom(_) | Type::Nullable(box Type::Custom(_)) => {
+                        errors.push(SqlError::new(
+                            &format!("cannot return the foreign key field `{}`", field),
+                            arg.span,
+                        ));
+                    },
+                    Type::ManyToMany(_) => {
+                        errors.push(SqlError::new(
+                            &format!("cannot return the many-to-many field `{}`", field),
+                            arg.span,
+                        ));
+                    },
+                    Type::UnsupportedType(_) => {
+                        errors.push(SqlError::new(
+                            &format!("cannot return the field `{}` of unsupported type", field),
+                            arg.span,
+                        ));
+                    },
+                    _ => (),
+                }
+            },
+            None => check_field(&field, arg.span, table, &mut errors),
+        }
+        identifier = field;
+    }
+    res(identifier, errors)
+}
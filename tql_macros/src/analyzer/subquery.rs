@@ -0,0 +1,338 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+/// Analyzer for the subqueries in the filter() method.
+
+use std::mem;
+
+use syntax::ast::Expr_::{ExprAddrOf, ExprBinary, ExprCall, ExprCast, ExprField, ExprIndex, ExprMethodCall, ExprParen, ExprPath, ExprTup, ExprTupField, ExprUnary, ExprVec};
+use syntax::codemap::{Span, Spanned};
+
+use ast::{Correlation, Expression, Filter, FilterExpression, FilterValue, Identifier, Membership, MembershipValues, Query};
+use error::{SqlError, SqlResult, res};
+use parser::{MethodCall, parse};
+use state::{SqlTable, get_field_type, get_primary_key_type_by_table_name, tables_singleton};
+use types::Type;
+use super::{analyze, analyze_types, check_field, convert_arguments, get_field_type_by_filter_value, mismatched_types, try};
+use super::aggregate::argument_to_group;
+
+/// Convert an `Expression` to a subquery.
+/// The subquery is analyzed like the other queries and its `outer.field` values refer to the
+/// fields of the `outer_table`.
+pub fn argument_to_subquery(arg: &Expression, outer_table: &SqlTable) -> SqlResult<Query> {
+    let mut errors = vec![];
+    let mut method_calls = try!(parse(arg.clone()));
+
+    let invalid_methods = ["create", "create_junction_tables", "delete", "drop", "insert", "insert_many", "prefetch", "update"];
+    for call in method_calls.calls.iter().filter(|call| invalid_methods.contains(&&*call.name)) {
+        errors.push(SqlError::new(
+            &format!("cannot call the {method}() method in a subquery",
+                method = call.name
+            ),
+            call.position,
+        ));
+    }
+
+    // NOTE: Without the aggregate() and distinct() methods, values() selects the column of the
+    // subquery.
+    let selects_values = method_calls.calls.iter().all(|call| call.name != "aggregate" && call.name != "distinct");
+    let values_index = method_calls.calls.iter().position(|call| call.name == "values");
+    let values_call =
+        match values_index {
+            Some(index) if selects_values => Some(method_calls.calls.remove(index)),
+            _ => None,
+        };
+
+    let mut query =
+        match analyze(method_calls, tables_singleton()) {
+            Ok(query) => query,
+            Err(mut analyze_errors) => {
+                errors.append(&mut analyze_errors);
+                return Err(errors);
+            },
+        };
+
+    if let Some(values_call) = values_call {
+        select_values(&mut query, &values_call, &mut errors);
+    }
+
+    match query {
+        Query::Aggregate { ref mut filter, ref table, .. } | Query::Count { ref mut filter, ref table, .. } |
+            Query::Exists { ref mut filter, ref table, .. } | Query::Select { ref mut filter, ref table, .. } =>
+        {
+            correlate_filter(filter, table, outer_table, &mut errors);
+        },
+        _ => (), // NOTE: The other queries are rejected above.
+    }
+
+    match analyze_types(query) {
+        Ok(query) => res(query, errors),
+        Err(mut type_errors) => {
+            errors.append(&mut type_errors);
+            Err(errors)
+        },
+    }
+}
+
+/// Check that the `actual_type` of a column can be compared to the `expected_type`.
+pub fn check_column_type(expected_type: &Type, actual_type: &Type, position: Span, errors: &mut Vec<SqlError>) {
+    if column_type(expected_type) != column_type(actual_type) {
+        mismatched_types(expected_type, actual_type, position, errors);
+    }
+}
+
+/// Add an error for each `outer.field` value in the arguments of the `filter_value`.
+fn check_filter_value_outer_fields(filter_value: &FilterValue, errors: &mut Vec<SqlError>) {
+    match *filter_value {
+        FilterValue::In(Membership { ref operand, ref values }) => {
+            check_filter_value_outer_fields(operand, errors);
+            match *values {
+                MembershipValues::Array(ref expression) => check_nested_outer_fields(expression, errors),
+                MembershipValues::InclusiveRange(ref start, ref end) | MembershipValues::Range(ref start, ref end) => {
+                    check_nested_outer_fields(start, errors);
+                    check_nested_outer_fields(end, errors);
+                },
+                MembershipValues::List(ref expressions) => {
+                    for expression in expressions {
+                        check_nested_outer_fields(expression, errors);
+                    }
+                },
+                MembershipValues::Query(_) => (),
+            }
+        },
+        FilterValue::MethodCall(ref method_call) => {
+            for argument in &method_call.arguments {
+                check_nested_outer_fields(argument, errors);
+            }
+        },
+        FilterValue::Correlation(_) | FilterValue::Exists(_) | FilterValue::Identifier(_) => (),
+    }
+}
+
+/// Add an error for each `outer.field` value nested in the `expression`.
+/// An `outer.field` value can only be compared as a whole to a field of the subquery.
+fn check_nested_outer_fields(expression: &Expression, errors: &mut Vec<SqlError>) {
+    if outer_field_name(expression).is_some() {
+        errors.push(SqlError::new(
+            "cannot use a field of the outer query in an expression",
+            expression.span,
+        ));
+        errors.push(SqlError::new_help(
+            "compare a field of the subquery to the outer.field value directly",
+            expression.span,
+        ));
+        return;
+    }
+
+    match expression.node {
+        ExprAddrOf(_, ref expr) | ExprCast(ref expr, _) | ExprField(ref expr, _) | ExprParen(ref expr) |
+            ExprTupField(ref expr, _) | ExprUnary(_, ref expr) =>
+        {
+            check_nested_outer_fields(expr, errors);
+        },
+        ExprBinary(_, ref expr1, ref expr2) | ExprIndex(ref expr1, ref expr2) => {
+            check_nested_outer_fields(expr1, errors);
+            check_nested_outer_fields(expr2, errors);
+        },
+        ExprCall(ref function, ref arguments) => {
+            check_nested_outer_fields(function, errors);
+            for argument in arguments {
+                check_nested_outer_fields(argument, errors);
+            }
+        },
+        ExprMethodCall(_, _, ref expressions) | ExprTup(ref expressions) | ExprVec(ref expressions) => {
+            for expression in expressions {
+                check_nested_outer_fields(expression, errors);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// Check that the `query` of the in() method selects one column of the `operand_type`.
+pub fn check_subquery_type(operand_type: &Type, query: &Query, position: Span, errors: &mut Vec<SqlError>) {
+    let column_types = selected_column_types(query);
+    if column_types.len() == 1 {
+        if let Some(ref typ) = column_types[0] {
+            check_column_type(operand_type, typ, position, errors);
+        }
+    }
+    else {
+        errors.push(SqlError::new(
+            &format!("expected a subquery selecting 1 column, found {} columns", column_types.len()),
+            position,
+        ));
+        errors.push(SqlError::new_help(
+            "select a field with the values() method",
+            position,
+        ));
+    }
+}
+
+/// Get the type of the values stored in a column of type `typ`.
+/// A foreign key stores the primary key of the related table.
+fn column_type(typ: &Type) -> Type {
+    match *typ {
+        Type::Custom(ref table_name) => get_primary_key_type_by_table_name(table_name).unwrap_or(typ.clone()),
+        Type::Nullable(ref typ) => column_type(typ),
+        Type::Serial => Type::I32,
+        ref typ => typ.clone(),
+    }
+}
+
+/// Convert the comparisons with the `outer.field` values in the `filter` to `Correlation`s.
+fn correlate_filter(filter: &mut FilterExpression, table_name: &str, outer_table: &SqlTable, errors: &mut Vec<SqlError>) {
+    let outer_field =
+        match *filter {
+            FilterExpression::Filter(ref comparison) => {
+                check_filter_value_outer_fields(&comparison.operand1, errors);
+                let outer_field = outer_field_name(&comparison.operand2)
+                    .map(|field| (field, comparison.operand2.span));
+                if outer_field.is_none() {
+                    check_nested_outer_fields(&comparison.operand2, errors);
+                }
+                outer_field
+            },
+            FilterExpression::Filters(ref mut filters) => {
+                correlate_filter(&mut filters.operand1, table_name, outer_table, errors);
+                correlate_filter(&mut filters.operand2, table_name, outer_table, errors);
+                None
+            },
+            FilterExpression::NegFilter(ref mut filter) | FilterExpression::ParenFilter(ref mut filter) => {
+                correlate_filter(filter, table_name, outer_table, errors);
+                None
+            },
+            FilterExpression::FilterValue(ref filter_value) => {
+                check_filter_value_outer_fields(&filter_value.node, errors);
+                None
+            },
+            FilterExpression::NoFilters => None,
+        };
+
+    if let Some((field, position)) = outer_field {
+        // NOTE: A field qualified by the table name refers to the table of the subquery when it
+        // is also the table of the outer query.
+        if table_name == outer_table.name {
+            errors.push(SqlError::new(
+                &format!("cannot refer to the outer query on the same table `{}` in a subquery", table_name),
+                position,
+            ));
+            return;
+        }
+        check_field(&field, position, outer_table, errors);
+        if let FilterExpression::Filter(Filter { operand1, operator, .. }) = mem::replace(filter, FilterExpression::NoFilters) {
+            if let Some(outer_type) = outer_table.fields.get(&field) {
+                let field_type = get_field_type_by_filter_value(table_name, &operand1);
+                check_column_type(&field_type, &outer_type.node, position, errors);
+            }
+            *filter = FilterExpression::FilterValue(Spanned {
+                node: FilterValue::Correlation(Correlation {
+                    operand: box operand1,
+                    operator: operator,
+                    outer_field: outer_table.name.clone() + "." + &field,
+                }),
+                span: position,
+            });
+        }
+    }
+}
+
+/// Check if the `expression` is a query on an SQL table (for instance, `Table.filter(…)`).
+pub fn is_subquery(expression: &Expression) -> bool {
+    let object =
+        match expression.node {
+            ExprIndex(ref object, _) => object,
+            ExprMethodCall(_, _, ref arguments) => &arguments[0],
+            _ => return false,
+        };
+    match object.node {
+        ExprPath(None, ref path) if path.segments.len() == 1 => {
+            let table_name = path.segments[0].identifier.to_string();
+            tables_singleton().contains_key(&table_name)
+        },
+        _ => is_subquery(object),
+    }
+}
+
+/// Get the field name if the `expression` is a field of the outer query (`outer.field`).
+fn outer_field_name(expression: &Expression) -> Option<Identifier> {
+    if let ExprField(ref object, ref field) = expression.node {
+        if let ExprPath(None, ref path) = object.node {
+            if path.segments.len() == 1 && path.segments[0].identifier.to_string() == "outer" {
+                return Some(field.node.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Select the fields of the values() method `call` in the select `query`.
+fn select_values(query: &mut Query, call: &MethodCall, errors: &mut Vec<SqlError>) {
+    if let Query::Select { ref mut fields, ref table, .. } = *query {
+        // NOTE: At this stage (after the analysis), the table exists, hence unwrap().
+        let sql_table = tables_singleton().get(table).unwrap();
+        try(convert_arguments(&call.arguments, sql_table, argument_to_group), errors, |values| {
+            *fields = values.iter()
+                .map(|field| table.clone() + "." + field)
+                .collect();
+        });
+    }
+    else {
+        errors.push(SqlError::new(
+            "the values() method must select the column of a select subquery",
+            call.position,
+        ));
+    }
+}
+
+/// Get the types of the columns selected by the `query`.
+/// The type is `None` when it is not a field of the query table.
+fn selected_column_types(query: &Query) -> Vec<Option<Type>> {
+    match *query {
+        Query::Aggregate { ref aggregates, ref fields, ref groups, ref table, ref windows, .. } => {
+            let grouped_fields =
+                if fields.is_empty() {
+                    groups
+                }
+                else {
+                    fields
+                };
+            grouped_fields.iter()
+                .map(|field| selected_field_type(table, field))
+                .chain(aggregates.iter().map(|aggregate| Some(aggregate.result_type.clone())))
+                .chain(windows.iter().map(|window| Some(window.function.result_type.clone())))
+                .collect()
+        },
+        Query::Count { .. } => vec![Some(Type::I64)],
+        Query::Exists { .. } => vec![Some(Type::Bool)],
+        Query::Select { ref fields, ref table, .. } => {
+            fields.iter()
+                .map(|field| selected_field_type(table, field))
+                .collect()
+        },
+        // NOTE: These queries are rejected by argument_to_subquery().
+        Query::CreateJunctionTables { .. } | Query::CreateTable { .. } | Query::Delete { .. } | Query::Drop { .. } |
+            Query::Insert { .. } | Query::InsertMany { .. } | Query::Update { .. } => vec![],
+    }
+}
+
+/// Get the type of the selected `field` if it is a field of the table `table_name`.
+fn selected_field_type(table_name: &str, field: &str) -> Option<Type> {
+    // NOTE: The selected fields may be qualified by the table name.
+    let field = field.split('.').last().unwrap_or(field);
+    get_field_type(table_name, field).cloned()
+}
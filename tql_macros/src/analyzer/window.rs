@@ -0,0 +1,120 @@
+/*
+ * Copyright (C) 2015  Boucher, Antoni <bouanto@zoho.com>
+ * 
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ * 
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ * 
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+
+/// Analyzer for the window functions (called with over() in the annotate() method).
+
+use syntax::ast::Expr_::{ExprAssign, ExprCall, ExprMethodCall};
+
+use ast::{Expression, Window};
+use error::{SqlError, SqlResult, res};
+use state::{SqlAggregates, SqlTable, aggregates_singleton, window_functions_singleton};
+use string::plural_verb;
+use super::{path_expr_to_identifier, try};
+use super::aggregate::{argument_to_group, call_to_aggregate, get_call_from_aggregate, table_field};
+use super::sort::argument_to_order;
+
+/// Convert an `Expression` to a `Window`.
+pub fn argument_to_window(arg: &Expression, table: &SqlTable) -> SqlResult<Window> {
+    let mut errors = vec![];
+    let mut window = Window::default();
+    window.table = table.name.clone();
+    let window_functions: &SqlAggregates = window_functions_singleton();
+    let aggregates: &SqlAggregates = aggregates_singleton();
+
+    let call = get_call_from_aggregate(arg, &mut window.function, &mut errors);
+
+    if let ExprMethodCall(_, _, ref arguments) = call.node {
+        // NOTE: The first argument is the function call on which over() is called.
+        call_to_aggregate(&arguments[0], &[window_functions, aggregates], &mut window.function, &mut |expression, errors| table_field(expression, table, errors), &mut errors);
+        if arguments.len() == 2 {
+            add_window_clauses(&arguments[1], table, &mut window, &mut errors);
+        }
+        else {
+            errors.push(SqlError::new_with_code(
+                &format!("this function takes 1 parameter but {} parameter{} supplied", arguments.len() - 1, plural_verb(arguments.len() - 1)),
+                call.span,
+                "E0061",
+            ));
+        }
+    }
+
+    res(window, errors)
+}
+
+/// Check if the `arg` expression is a window function call (like `rank().over(sort(field))`).
+pub fn is_window(arg: &Expression) -> bool {
+    let call =
+        if let ExprAssign(_, ref right_value) = arg.node {
+            right_value
+        }
+        else {
+            arg
+        };
+    if let ExprMethodCall(ref method, _, _) = call.node {
+        method.node.to_string() == "over"
+    }
+    else {
+        false
+    }
+}
+
+/// Add the PARTITION BY and ORDER BY clauses from the `expression` (like
+/// `partition(field1).sort(-field2)`) to the `window`.
+fn add_window_clauses(expression: &Expression, table: &SqlTable, window: &mut Window, errors: &mut Vec<SqlError>) {
+    match expression.node {
+        ExprMethodCall(ref method, _, ref arguments) if method.node.to_string() == "sort" => {
+            add_window_clauses(&arguments[0], table, window, errors);
+            add_window_order(&arguments[1..], table, window, errors);
+        },
+        ExprCall(ref function, ref arguments) => {
+            if let Some(identifier) = path_expr_to_identifier(function, errors) {
+                match &identifier[..] {
+                    "partition" => {
+                        for argument in arguments {
+                            try(argument_to_group(argument, table), errors, |group| {
+                                window.partition.push(group);
+                            });
+                        }
+                    },
+                    "sort" => add_window_order(arguments, table, window, errors),
+                    _ => {
+                        errors.push(SqlError::new(
+                            "Expected partition() or sort()", // TODO: improve this message.
+                            function.span,
+                        ));
+                    },
+                }
+            }
+        },
+        _ => {
+            errors.push(SqlError::new(
+                "Expected partition() or sort()", // TODO: improve this message.
+                expression.span,
+            ));
+        },
+    }
+}
+
+/// Add the orders from the sort() `arguments` to the `window`.
+fn add_window_order(arguments: &[Expression], table: &SqlTable, window: &mut Window, errors: &mut Vec<SqlError>) {
+    for argument in arguments {
+        try(argument_to_order(argument, table), errors, |order| {
+            window.order.push(order);
+        });
+    }
+}
@@ -20,31 +20,32 @@
 use syntax::ast::Expr_::ExprLit;
 use syntax::ext::base::ExtCtxt;
 
-use ast::{Aggregate, AggregateFilterExpression, Assignment, Expression, FilterExpression, FilterValue, Identifier, Limit, MethodCall, Query, query_table};
-use state::{get_field_type, get_method_types};
+use analyzer::get_field_type_by_filter_value;
+use ast::{Aggregate, AggregateFilterExpression, Assignment, AssignmentValue, ConflictAction, Expression, FilterExpression, FilterValue, Identifier, Limit, Membership, MembershipValues, MethodCall, Query, query_table};
+use state::{get_aggregate_types, get_field_type, get_method_types, get_primary_key_type_by_table_name, tables_singleton};
 use types::Type;
 
 macro_rules! add_filter_arguments {
     ( $name:ident, $typ:ident, $func:ident ) => {
         /// Create arguments from the `filter` and add them to `arguments`.
-        fn $name(filter: $typ, args: &mut Args, table_name: &str) {
-            match filter {
-                $typ::Filter(filter) => {
-                    $func(&filter.operand1, args, table_name, Some(filter.operand2));
+        fn $name(cx: &mut ExtCtxt, filter: &$typ, args: &mut Args, table_name: &str) {
+            match *filter {
+                $typ::Filter(ref filter) => {
+                    $func(cx, &filter.operand1, args, table_name, Some(filter.operand2.clone()));
                 },
-                $typ::Filters(filters) => {
-                    $name(*filters.operand1, args, table_name);
-                    $name(*filters.operand2, args, table_name);
+                $typ::Filters(ref filters) => {
+                    $name(cx, &filters.operand1, args, table_name);
+                    $name(cx, &filters.operand2, args, table_name);
                 },
-                $typ::NegFilter(box filter) => {
-                    $name(filter, args, table_name);
+                $typ::NegFilter(ref filter) => {
+                    $name(cx, filter, args, table_name);
                 },
                 $typ::NoFilters => (),
-                $typ::ParenFilter(box filter) => {
-                    $name(filter, args, table_name);
+                $typ::ParenFilter(ref filter) => {
+                    $name(cx, filter, args, table_name);
                 },
-                $typ::FilterValue(filter_value) => {
-                    $func(&filter_value.node, args, table_name, None);
+                $typ::FilterValue(ref filter_value) => {
+                    $func(cx, &filter_value.node, args, table_name, None);
                 },
             }
         }
@@ -71,12 +72,29 @@ fn add(arguments: &mut Args, field_name: Option<Identifier>, typ: Type, expr: Ex
     });
 }
 
+/// Create arguments from the assignment `value` (or a part of it) to the field `identifier` and
+/// add them to `arguments`.
+fn add_assignment_value(value: &AssignmentValue, identifier: &Identifier, arguments: &mut Args, table_name: &str) {
+    match *value {
+        // NOTE: The excluded values come from the row to insert, hence there is no argument to
+        // add.
+        AssignmentValue::Excluded(_) => (),
+        AssignmentValue::Expression(ref value) => {
+            // NOTE: At this stage (code generation), the field exists, hence unwrap().
+            let field_type = get_field_type(table_name, identifier).unwrap();
+            add(arguments, Some(identifier.clone()), argument_type(field_type), value.clone());
+        },
+        AssignmentValue::Operation(ref operand1, _, ref operand2) => {
+            add_assignment_value(operand1, identifier, arguments, table_name);
+            add_assignment_value(operand2, identifier, arguments, table_name);
+        },
+    }
+}
+
 /// Create arguments from the `assignments` and add them to `arguments`.
-fn add_assignments(assignments: Vec<Assignment>, arguments: &mut Args, table_name: &str) {
+fn add_assignments(assignments: &[Assignment], arguments: &mut Args, table_name: &str) {
     for assign in assignments {
-        // NOTE: At this stage (code generation), the field exists, hence unwrap().
-        let field_type = get_field_type(table_name, &assign.identifier).unwrap();
-        add(arguments, Some(assign.identifier), field_type.clone(), assign.value);
+        add_assignment_value(&assign.value, &assign.identifier, arguments, table_name);
     }
 }
 
@@ -93,24 +111,37 @@ add_filter_arguments!(add_filter_arguments, FilterExpression, add_filter_value_a
 
 add_filter_arguments!(add_aggregate_filter_arguments, AggregateFilterExpression, add_aggregate_filter_value_arguments);
 
+/// Get the type of the argument assigned to or compared with a field of type `field_type`.
+/// A foreign key referencing a compound primary key is sent as the last field of this key since
+/// its other fields are assigned or compared separately.
+fn argument_type(field_type: &Type) -> Type {
+    match *field_type {
+        Type::Custom(ref related_table_name) if tables_singleton().get(related_table_name).map_or(false, |table| table.primary_key.len() > 1) =>
+            // NOTE: At this stage (code generation), the related table has a primary key, hence
+            // unwrap().
+            get_primary_key_type_by_table_name(related_table_name).unwrap(),
+        ref typ => typ.clone(),
+    }
+}
+
 /// Create arguments from the `limit` and add them to `arguments`.
-fn add_limit_arguments(cx: &mut ExtCtxt, limit: Limit, arguments: &mut Args) {
-    match limit {
-        Limit::EndRange(expression) => add(arguments, None, Type::I64, expression),
-        Limit::Index(expression) => add(arguments, None, Type::I64, expression),
+fn add_limit_arguments(cx: &mut ExtCtxt, limit: &Limit, arguments: &mut Args) {
+    match *limit {
+        Limit::EndRange(ref expression) => add(arguments, None, Type::I64, expression.clone()),
+        Limit::Index(ref expression) => add(arguments, None, Type::I64, expression.clone()),
         Limit::LimitOffset(_, _) => (), // NOTE: there are no arguments to add for a `LimitOffset` because it is always using literals.
         Limit::NoLimit => (),
-        Limit::Range(expression1, expression2) => {
+        Limit::Range(ref expression1, ref expression2) => {
             let offset = expression1.clone();
-            add(arguments, None, Type::I64, expression1);
-            let expr2 = expression2;
+            add(arguments, None, Type::I64, expression1.clone());
+            let expr2 = expression2.clone();
             add_expr(arguments, Arg {
                 expression: quote_expr!(cx, $expr2 - $offset),
                 field_name: None,
                 typ: Type::I64,
             });
         },
-        Limit::StartRange(expression) => add(arguments, None, Type::I64, expression),
+        Limit::StartRange(ref expression) => add(arguments, None, Type::I64, expression.clone()),
     }
 }
 
@@ -125,13 +156,27 @@ fn add_with_method(args: &mut Args, method_name: &str, object_name: &str, index:
     });
 }
 
-fn add_aggregate_filter_value_arguments(aggregate: &Aggregate, args: &mut Args, _table_name: &str, expression: Option<Expression>) {
+/// Create arguments from the `aggregate` function call and add them to `arguments`.
+fn add_aggregate_arguments(aggregate: &Aggregate, arguments: &mut Args) {
+    if !aggregate.arguments.is_empty() {
+        // NOTE: At this stage (code generation), the aggregate exists, hence unwrap().
+        // The field is the first argument of the function.
+        let aggregate_types = get_aggregate_types(&aggregate.function, aggregate.arguments.len() + 1).unwrap();
+        for (argument, argument_type) in aggregate.arguments.iter().zip(&aggregate_types.argument_types[1..]) {
+            add(arguments, None, argument_type.clone(), argument.clone());
+        }
+    }
+}
+
+fn add_aggregate_filter_value_arguments(_cx: &mut ExtCtxt, aggregate: &Aggregate, args: &mut Args, _table_name: &str, expression: Option<Expression>) {
+    // NOTE: The arguments of the aggregate function appear before the value in the HAVING clause.
+    add_aggregate_arguments(aggregate, args);
     if let Some(expr) = expression {
-        add(args, Some(aggregate.field.clone()), Type::I32, expr); // TODO: use the right type.
+        add(args, Some(aggregate.result_name.clone()), aggregate.result_type.clone(), expr);
     }
 }
 
-fn add_filter_value_arguments(filter_value: &FilterValue, args: &mut Args, table_name: &str, expression: Option<Expression>) {
+fn add_filter_value_arguments(cx: &mut ExtCtxt, filter_value: &FilterValue, args: &mut Args, table_name: &str, expression: Option<Expression>) {
     match *filter_value {
         FilterValue::Identifier(ref identifier) => {
             // It is possible to have an identifier without expression, when the identifier is a
@@ -139,9 +184,16 @@ fn add_filter_value_arguments(filter_value: &FilterValue, args: &mut Args, table
             if let Some(expr) = expression {
                 // NOTE: At this stage (code generation), the field exists, hence unwrap().
                 let field_type = get_field_type(table_name, identifier).unwrap();
-                add(args, Some(identifier.clone()), field_type.clone(), expr);
+                add(args, Some(identifier.clone()), argument_type(field_type), expr);
             }
         },
+        FilterValue::Correlation(_) => (), // NOTE: The outer field is not an argument.
+        FilterValue::Exists(ref query) => {
+            add_query_arguments(cx, query, args);
+        },
+        FilterValue::In(ref membership) => {
+            add_membership_arguments(cx, membership, args, table_name);
+        },
         FilterValue::MethodCall(MethodCall { ref arguments, ref method_name, ref object_name, .. }) => {
             for (index, arg) in arguments.iter().enumerate() {
                 add_with_method(args, method_name, object_name, index, arg.clone(), table_name);
@@ -150,33 +202,91 @@ fn add_filter_value_arguments(filter_value: &FilterValue, args: &mut Args, table
     }
 }
 
-/// Extract the Rust `Expression`s from the `Query`.
-pub fn arguments(cx: &mut ExtCtxt, query: Query) -> Args {
-    let mut arguments = vec![];
-    let table_name = query_table(&query);
+/// Create arguments from the `membership` and add them to `args`.
+fn add_membership_arguments(cx: &mut ExtCtxt, &Membership { ref operand, ref values }: &Membership, args: &mut Args, table_name: &str) {
+    // The arguments of the operand come first because it is before the values in the query.
+    add_filter_value_arguments(cx, operand, args, table_name, None);
+    let field_name =
+        if let FilterValue::Identifier(ref identifier) = **operand {
+            Some(identifier.clone())
+        }
+        else {
+            None
+        };
+    let operand_type = get_field_type_by_filter_value(table_name, operand).into_owned();
+    match *values {
+        MembershipValues::Array(ref expression) => {
+            add(args, field_name, Type::Array(box operand_type), expression.clone());
+        },
+        MembershipValues::InclusiveRange(ref start, ref end) | MembershipValues::Range(ref start, ref end) => {
+            add(args, field_name.clone(), operand_type.clone(), start.clone());
+            add(args, field_name, operand_type, end.clone());
+        },
+        MembershipValues::List(ref expressions) => {
+            for expression in expressions {
+                add(args, field_name.clone(), operand_type.clone(), expression.clone());
+            }
+        },
+        MembershipValues::Query(ref query) => {
+            add_query_arguments(cx, query, args);
+        },
+    }
+}
 
-    match query {
-        Query::Aggregate { aggregate_filter, filter, .. } => {
-            add_filter_arguments(filter, &mut arguments, &table_name);
-            add_aggregate_filter_arguments(aggregate_filter, &mut arguments, &table_name);
+/// Create arguments from the `query` and add them to `arguments`.
+fn add_query_arguments(cx: &mut ExtCtxt, query: &Query, arguments: &mut Args) {
+    let table_name = query_table(query);
+
+    match *query {
+        Query::Aggregate { ref aggregate_filter, ref aggregates, ref filter, ref windows, .. } => {
+            for aggregate in aggregates {
+                add_aggregate_arguments(aggregate, arguments);
+            }
+            // NOTE: The window functions are selected after the aggregates.
+            for window in windows {
+                add_aggregate_arguments(&window.function, arguments);
+            }
+            add_filter_arguments(cx, filter, arguments, &table_name);
+            add_aggregate_filter_arguments(cx, aggregate_filter, arguments, &table_name);
+        },
+        Query::Count { ref filter, .. } => {
+            add_filter_arguments(cx, filter, arguments, &table_name);
         },
-        Query::CreateTable { .. } => (), // No arguments.
-        Query::Delete { filter, .. } => {
-            add_filter_arguments(filter, &mut arguments, &table_name);
+        Query::CreateJunctionTables { .. } | Query::CreateTable { .. } => (), // No arguments.
+        Query::Delete { ref filter, .. } => {
+            add_filter_arguments(cx, filter, arguments, &table_name);
         },
         Query::Drop { .. } => (), // No arguments.
-        Query::Insert { assignments, .. } => {
-            add_assignments(assignments, &mut arguments, &table_name);
+        Query::Exists { ref filter, .. } => {
+            add_filter_arguments(cx, filter, arguments, &table_name);
+        },
+        Query::Insert { ref assignments, ref on_conflict, .. } => {
+            add_assignments(assignments, arguments, &table_name);
+            if let Some(ref on_conflict) = *on_conflict {
+                if let ConflictAction::Update(ref assignments) = on_conflict.action {
+                    add_assignments(assignments, arguments, &table_name);
+                }
+            }
+        },
+        Query::InsertMany { ref assignments, .. } => {
+            // NOTE: These arguments are evaluated for each row.
+            add_assignments(assignments, arguments, &table_name);
         },
-        Query::Select { filter, limit, ..} => {
-            add_filter_arguments(filter, &mut arguments, &table_name);
-            add_limit_arguments(cx, limit, &mut arguments);
+        Query::Select { ref filter, ref limit, ..} => {
+            add_filter_arguments(cx, filter, arguments, &table_name);
+            add_limit_arguments(cx, limit, arguments);
         },
-        Query::Update { assignments, filter, .. } => {
-            add_assignments(assignments, &mut arguments, &table_name);
-            add_filter_arguments(filter, &mut arguments, &table_name);
+        Query::Update { ref assignments, ref filter, .. } => {
+            add_assignments(assignments, arguments, &table_name);
+            add_filter_arguments(cx, filter, arguments, &table_name);
         },
     }
+}
 
+/// Extract the Rust `Expression`s from the `Query`.
+/// The arguments of the subqueries are extracted where they appear in the query.
+pub fn arguments(cx: &mut ExtCtxt, query: Query) -> Args {
+    let mut arguments = vec![];
+    add_query_arguments(cx, &query, &mut arguments);
     arguments
 }
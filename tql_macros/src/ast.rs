@@ -23,7 +23,7 @@ use syntax::ast::Expr;
 use syntax::codemap::Spanned;
 use syntax::ptr::P;
 
-use state::tables_singleton;
+use state::{SqlTable, get_field_type, get_primary_key_field_by_table_name, get_unique_field_lists, tables_singleton};
 use types::Type;
 
 pub type Expression = P<Expr>;
@@ -85,9 +85,16 @@ macro_rules! filters {
 /// `Aggregate` for une in SQL Aggregate `Query`.
 #[derive(Clone, Debug, Default)]
 pub struct Aggregate {
-    pub field: Identifier,
+    /// The arguments following the field (for instance, the separator of `string_agg()`).
+    pub arguments: Vec<Expression>,
+    /// The name of the Rust aggregate function.
     pub function: Identifier,
+    /// The aggregated field or arithmetic expression (`None` for a function without arguments,
+    /// like `count()`).
+    pub operand: Option<AggregateOperand>,
     pub result_name: Identifier,
+    pub result_type: Type,
+    pub template: String,
 }
 
 /// `AggregateFilter` for SQL `Query` (HAVING clause).
@@ -99,16 +106,45 @@ filter_expression!(AggregateFilterExpression, AggregateFilter, AggregateFilters,
 /// A `Filters` is used to combine `AggregateFilterExpression`s with a `LogicalOperator`.
 filters!(AggregateFilters, AggregateFilterExpression);
 
+/// The value to aggregate: a field or an arithmetic operation on fields and literals.
+#[derive(Clone, Debug)]
+pub enum AggregateOperand {
+    Binary(Box<AggregateOperand>, ArithmeticOperator, Box<AggregateOperand>),
+    Field(Identifier),
+    Literal(Expression),
+}
+
+/// An arithmetic operator for use in an `AggregateOperand` or an `AssignmentValue`.
+#[derive(Clone, Copy, Debug)]
+pub enum ArithmeticOperator {
+    Add,
+    Divide,
+    Modulo,
+    Mul,
+    Sub,
+}
+
 /// `Assignment` for use in SQL Insert and Update `Query`.
 #[derive(Debug)]
 pub struct Assignment {
     pub identifier: Identifier,
     pub operator: Spanned<AssignementOperator>,
-    pub value: Expression,
+    pub value: AssignmentValue,
+}
+
+/// The value assigned to a field.
+#[derive(Debug)]
+pub enum AssignmentValue {
+    /// The field value of the row proposed for insertion (comes from `excluded::field` in an
+    /// `on_conflict().update()`).
+    Excluded(Identifier),
+    Expression(Expression),
+    /// An arithmetic operation using an `Excluded` value (for instance, `excluded::field + 1`).
+    Operation(Box<AssignmentValue>, ArithmeticOperator, Box<AssignmentValue>),
 }
 
 /// `AssignementOperator` for use in SQL Insert and Update `Query`.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AssignementOperator {
     Add,
     Divide,
@@ -134,6 +170,49 @@ impl Display for AssignementOperator {
     }
 }
 
+/// The action to do when an insertion conflicts with an existing row.
+#[derive(Debug)]
+pub enum ConflictAction {
+    /// Comes from `on_conflict().ignore()`.
+    Ignore,
+    /// Comes from `on_conflict().update()`.
+    Update(Vec<Assignment>),
+}
+
+/// A foreign key referencing a compound primary key (created with the table).
+#[derive(Debug)]
+pub struct CompoundForeignKey {
+    pub fields: FieldList,
+    pub related_fields: FieldList,
+    pub related_table: Identifier,
+}
+
+/// A comparison between a value of a subquery and a field of the outer query (comes from
+/// `field == outer.field`) or between two fields in a `#[check]` attribute.
+#[derive(Debug)]
+pub struct Correlation {
+    pub operand: Box<FilterValue>,
+    pub operator: RelationalOperator,
+    /// The qualified name of the field of the outer query (or the name of the compared field).
+    pub outer_field: Identifier,
+}
+
+/// An SQL DISTINCT clause.
+#[derive(Debug)]
+pub enum Distinct {
+    /// Comes from `distinct()`.
+    Distinct,
+    /// Comes from `distinct_on(fields)`.
+    DistinctOn(FieldList),
+    NoDistinct,
+}
+
+impl Default for Distinct {
+    fn default() -> Distinct {
+        Distinct::NoDistinct
+    }
+}
+
 /// `Filter` for SQL `Query` (WHERE clause).
 filter!(Filter, FilterValue);
 
@@ -143,20 +222,48 @@ filter_expression!(FilterExpression, Filter, Filters, FilterExpression, FilterVa
 /// A `Filters` is used to combine `FilterExpression`s with a `LogicalOperator`.
 filters!(Filters, FilterExpression);
 
-/// Either an identifier or a method call.
+/// Either an identifier, a membership test, a method call or a subquery test.
 #[derive(Debug)]
 pub enum FilterValue {
+    /// A comparison with a field of the outer query (only in a subquery).
+    Correlation(Correlation),
+    /// Comes from `exists(Table.filter(…))`.
+    Exists(Box<Query>),
     Identifier(Identifier),
+    In(Membership),
     MethodCall(MethodCall),
 }
 
 /// A `Join` with another `joined_table` via a specific `joined_field`.
 #[derive(Clone, Debug, Default)]
 pub struct Join {
+    /// The name used to refer to the `joined_table` in the query.
+    /// It differs from the table name when this table is already in the query.
+    pub alias: Identifier,
     pub base_field: Identifier,
     pub base_table: Identifier,
     pub joined_field: Identifier,
     pub joined_table: Identifier,
+    /// The other (base field, joined field) pairs of a compound foreign key.
+    pub key_fields: Vec<(Identifier, Identifier)>,
+    /// The foreign key fields leading from the main table to the `joined_table`.
+    pub path: Vec<Identifier>,
+    pub typ: JoinType,
+}
+
+/// The type of a `Join`.
+#[derive(Clone, Debug)]
+pub enum JoinType {
+    /// Comes from `inner_join()` or from `join()` on a foreign key.
+    Inner,
+    /// Comes from `left_join()` or from `join()` on a nullable foreign key.
+    Left,
+}
+
+impl Default for JoinType {
+    fn default() -> JoinType {
+        JoinType::Inner
+    }
 }
 
 /// An SQL LIMIT clause.
@@ -182,6 +289,23 @@ impl Default for Limit {
     }
 }
 
+/// A junction table storing the relation of a `ManyToMany` `field`.
+/// Its columns are named after the `base_table` and the `field`.
+#[derive(Clone, Debug, Default)]
+pub struct JunctionTable {
+    /// The table containing the `ManyToMany` `field`.
+    pub base_table: Identifier,
+    pub field: Identifier,
+    pub related_table: Identifier,
+}
+
+impl JunctionTable {
+    /// Get the name of the junction table.
+    pub fn name(&self) -> Identifier {
+        self.base_table.clone() + "_" + &self.field
+    }
+}
+
 /// `LogicalOperator` to combine `Filter`s.
 #[derive(Debug, PartialEq)]
 pub enum LogicalOperator {
@@ -190,6 +314,38 @@ pub enum LogicalOperator {
     Or,
 }
 
+/// A `ManyToMany` field whose related objects are fetched with a second query (comes from
+/// `join(field)` or `prefetch(field)`).
+#[derive(Clone, Debug, Default)]
+pub struct ManyToManyJoin {
+    /// The fields of the related table needed to create its struct.
+    pub fields: FieldList,
+    pub junction_table: JunctionTable,
+}
+
+/// A membership test created by the `in()` method.
+#[derive(Debug)]
+pub struct Membership {
+    /// The value searched in `values`.
+    pub operand: Box<FilterValue>,
+    pub values: MembershipValues,
+}
+
+/// The values in which the `Membership` operand is searched.
+#[derive(Debug)]
+pub enum MembershipValues {
+    /// A `Vec` or slice expression sent as an array parameter.
+    Array(Expression),
+    /// Not created from a query. It is converted from a `Range` having a literal end.
+    InclusiveRange(Expression, Expression),
+    /// [3, 4, 5]
+    List(Vec<Expression>),
+    /// Comes from `Table.filter(…).values(field)`: the subquery selects one column.
+    Query(Box<Query>),
+    /// start..end
+    Range(Expression, Expression),
+}
+
 /// A method call is an abstraction of SQL function call.
 #[derive(Debug)]
 pub struct MethodCall {
@@ -199,6 +355,14 @@ pub struct MethodCall {
     pub template: String,
 }
 
+/// An SQL ON CONFLICT clause.
+#[derive(Debug)]
+pub struct OnConflict {
+    pub action: ConflictAction,
+    /// The fields of the unique key that can conflict.
+    pub fields: Vec<Identifier>,
+}
+
 /// An SQL ORDER BY clause.
 #[derive(Debug)]
 pub enum Order {
@@ -208,6 +372,16 @@ pub enum Order {
     Descending(Identifier),
 }
 
+/// The related objects fetched with a second query (comes from `prefetch(Table.field)`).
+#[derive(Clone, Debug, Default)]
+pub struct Prefetch {
+    /// The foreign key `field` of the related `table` referring to the main table.
+    pub field: Identifier,
+    /// The fields of the related `table` needed to create its struct.
+    pub fields: FieldList,
+    pub table: Identifier,
+}
+
 /// `RelationalOperator` to be used in a `Filter`.
 #[derive(Debug)]
 pub enum RelationalOperator {
@@ -219,43 +393,92 @@ pub enum RelationalOperator {
     GreaterThanEqual,
 }
 
+/// An SQL RETURNING clause.
+#[derive(Debug)]
+pub enum Returning {
+    /// Comes from `returning(field1, field2)`.
+    Fields(FieldList),
+    /// Comes from `returning()`: contains the fields needed to create the table struct.
+    Table(FieldList),
+}
+
 /// An SQL `Query`.
 #[derive(Debug)]
 pub enum Query {
     Aggregate {
         aggregates: Vec<Aggregate>,
         aggregate_filter: AggregateFilterExpression,
+        /// `Distinct::Distinct` when the values are selected with the distinct() method.
+        distinct: Distinct,
+        /// The fields of the annotated objects (empty when the annotate() method is not called).
+        fields: FieldList,
         filter: FilterExpression,
         groups: Groups,
         joins: Vec<Join>,
         table: Identifier,
+        windows: Vec<Window>,
+    },
+    Count {
+        filter: FilterExpression,
+        joins: Vec<Join>,
+        table: Identifier,
+    },
+    /// The junction tables are created separately because they reference the related tables.
+    CreateJunctionTables {
+        junction_tables: Vec<JunctionTable>,
+        table: Identifier,
     },
     CreateTable {
+        /// The SQL expressions of the CHECK constraints.
+        checks: Vec<String>,
         fields: Vec<TypedField>,
+        foreign_keys: Vec<CompoundForeignKey>,
+        indexes: Vec<TableIndex>,
+        /// The fields of the compound primary key (empty for a `PrimaryKey` field).
+        primary_key: FieldList,
         table: Identifier,
+        unique_keys: Vec<FieldList>,
     },
     Delete {
         filter: FilterExpression,
+        returning: Option<Returning>,
         table: Identifier,
     },
     Drop {
+        junction_tables: Vec<JunctionTable>,
+        table: Identifier,
+    },
+    Exists {
+        filter: FilterExpression,
+        joins: Vec<Join>,
         table: Identifier,
     },
     Insert {
         assignments: Vec<Assignment>,
+        on_conflict: Option<OnConflict>,
+        table: Identifier,
+    },
+    InsertMany {
+        /// The assignments to do for each `row`.
+        assignments: Vec<Assignment>,
+        rows: Expression,
         table: Identifier,
     },
     Select {
+        distinct: Distinct,
         fields: FieldList,
         filter: FilterExpression,
         joins: Vec<Join>,
         limit: Limit,
+        many_to_many: Vec<ManyToManyJoin>,
         order: Vec<Order>,
+        prefetch: Option<Prefetch>,
         table: Identifier,
     },
     Update {
         assignments: Vec<Assignment>,
         filter: FilterExpression,
+        returning: Option<Returning>,
         table: Identifier,
     },
 }
@@ -264,10 +487,38 @@ pub enum Query {
 pub enum QueryType {
     AggregateMulti,
     AggregateOne,
+    /// The objects are returned with their aggregates.
+    Annotate,
+    /// The number of rows is returned.
+    Count,
     Exec,
-    InsertOne,
+    /// Many statements executed without arguments.
+    ExecBatch,
+    /// Whether there is a row is returned.
+    Exists,
+    /// The type of the returned primary keys.
+    InsertMany(Expression, Type),
+    /// The type of the returned primary key.
+    InsertOne(Type),
+    InsertOptional(Type),
+    /// The types of the returned fields.
+    ReturningFields(Vec<Type>),
+    ReturningTable,
     SelectMulti,
     SelectOne,
+    /// The selected objects are returned with their related objects.
+    SelectPrefetchMulti(Prefetch),
+    SelectPrefetchOne(Prefetch),
+}
+
+/// An index of a `table` created with the table.
+#[derive(Debug)]
+pub struct TableIndex {
+    pub fields: FieldList,
+    pub method: Option<Identifier>,
+    pub name: Identifier,
+    pub table: Identifier,
+    pub unique: bool,
 }
 
 /// An SQL field with its type.
@@ -277,15 +528,67 @@ pub struct TypedField {
     pub typ: String,
 }
 
+/// A window function call for use in the SQL Aggregate `Query`.
+/// Comes from `function().over(partition(fields).sort(fields))`.
+#[derive(Debug, Default)]
+pub struct Window {
+    /// The function called over the window (a window function or an aggregate function).
+    pub function: Aggregate,
+    /// The ORDER BY clause of the window.
+    pub order: Vec<Order>,
+    /// The PARTITION BY clause of the window.
+    pub partition: Groups,
+    /// The name of the table containing the fields of the function, the partition and the order.
+    pub table: Identifier,
+}
+
+/// Add the fields compared for equality in the `filter` to `fields`.
+/// Only the filters combined with the AND operator are considered.
+fn add_equal_fields(filter: &FilterExpression, fields: &mut Vec<Identifier>) {
+    match *filter {
+        FilterExpression::Filter(Filter { operand1: FilterValue::Identifier(ref identifier), operator: RelationalOperator::Equal, .. }) =>
+            fields.push(identifier.clone()),
+        FilterExpression::Filters(Filters { ref operand1, operator: LogicalOperator::And, ref operand2 }) => {
+            add_equal_fields(operand1, fields);
+            add_equal_fields(operand2, fields);
+        },
+        FilterExpression::ParenFilter(ref filter) => add_equal_fields(filter, fields),
+        _ => (), // NOTE: Other filters can match many rows.
+    }
+}
+
+/// Get the type of the primary key returned by an insert in the table `table_name`.
+fn inserted_primary_key_type(table_name: &str) -> Type {
+    let typ = get_primary_key_field_by_table_name(table_name)
+        .and_then(|primary_key| get_field_type(table_name, &primary_key));
+    match typ {
+        Some(&Type::Serial) | None => Type::I32,
+        Some(typ) => typ.clone(),
+    }
+}
+
+/// Check if the `filter` compares all the fields of a unique key of the `table` for equality,
+/// i.e. if it matches at most one row.
+pub fn is_unique_filter(filter: &FilterExpression, table: &SqlTable) -> bool {
+    let mut fields = vec![];
+    add_equal_fields(filter, &mut fields);
+    get_unique_field_lists(table).iter()
+        .any(|unique_key| unique_key.iter().all(|field| fields.contains(field)))
+}
+
 /// Get the query table name.
 pub fn query_table(query: &Query) -> Identifier {
     let table_name =
         match *query {
             Query::Aggregate { ref table, .. } => table,
+            Query::Count { ref table, .. } => table,
+            Query::CreateJunctionTables { ref table, .. } => table,
             Query::CreateTable { ref table, .. } => table,
             Query::Delete { ref table, .. } => table,
             Query::Drop { ref table, .. } => table,
+            Query::Exists { ref table, .. } => table,
             Query::Insert { ref table, .. } => table,
+            Query::InsertMany { ref table, .. } => table,
             Query::Select { ref table, .. } => table,
             Query::Update { ref table, .. } => table,
         };
@@ -295,6 +598,7 @@ pub fn query_table(query: &Query) -> Identifier {
 /// Get the query type.
 pub fn query_type(query: &Query) -> QueryType {
     match *query {
+        Query::Aggregate { ref fields, .. } if !fields.is_empty() => QueryType::Annotate,
         Query::Aggregate { ref groups, .. } => {
             if !groups.is_empty() {
                 QueryType::AggregateMulti
@@ -303,24 +607,57 @@ pub fn query_type(query: &Query) -> QueryType {
                 QueryType::AggregateOne
             }
         },
-        Query::Insert { .. } => QueryType::InsertOne,
-        Query::Select { ref filter, ref limit, ref table, .. } => {
+        Query::Count { .. } => QueryType::Count,
+        Query::Exists { .. } => QueryType::Exists,
+        // NOTE: There is no inserted id to return when the table has no PrimaryKey field (for
+        // instance, a junction table with a compound primary key).
+        Query::Insert { ref table, .. } if get_primary_key_field_by_table_name(table).is_none() => QueryType::Exec,
+        Query::Insert { on_conflict: Some(OnConflict { action: ConflictAction::Ignore, .. }), ref table, .. } =>
+            QueryType::InsertOptional(inserted_primary_key_type(table)),
+        Query::Insert { ref table, .. } => QueryType::InsertOne(inserted_primary_key_type(table)),
+        Query::InsertMany { ref rows, ref table, .. } => QueryType::InsertMany(rows.clone(), inserted_primary_key_type(table)),
+        Query::Select { ref filter, ref limit, ref prefetch, ref table, .. } => {
             let mut typ = QueryType::SelectMulti;
+            let tables = tables_singleton();
+            // NOTE: At this stage (code generation), the table and the field exist, hence unwrap().
+            let table = tables.get(table).unwrap();
             if let FilterExpression::Filter(ref filter) = *filter {
-                let tables = tables_singleton();
-                // NOTE: At this stage (code generation), the table and the field exist, hence unwrap().
-                let table = tables.get(table).unwrap();
                 if let FilterValue::Identifier(ref identifier) = filter.operand1 {
-                    if table.fields.get(identifier).unwrap().node == Type::Serial {
+                    if table.fields.get(identifier).unwrap().node == Type::Serial && table.primary_key.is_empty() {
                         typ = QueryType::SelectOne;
                     }
                 }
             }
+            if is_unique_filter(filter, table) {
+                typ = QueryType::SelectOne;
+            }
             if let Limit::Index(_) = *limit {
                 typ = QueryType::SelectOne;
             }
-            typ
+            match *prefetch {
+                Some(ref prefetch) =>
+                    match typ {
+                        QueryType::SelectOne => QueryType::SelectPrefetchOne(prefetch.clone()),
+                        _ => QueryType::SelectPrefetchMulti(prefetch.clone()),
+                    },
+                None => typ,
+            }
+        },
+        Query::Delete { returning: Some(ref returning), ref table, .. } | Query::Update { returning: Some(ref returning), ref table, .. } => {
+            match *returning {
+                Returning::Fields(ref fields) => {
+                    // NOTE: At this stage (code generation), the fields exist, hence unwrap().
+                    let types = fields.iter()
+                        .map(|field| get_field_type(table, field).unwrap().clone())
+                        .collect();
+                    QueryType::ReturningFields(types)
+                },
+                Returning::Table(_) => QueryType::ReturningTable,
+            }
         },
-        Query::CreateTable { .. } | Query::Delete { .. } | Query::Drop { .. } | Query::Update { .. } => QueryType::Exec,
+        Query::CreateTable { ref indexes, .. } if indexes.is_empty() => QueryType::Exec,
+        // NOTE: The indexes are created in the same batch as the table.
+        Query::CreateJunctionTables { .. } | Query::CreateTable { .. } => QueryType::ExecBatch,
+        Query::Delete { .. } | Query::Drop { .. } | Query::Update { .. } => QueryType::Exec,
     }
 }
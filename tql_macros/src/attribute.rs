@@ -15,17 +15,84 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
-//! A conversion function for the #[SqlTable] attribute.
+//! Conversion functions for the #[SqlTable] attribute and the attributes of the table struct.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Write;
 
-use syntax::ast::{AngleBracketedParameters, AngleBracketedParameterData, StructField, StructFieldKind, Ty};
+use syntax::ast::{AngleBracketedParameters, AngleBracketedParameterData, Attribute, Lit, MetaItem, StructField, StructFieldKind, Ty};
+use syntax::ast::Lit_::LitStr;
+use syntax::ast::MetaItem_::{MetaList, MetaNameValue, MetaWord};
+use syntax::ptr::P;
 use syntax::ast::Ty_::TyPath;
-use syntax::codemap::Spanned;
+use syntax::attr::mark_used;
+use syntax::codemap::{Span, Spanned};
 
-use state::SqlFields;
-use types::Type;
+use analyzer::{check_default_type, propose_similar_name};
+use ast::FieldList;
+use error::{SqlError, SqlResult, res};
+use state::{SqlFields, SqlIndex};
+use types::{Type, get_related_table_name};
+
+/// Add the options of an `#[index]` attribute to the `index`.
+fn add_index_options(items: &[P<MetaItem>], index: &mut SqlIndex, table_name: &str, sql_fields: &SqlFields, errors: &mut Vec<SqlError>) {
+    for item in items {
+        match item.node {
+            MetaList(ref option, ref fields) if *option == "fields" => {
+                for field in fields {
+                    if let Some(field) = meta_item_to_field(field, table_name, sql_fields, errors) {
+                        index.fields.push(field);
+                    }
+                }
+            },
+            MetaNameValue(ref option, ref value) if *option == "method" => {
+                if let Some(method) = lit_to_string(value, errors) {
+                    let methods = get_index_methods();
+                    if !methods.contains(&method) {
+                        errors.push(SqlError::new(
+                            &format!("unknown index method `{}`", method),
+                            value.span,
+                        ));
+                        propose_similar_name(&method, methods.iter(), value.span, errors);
+                    }
+                    index.method = Some(method);
+                }
+            },
+            MetaNameValue(ref option, ref value) if *option == "name" => {
+                if let Some(name) = lit_to_string(value, errors) {
+                    index.name = name;
+                }
+            },
+            MetaWord(ref option) if *option == "unique" => index.unique = true,
+            _ => {
+                errors.push(SqlError::new(
+                    "unknown option in the #[index] attribute",
+                    item.span,
+                ));
+                errors.push(SqlError::new_help(
+                    "expected one of `fields(...)`, `method = \"...\"`, `name = \"...\"` or `unique`",
+                    item.span,
+                ));
+            },
+        }
+    }
+}
+
+/// Check that a unique `index` uses the btree method, the only one supporting unique indexes.
+fn check_unique_index_method(index: &SqlIndex, position: Span, errors: &mut Vec<SqlError>) {
+    if let Some(ref method) = index.method {
+        if index.unique && *method != "btree" {
+            errors.push(SqlError::new(
+                &format!("the index method `{}` does not support unique indexes", method),
+                position,
+            ));
+            errors.push(SqlError::new_help(
+                "use the btree method for a unique index",
+                position,
+            ));
+        }
+    }
+}
 
 /// Convert a type from the Rust AST to the SQL `Type`.
 #[allow(cmp_owned)]
@@ -71,3 +138,427 @@ pub fn fields_vec_to_hashmap(fields: &[StructField]) -> SqlFields {
     }
     sql_fields
 }
+
+/// Get the expressions of the `#[check = "expression"]` attributes of the table struct with the
+/// position of their attribute.
+pub fn get_checks(attributes: &[Attribute]) -> SqlResult<Vec<Spanned<String>>> {
+    let mut errors = vec![];
+    let mut checks = vec![];
+
+    for attribute in attributes.iter().filter(|attribute| is_attribute(attribute, "check")) {
+        mark_used(attribute);
+        // NOTE: The expression is in a string because an attribute cannot contain an expression.
+        if let MetaNameValue(_, ref value) = attribute.node.value.node {
+            if let Some(expression) = lit_to_string(value, &mut errors) {
+                checks.push(Spanned {
+                    node: expression,
+                    span: attribute.span,
+                });
+            }
+        }
+        else {
+            errors.push(SqlError::new(
+                "expected an expression in the #[check] attribute",
+                attribute.span,
+            ));
+            errors.push(SqlError::new_help(
+                "use #[check = \"expression\"]",
+                attribute.span,
+            ));
+        }
+    }
+
+    res(checks, errors)
+}
+
+/// Get the default values of the struct `fields` from their `#[default = "value"]` or
+/// `#[default(value)]` attributes.
+/// A default value is an SQL expression (for instance, `now()`).
+/// Only a string is accepted in `#[default = "value"]` and only an identifier (for instance,
+/// `false`) is accepted in `#[default(value)]`: a number must be written as a string.
+/// The literal default values are type-checked against the type of the field in `sql_fields`.
+pub fn get_defaults(fields: &[StructField], sql_fields: &SqlFields) -> SqlResult<HashMap<String, String>> {
+    let mut errors = vec![];
+    let mut defaults = HashMap::new();
+
+    for field in fields {
+        if let StructFieldKind::NamedField(ident, _) = field.node.kind {
+            for attribute in field.node.attrs.iter().filter(|attribute| is_attribute(attribute, "default")) {
+                mark_used(attribute);
+                let value =
+                    match attribute.node.value.node {
+                        MetaList(_, ref items) if items.len() == 1 => {
+                            if let MetaWord(ref value) = items[0].node {
+                                Some(value.to_string())
+                            }
+                            else {
+                                None
+                            }
+                        },
+                        MetaNameValue(_, ref value) => {
+                            match lit_to_string(value, &mut errors) {
+                                Some(value) => Some(value),
+                                // NOTE: An error is emitted in the lit_to_string() function.
+                                None => continue,
+                            }
+                        },
+                        _ => None,
+                    };
+                match value {
+                    Some(ref value) if !value.is_empty() => {
+                        if defaults.contains_key(&ident.to_string()) {
+                            errors.push(SqlError::new(
+                                "only one #[default] attribute is allowed",
+                                attribute.span,
+                            ));
+                        }
+                        if let Some(field_type) = sql_fields.get(&ident.to_string()) {
+                            check_default_type(value, &field_type.node, attribute.span, &mut errors);
+                        }
+                        defaults.insert(ident.to_string(), value.clone());
+                    },
+                    _ => {
+                        errors.push(SqlError::new(
+                            "expected a default value in the #[default] attribute",
+                            attribute.span,
+                        ));
+                        errors.push(SqlError::new_help(
+                            "use #[default = \"value\"] or #[default(value)]",
+                            attribute.span,
+                        ));
+                    },
+                }
+            }
+        }
+    }
+
+    res(defaults, errors)
+}
+
+/// Get the fields of the foreign keys from the `#[foreign_key(field1, field2)]` attributes of the
+/// struct `fields`.
+/// These fields are listed in the order of the compound primary key referenced by the foreign key,
+/// the foreign key field itself referencing the last field of this key.
+pub fn get_foreign_keys(fields: &[StructField], table_name: &str, sql_fields: &SqlFields) -> SqlResult<HashMap<String, FieldList>> {
+    let mut errors = vec![];
+    let mut foreign_keys = HashMap::new();
+
+    for field in fields {
+        if let StructFieldKind::NamedField(ident, _) = field.node.kind {
+            let field_name = ident.to_string();
+            for attribute in field.node.attrs.iter().filter(|attribute| is_attribute(attribute, "foreign_key")) {
+                mark_used(attribute);
+                if sql_fields.get(&field_name).and_then(|typ| get_related_table_name(&typ.node)).is_none() {
+                    errors.push(SqlError::new(
+                        &format!("cannot use the #[foreign_key] attribute on the field `{}` which is not a ForeignKey", field_name),
+                        attribute.span,
+                    ));
+                    continue;
+                }
+                if foreign_keys.contains_key(&field_name) {
+                    errors.push(SqlError::new(
+                        "only one #[foreign_key] attribute is allowed",
+                        attribute.span,
+                    ));
+                    continue;
+                }
+                let mut key = vec![];
+                if let MetaList(_, ref items) = attribute.node.value.node {
+                    for item in items {
+                        match meta_item_to_field(item, table_name, sql_fields, &mut errors) {
+                            Some(field) => key.push(field),
+                            None => (), // NOTE: An error is emitted in the meta_item_to_field() function.
+                        }
+                    }
+                }
+                if key.len() < 2 {
+                    errors.push(SqlError::new(
+                        "expected at least two fields in the #[foreign_key] attribute",
+                        attribute.span,
+                    ));
+                    errors.push(SqlError::new_help(
+                        &format!("use #[foreign_key(field1, {})]", field_name),
+                        attribute.span,
+                    ));
+                }
+                else if key.last() != Some(&field_name) {
+                    errors.push(SqlError::new(
+                        &format!("the last field of the #[foreign_key] attribute must be `{}`", field_name),
+                        attribute.span,
+                    ));
+                    let mut fields: Vec<_> = key.iter()
+                        .filter(|field| **field != field_name)
+                        .cloned()
+                        .collect();
+                    fields.push(field_name.clone());
+                    errors.push(SqlError::new_help(
+                        &format!("use #[foreign_key({})]", fields.join(", ")),
+                        attribute.span,
+                    ));
+                }
+                foreign_keys.insert(field_name.clone(), key);
+            }
+        }
+    }
+
+    res(foreign_keys, errors)
+}
+
+/// Get the supported index methods.
+fn get_index_methods() -> Vec<String> {
+    vec![
+        "brin".to_owned(),
+        "btree".to_owned(),
+        "gin".to_owned(),
+        "gist".to_owned(),
+        "hash".to_owned(),
+        "spgist".to_owned(),
+    ]
+}
+
+/// Get the indexes from the `#[index]` attributes of the struct `fields` and from the
+/// `#[index(fields(field1, field2))]` attributes of the table struct.
+pub fn get_indexes(attributes: &[Attribute], fields: &[StructField], table_name: &str, sql_fields: &SqlFields) -> SqlResult<Vec<SqlIndex>> {
+    let mut errors = vec![];
+    let mut indexes = vec![];
+
+    for field in fields {
+        if let StructFieldKind::NamedField(ident, _) = field.node.kind {
+            for attribute in field.node.attrs.iter().filter(|attribute| is_attribute(attribute, "index")) {
+                mark_used(attribute);
+                let mut index = SqlIndex::default();
+                if let MetaList(_, ref items) = attribute.node.value.node {
+                    add_index_options(items, &mut index, table_name, sql_fields, &mut errors);
+                }
+                if !index.fields.is_empty() {
+                    errors.push(SqlError::new(
+                        "the #[index] attribute of a field does not take any field",
+                        attribute.span,
+                    ));
+                }
+                check_unique_index_method(&index, attribute.span, &mut errors);
+                index.fields = vec![ident.to_string()];
+                indexes.push(index);
+            }
+        }
+    }
+
+    for attribute in attributes.iter().filter(|attribute| is_attribute(attribute, "index")) {
+        mark_used(attribute);
+        let mut index = SqlIndex::default();
+        if let MetaList(_, ref items) = attribute.node.value.node {
+            add_index_options(items, &mut index, table_name, sql_fields, &mut errors);
+        }
+        check_unique_index_method(&index, attribute.span, &mut errors);
+        if index.fields.is_empty() {
+            errors.push(SqlError::new(
+                "expected at least one field in the #[index] attribute",
+                attribute.span,
+            ));
+            errors.push(SqlError::new_help(
+                "specify the fields with #[index(fields(field1, field2))]",
+                attribute.span,
+            ));
+        }
+        else {
+            indexes.push(index);
+        }
+    }
+
+    for index in &mut indexes {
+        if index.name.is_empty() {
+            index.name = format!("{}_{}_idx", table_name, index.fields.join("_"));
+        }
+    }
+
+    res(indexes, errors)
+}
+
+/// Get the fields of the primary key from the `#[primary_key]` attribute of a struct field or from
+/// the `#[primary_key(field1, field2)]` attribute of the table struct (compound primary key).
+pub fn get_primary_key(attributes: &[Attribute], fields: &[StructField], table_name: &str, sql_fields: &SqlFields) -> SqlResult<Vec<String>> {
+    let mut errors = vec![];
+    let mut primary_key = vec![];
+
+    for field in fields {
+        if let StructFieldKind::NamedField(ident, _) = field.node.kind {
+            for attribute in field.node.attrs.iter().filter(|attribute| is_attribute(attribute, "primary_key")) {
+                mark_used(attribute);
+                if let MetaList(_, _) = attribute.node.value.node {
+                    errors.push(SqlError::new(
+                        "the #[primary_key] attribute of a field does not take any field",
+                        attribute.span,
+                    ));
+                }
+                if !primary_key.is_empty() {
+                    errors.push(SqlError::new(
+                        "only one primary key is allowed",
+                        attribute.span,
+                    ));
+                    errors.push(SqlError::new_help(
+                        "use the #[primary_key(field1, field2)] attribute for a compound primary key",
+                        attribute.span,
+                    ));
+                    continue;
+                }
+                let field_name = ident.to_string();
+                if let Some(typ) = sql_fields.get(&field_name) {
+                    match typ.node {
+                        Type::I32 | Type::I64 | Type::Serial | Type::String | Type::Uuid => (),
+                        ref typ => {
+                            errors.push(SqlError::new(
+                                &format!("unsupported primary key type `{}`", typ),
+                                field.node.ty.span,
+                            ));
+                            errors.push(SqlError::new_help(
+                                "use i32, i64, String or Uuid",
+                                field.node.ty.span,
+                            ));
+                        },
+                    }
+                }
+                primary_key.push(field_name);
+            }
+        }
+    }
+
+    for (index, attribute) in attributes.iter().filter(|attribute| is_attribute(attribute, "primary_key")).enumerate() {
+        mark_used(attribute);
+        if index > 0 {
+            errors.push(SqlError::new(
+                "only one #[primary_key] attribute is allowed",
+                attribute.span,
+            ));
+            continue;
+        }
+        if !primary_key.is_empty() {
+            errors.push(SqlError::new(
+                "only one primary key is allowed",
+                attribute.span,
+            ));
+            continue;
+        }
+        if let MetaList(_, ref items) = attribute.node.value.node {
+            for item in items {
+                if let Some(field) = meta_item_to_field(item, table_name, sql_fields, &mut errors) {
+                    primary_key.push(field);
+                }
+            }
+        }
+        if primary_key.len() < 2 {
+            errors.push(SqlError::new(
+                "expected at least two fields in the #[primary_key] attribute",
+                attribute.span,
+            ));
+            errors.push(SqlError::new_help(
+                "use the #[primary_key] attribute on the field for a primary key with a single field",
+                attribute.span,
+            ));
+        }
+    }
+
+    res(primary_key, errors)
+}
+
+/// Get the unique keys from the `#[unique]` attributes of the struct `fields` and from the
+/// `#[unique(field1, field2)]` attributes of the table struct.
+pub fn get_unique_keys(attributes: &[Attribute], fields: &[StructField], table_name: &str, sql_fields: &SqlFields) -> SqlResult<Vec<FieldList>> {
+    let mut errors = vec![];
+    let mut unique_keys = vec![];
+
+    for field in fields {
+        if let StructFieldKind::NamedField(ident, _) = field.node.kind {
+            let field_name = ident.to_string();
+            for attribute in field.node.attrs.iter().filter(|attribute| is_attribute(attribute, "unique")) {
+                mark_used(attribute);
+                if let MetaList(_, _) = attribute.node.value.node {
+                    errors.push(SqlError::new(
+                        "the #[unique] attribute of a field does not take any field",
+                        attribute.span,
+                    ));
+                }
+                if let Some(&Spanned { node: Type::ManyToMany(_), .. }) = sql_fields.get(&field_name) {
+                    // NOTE: A many-to-many field is stored in a junction table.
+                    errors.push(SqlError::new(
+                        &format!("cannot use the #[unique] attribute on the many-to-many field `{}`", field_name),
+                        attribute.span,
+                    ));
+                }
+                else {
+                    unique_keys.push(vec![field_name.clone()]);
+                }
+            }
+        }
+    }
+
+    for attribute in attributes.iter().filter(|attribute| is_attribute(attribute, "unique")) {
+        mark_used(attribute);
+        let mut key = vec![];
+        if let MetaList(_, ref items) = attribute.node.value.node {
+            for item in items {
+                match meta_item_to_field(item, table_name, sql_fields, &mut errors) {
+                    Some(field) => key.push(field),
+                    None => (), // NOTE: An error is emitted in the meta_item_to_field() function.
+                }
+            }
+        }
+        if key.is_empty() {
+            errors.push(SqlError::new(
+                "expected at least one field in the #[unique] attribute",
+                attribute.span,
+            ));
+        }
+        else {
+            unique_keys.push(key);
+        }
+    }
+
+    res(unique_keys, errors)
+}
+
+/// Check if the `attribute` is named `name`.
+fn is_attribute(attribute: &Attribute, name: &str) -> bool {
+    match attribute.node.value.node {
+        MetaList(ref attribute_name, _) | MetaNameValue(ref attribute_name, _) | MetaWord(ref attribute_name) =>
+            *attribute_name == name,
+    }
+}
+
+/// Convert a string literal to a `String`.
+fn lit_to_string(literal: &Lit, errors: &mut Vec<SqlError>) -> Option<String> {
+    if let LitStr(ref string, _) = literal.node {
+        Some(string.to_string())
+    }
+    else {
+        errors.push(SqlError::new(
+            "expected a string literal",
+            literal.span,
+        ));
+        None
+    }
+}
+
+/// Convert a `MetaItem` to the name of a field of the table struct.
+fn meta_item_to_field(item: &MetaItem, table_name: &str, sql_fields: &SqlFields, errors: &mut Vec<SqlError>) -> Option<String> {
+    if let MetaWord(ref field) = item.node {
+        let field = field.to_string();
+        if sql_fields.contains_key(&field) {
+            return Some(field);
+        }
+        errors.push(SqlError::new(
+            &format!("attempted access of field `{field}` on type `{table}`, but no field with that name was found",
+                field = field,
+                table = table_name
+            ),
+            item.span,
+        ));
+        propose_similar_name(&field, sql_fields.keys(), item.span, errors);
+    }
+    else {
+        errors.push(SqlError::new(
+            "Expected field name", // TODO: improve this message.
+            item.span,
+        ));
+    }
+    None
+}
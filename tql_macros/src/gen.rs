@@ -21,20 +21,29 @@ use std::str::from_utf8;
 
 use syntax::ast::Expr_::ExprLit;
 use syntax::ast::Lit_::{LitBool, LitByte, LitByteStr, LitChar, LitFloat, LitFloatUnsuffixed, LitInt, LitStr};
+use syntax::ast::LitIntType::{SignedIntLit, UnsuffixedIntLit};
+use syntax::ast::Sign;
 
-use ast::{Aggregate, AggregateFilter, AggregateFilterExpression, AggregateFilters, Assignment, AssignementOperator, Expression, FieldList, Filter, Filters, FilterExpression, FilterValue, Identifier, Join, Limit, LogicalOperator, MethodCall, Order, RelationalOperator, Query, TypedField};
+use ast::{Aggregate, AggregateFilter, AggregateFilterExpression, AggregateFilters, AggregateOperand, ArithmeticOperator, Assignment, AssignementOperator, AssignmentValue, CompoundForeignKey, ConflictAction, Correlation, Distinct, Expression, FieldList, Filter, Filters, FilterExpression, FilterValue, Identifier, Join, JoinType, JunctionTable, Limit, LogicalOperator, ManyToManyJoin, Membership, MembershipValues, MethodCall, OnConflict, Order, Prefetch, RelationalOperator, Query, Returning, TableIndex, TypedField, Window};
 use ast::Limit::{EndRange, Index, LimitOffset, NoLimit, Range, StartRange};
 use sql::escape;
-use state::get_primary_key_field_by_table_name;
+use state::{get_primary_key_field_by_table_name, get_primary_key_type_by_table_name};
+use types::type_to_sql_name;
 
 /// Macro used to generate a ToSql implementation for a filter (for use in WHERE or HAVING).
 macro_rules! filter_to_sql {
     ( $name:ident ) => {
         impl ToSql for $name {
             fn to_sql(&self) -> String {
-                self.operand1.to_sql() + " " +
+                self.to_qualified_sql(None)
+            }
+        }
+
+        impl ToQualifiedSql for $name {
+            fn to_qualified_sql(&self, table: Option<&str>) -> String {
+                self.operand1.to_qualified_sql(table) + " " +
                     &self.operator.to_sql() + " " +
-                    &self.operand2.to_sql()
+                    &self.operand2.to_qualified_sql(table)
             }
         }
     };
@@ -45,18 +54,24 @@ macro_rules! filter_expression_to_sql {
     ( $name:ident ) => {
         impl ToSql for $name {
             fn to_sql(&self) -> String {
+                self.to_qualified_sql(None)
+            }
+        }
+
+        impl ToQualifiedSql for $name {
+            fn to_qualified_sql(&self, table: Option<&str>) -> String {
                 match *self {
-                    $name::Filter(ref filter) => filter.to_sql(),
-                    $name::Filters(ref filters) => filters.to_sql(),
+                    $name::Filter(ref filter) => filter.to_qualified_sql(table),
+                    $name::Filters(ref filters) => filters.to_qualified_sql(table),
                     $name::NegFilter(ref filter) =>
                         "NOT ".to_owned() +
-                        &filter.to_sql(),
+                        &filter.to_qualified_sql(table),
                     $name::NoFilters => "".to_owned(),
                     $name::ParenFilter(ref filter) =>
                         "(".to_owned() +
-                        &filter.to_sql() +
+                        &filter.to_qualified_sql(table) +
                         ")",
-                    $name::FilterValue(ref filter_value) => filter_value.node.to_sql(),
+                    $name::FilterValue(ref filter_value) => filter_value.node.to_qualified_sql(table),
                 }
             }
         }
@@ -79,15 +94,50 @@ pub trait ToSql {
     fn to_sql(&self) -> String;
 }
 
+/// A trait for converting a value to SQL with its fields prefixed by the `table` name, if any
+/// (to avoid ambiguities when tables are joined).
+pub trait ToQualifiedSql {
+    fn to_qualified_sql(&self, table: Option<&str>) -> String;
+}
+
 impl ToSql for Aggregate {
     fn to_sql(&self) -> String {
-        // TODO: do not use CAST when this is in a HAVING clause.
-        // TODO: do not hard-code the type.
-        "CAST(".to_owned() + &self.function.to_sql() + "(" + &self.field.to_sql() + ") AS INT)"
+        self.to_qualified_sql(None)
     }
 }
 
-slice_to_sql!(Aggregate, ", ");
+impl ToQualifiedSql for Aggregate {
+    fn to_qualified_sql(&self, table: Option<&str>) -> String {
+        // In the template, $0 represents the operand and $1, $2, ... the other arguments.
+        let mut sql = self.template.clone();
+        if let Some(ref operand) = self.operand {
+            sql = sql.replace("$0", &operand.to_qualified_sql(table));
+        }
+        let mut index = 1;
+        for argument in &self.arguments {
+            sql = sql.replace(&format!("${}", index), &argument.to_sql());
+            index += 1;
+        }
+        sql
+    }
+}
+
+impl ToSql for AggregateOperand {
+    fn to_sql(&self) -> String {
+        self.to_qualified_sql(None)
+    }
+}
+
+impl ToQualifiedSql for AggregateOperand {
+    fn to_qualified_sql(&self, table: Option<&str>) -> String {
+        match *self {
+            AggregateOperand::Binary(ref operand1, ref operator, ref operand2) =>
+                binary_operand_to_sql(operand1, table) + " " + &operator.to_sql() + " " + &binary_operand_to_sql(operand2, table),
+            AggregateOperand::Field(ref identifier) => qualified_field(identifier, table),
+            AggregateOperand::Literal(ref literal) => literal.to_sql(),
+        }
+    }
+}
 
 filter_to_sql!(AggregateFilter);
 
@@ -95,24 +145,37 @@ filter_expression_to_sql!(AggregateFilterExpression);
 
 filter_to_sql!(AggregateFilters);
 
+impl ToSql for ArithmeticOperator {
+    fn to_sql(&self) -> String {
+        match *self {
+            ArithmeticOperator::Add => "+",
+            ArithmeticOperator::Divide => "/",
+            ArithmeticOperator::Modulo => "%",
+            ArithmeticOperator::Mul => "*",
+            ArithmeticOperator::Sub => "-",
+        }.to_owned()
+    }
+}
+
 impl ToSql for Assignment {
     fn to_sql(&self) -> String {
-        if let AssignementOperator::Equal = self.operator.node {
-            self.identifier.to_sql() +
-                &self.operator.node.to_sql() +
-                &self.value.to_sql()
-        }
-        else {
-            let identifier = self.identifier.to_sql();
-            identifier.clone() +
-                &self.operator.node.to_sql().replace("{}", &identifier) +
-                &self.value.to_sql()
-        }
+        assignment_to_sql(self, &self.identifier)
     }
 }
 
 slice_to_sql!(Assignment, ", ");
 
+impl ToSql for AssignmentValue {
+    fn to_sql(&self) -> String {
+        match *self {
+            AssignmentValue::Excluded(ref identifier) => "EXCLUDED.".to_owned() + identifier,
+            AssignmentValue::Expression(ref expression) => expression.to_sql(),
+            AssignmentValue::Operation(ref operand1, ref operator, ref operand2) =>
+                assignment_operand_to_sql(operand1) + " " + &operator.to_sql() + " " + &assignment_operand_to_sql(operand2),
+        }
+    }
+}
+
 impl ToSql for AssignementOperator {
     fn to_sql(&self) -> String {
         match *self {
@@ -126,6 +189,16 @@ impl ToSql for AssignementOperator {
     }
 }
 
+impl ToSql for CompoundForeignKey {
+    fn to_sql(&self) -> String {
+        format!("FOREIGN KEY ({fields}) REFERENCES {related_table}({related_fields})",
+            fields = self.fields.join(", "),
+            related_table = self.related_table,
+            related_fields = self.related_fields.join(", ")
+        )
+    }
+}
+
 /// Convert a literal expression to its SQL representation.
 /// A non-literal is converted to ? for use with query parameters.
 impl ToSql for Expression {
@@ -149,6 +222,8 @@ impl ToSql for Expression {
                         "'",
                     LitFloat(ref float, _) => float.to_string(),
                     LitFloatUnsuffixed(ref float) => float.to_string(),
+                    LitInt(number, SignedIntLit(_, Sign::Minus)) | LitInt(number, UnsuffixedIntLit(Sign::Minus)) =>
+                        "-".to_owned() + &number.to_string(),
                     LitInt(number, _) => number.to_string(),
                     LitStr(ref string, _) =>
                         "'".to_owned() +
@@ -163,6 +238,23 @@ impl ToSql for Expression {
 
 slice_to_sql!(Expression, ", ");
 
+impl ToQualifiedSql for Expression {
+    fn to_qualified_sql(&self, _table: Option<&str>) -> String {
+        // NOTE: An expression is either a literal or a parameter, hence it has no field to prefix.
+        self.to_sql()
+    }
+}
+
+impl ToSql for Distinct {
+    fn to_sql(&self) -> String {
+        match *self {
+            Distinct::Distinct => "DISTINCT ".to_owned(),
+            Distinct::DistinctOn(ref fields) => "DISTINCT ON (".to_owned() + &fields.to_sql() + ") ",
+            Distinct::NoDistinct => "".to_owned(),
+        }
+    }
+}
+
 impl ToSql for FieldList {
     fn to_sql(&self) -> String {
         self.join(", ")
@@ -177,32 +269,54 @@ filter_to_sql!(Filters);
 
 impl ToSql for FilterValue {
     fn to_sql(&self) -> String {
+        self.to_qualified_sql(None)
+    }
+}
+
+impl ToQualifiedSql for FilterValue {
+    fn to_qualified_sql(&self, table: Option<&str>) -> String {
         match *self {
-            FilterValue::Identifier(ref identifier) => identifier.to_sql(),
-            FilterValue::MethodCall(MethodCall { ref arguments, ref object_name, ref template, ..  }) => {
-                // In the template, $0 represents the object identifier and $1, $2, ... the
-                // arguments.
-                let mut sql = template.replace("$0", object_name);
-                let mut index = 1;
-                for argument in arguments {
-                    sql = sql.replace(&format!("${}", index), &argument.to_sql());
-                    index += 1;
-                }
-                sql
-            },
+            FilterValue::Correlation(Correlation { ref operand, ref operator, ref outer_field }) =>
+                operand.to_qualified_sql(table) + " " + &operator.to_sql() + " " + outer_field,
+            // NOTE: The subquery has its own table.
+            FilterValue::Exists(ref query) => "EXISTS(".to_owned() + &query.to_sql() + ")",
+            FilterValue::Identifier(ref identifier) => qualified_field(identifier, table),
+            FilterValue::In(Membership { ref operand, ref values }) => operand.to_qualified_sql(table) + &values.to_sql(),
+            FilterValue::MethodCall(ref method_call) => method_call_to_sql(method_call, &qualified_field(&method_call.object_name, table)),
         }
     }
 }
 
 impl ToSql for Join {
     fn to_sql(&self) -> String {
-        " INNER JOIN ".to_owned() + &self.joined_table +
+        let alias =
+            if self.alias != self.joined_table {
+                " AS ".to_owned() + &self.alias
+            }
+            else {
+                "".to_owned()
+            };
+        let key_fields: String = self.key_fields.iter()
+            .map(|&(ref base_field, ref joined_field)|
+                 " AND ".to_owned() + &self.base_table + "." + base_field + " = " + &self.alias + "." + joined_field
+            )
+            .collect();
+        " ".to_owned() + &self.typ.to_sql() + " " + &self.joined_table + &alias +
             " ON " + &self.base_table + "." + &self.base_field + " = "
-            + &self.joined_table + "." + &self.joined_field
+            + &self.alias + "." + &self.joined_field + &key_fields
     }
 }
 
-slice_to_sql!(Join, " ");
+slice_to_sql!(Join, "");
+
+impl ToSql for JoinType {
+    fn to_sql(&self) -> String {
+        match *self {
+            JoinType::Inner => "INNER JOIN",
+            JoinType::Left => "LEFT JOIN",
+        }.to_owned()
+    }
+}
 
 impl ToSql for Identifier {
     fn to_sql(&self) -> String {
@@ -210,6 +324,29 @@ impl ToSql for Identifier {
     }
 }
 
+impl ToSql for JunctionTable {
+    fn to_sql(&self) -> String {
+        // NOTE: At this stage (code generation), the tables have a primary key, hence unwrap().
+        let base_primary_key = get_primary_key_field_by_table_name(&self.base_table).unwrap();
+        let related_primary_key = get_primary_key_field_by_table_name(&self.related_table).unwrap();
+        // NOTE: The columns have the types of the primary keys they reference.
+        let base_type = get_primary_key_type_by_table_name(&self.base_table).unwrap();
+        let related_type = get_primary_key_type_by_table_name(&self.related_table).unwrap();
+        format!("CREATE TABLE {name} ({base_table} {base_type} NOT NULL REFERENCES {base_table}({base_primary_key}) ON DELETE CASCADE, \
+                {field} {related_type} NOT NULL REFERENCES {related_table}({related_primary_key}) ON DELETE CASCADE, \
+                PRIMARY KEY ({base_table}, {field}))",
+                name = self.name(),
+                base_table = self.base_table,
+                base_type = type_to_sql_name(&base_type),
+                base_primary_key = base_primary_key,
+                field = self.field,
+                related_table = self.related_table,
+                related_type = type_to_sql_name(&related_type),
+                related_primary_key = related_primary_key
+               )
+    }
+}
+
 impl ToSql for Limit {
     fn to_sql(&self) -> String {
         match *self {
@@ -239,29 +376,102 @@ impl ToSql for LogicalOperator {
     }
 }
 
+impl ToSql for ManyToManyJoin {
+    fn to_sql(&self) -> String {
+        let junction_table = &self.junction_table;
+        // NOTE: At this stage (code generation), the related table has a primary key, hence unwrap().
+        let related_primary_key = get_primary_key_field_by_table_name(&junction_table.related_table).unwrap();
+        // NOTE: The primary key of the base table is fetched last to associate the related objects
+        // with the selected objects.
+        format!("SELECT {fields}, {junction_table}.{base_table} FROM {related_table} \
+                INNER JOIN {junction_table} ON {junction_table}.{field} = {related_table}.{related_primary_key} \
+                WHERE {junction_table}.{base_table} = ANY($1)",
+                fields = self.fields.to_sql(),
+                junction_table = junction_table.name(),
+                base_table = junction_table.base_table,
+                related_table = junction_table.related_table,
+                field = junction_table.field,
+                related_primary_key = related_primary_key
+               )
+    }
+}
+
+impl ToSql for MembershipValues {
+    fn to_sql(&self) -> String {
+        match *self {
+            MembershipValues::Array(ref expression) =>
+                " = ANY(".to_owned() + &expression.to_sql() + ")",
+            MembershipValues::InclusiveRange(ref start, ref end) =>
+                " BETWEEN ".to_owned() + &start.to_sql() +
+                " AND " + &end.to_sql(),
+            MembershipValues::List(ref expressions) =>
+                " IN (".to_owned() + &expressions.to_sql() + ")",
+            MembershipValues::Query(ref query) =>
+                " IN (".to_owned() + &query.to_sql() + ")",
+            MembershipValues::Range(ref start, ref end) =>
+                " BETWEEN ".to_owned() + &start.to_sql() +
+                " AND " + &end.to_sql() + " - 1",
+        }
+    }
+}
+
 impl ToSql for Order {
     fn to_sql(&self) -> String {
+        self.to_qualified_sql(None)
+    }
+}
+
+impl ToQualifiedSql for Order {
+    fn to_qualified_sql(&self, table: Option<&str>) -> String {
         match *self {
-            Order::Ascending(ref field) => field.to_sql(),
-            Order::Descending(ref field) => field.to_sql() + " DESC",
+            Order::Ascending(ref field) => qualified_field(field, table),
+            Order::Descending(ref field) => qualified_field(field, table) + " DESC",
         }
     }
 }
 
 slice_to_sql!(Order, ", ");
 
+impl ToQualifiedSql for [Order] {
+    fn to_qualified_sql(&self, table: Option<&str>) -> String {
+        self.iter()
+            .map(|order| order.to_qualified_sql(table))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl ToSql for Prefetch {
+    fn to_sql(&self) -> String {
+        // NOTE: The foreign key is fetched last to associate the related objects with the
+        // selected objects.
+        format!("SELECT {fields}, {table}.{field} FROM {table} WHERE {table}.{field} = ANY($1)",
+                fields = self.fields.to_sql(),
+                table = self.table,
+                field = self.field
+               )
+    }
+}
+
 /// Convert a whole `Query` to SQL.
 impl ToSql for Query {
+    /// Convert the query to SQL with `?` placeholders.
+    /// The placeholders are numbered by `replace_placeholder()` once the whole query (including
+    /// its subqueries) is converted.
     fn to_sql(&self) -> String {
         match *self {
-            Query::Aggregate{ref aggregates, ref aggregate_filter, ref filter, ref groups, ref joins, ref table} => {
+            Query::Aggregate{ref aggregates, ref aggregate_filter, ref distinct, ref fields, ref filter, ref groups, ref joins, ref table, ref windows} => {
                 let where_clause = filter_to_where_clause(filter);
+                // NOTE: The distinct values are not grouped.
                 let group_clause =
-                    if !groups.is_empty() {
-                        " GROUP BY "
+                    if let Distinct::Distinct = *distinct {
+                        "".to_owned()
+                    }
+                    else if !groups.is_empty() {
+                        " GROUP BY ".to_owned() + &groups.to_sql()
                     }
                     else {
-                        ""
+                        "".to_owned()
                     };
                 let having_clause =
                     if let AggregateFilterExpression::NoFilters = *aggregate_filter {
@@ -270,51 +480,129 @@ impl ToSql for Query {
                     else {
                         " HAVING "
                     };
-                replace_placeholder(format!("SELECT {aggregates} FROM {table_name}{joins}{where_clause}{filter}{group_clause}{groups}{having_clause}{aggregate_filter}",
-                                            aggregates = aggregates.to_sql(),
+                // NOTE: The grouped fields are selected to know which group each row belongs to.
+                // The fields of the annotated objects are selected instead when annotate() is
+                // called (these objects are grouped by primary key).
+                let grouped_fields =
+                    if !fields.is_empty() {
+                        fields
+                    }
+                    else {
+                        groups
+                    };
+                let selected: Vec<_> = vec![grouped_fields.to_sql(), aggregates_to_sql(aggregates), windows_to_sql(windows)].into_iter()
+                    .filter(|selected| !selected.is_empty())
+                    .collect();
+                format!("SELECT {distinct}{selected} FROM {table_name}{joins}{where_clause}{filter}{group_clause}{having_clause}{aggregate_filter}",
+                                            distinct = distinct.to_sql(),
+                                            selected = selected.join(", "),
                                             table_name = table,
                                             joins = joins.to_sql(),
                                             where_clause = where_clause,
-                                            filter = filter.to_sql(),
+                                            filter = filter.to_qualified_sql(joined_table(table, joins)),
                                             group_clause = group_clause,
-                                            groups = groups.to_sql(),
                                             having_clause = having_clause,
                                             aggregate_filter = aggregate_filter.to_sql())
-                                    )
             },
-            Query::CreateTable { ref fields, ref table } => {
-                format!("CREATE TABLE {table} ({fields})",
+            Query::Count { ref filter, ref joins, ref table } => {
+                let where_clause = filter_to_where_clause(filter);
+                format!("SELECT COUNT(*) FROM {table}{joins}{where_clause}{filter}",
+                                            table = table,
+                                            joins = joins.to_sql(),
+                                            where_clause = where_clause,
+                                            filter = filter.to_qualified_sql(joined_table(table, joins))
+                                           )
+            },
+            Query::CreateJunctionTables { ref junction_tables, .. } => {
+                junction_tables.iter()
+                    .map(ToSql::to_sql)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            },
+            Query::CreateTable { ref checks, ref fields, ref foreign_keys, ref indexes, ref primary_key, ref table, ref unique_keys } => {
+                let primary_key_constraint =
+                    if !primary_key.is_empty() {
+                        ", PRIMARY KEY (".to_owned() + &primary_key.join(", ") + ")"
+                    }
+                    else {
+                        "".to_owned()
+                    };
+                let foreign_key_constraints: String = foreign_keys.iter()
+                    .map(|foreign_key| ", ".to_owned() + &foreign_key.to_sql())
+                    .collect();
+                let indexes: String = indexes.iter()
+                    .map(|index| "; ".to_owned() + &index.to_sql())
+                    .collect();
+                let unique_constraints: String = unique_keys.iter()
+                    .map(|unique_key| ", UNIQUE (".to_owned() + &unique_key.join(", ") + ")")
+                    .collect();
+                let check_constraints: String = checks.iter()
+                    .map(|check| ", CHECK (".to_owned() + check + ")")
+                    .collect();
+                format!("CREATE TABLE {table} ({fields}{primary_key_constraint}{foreign_key_constraints}{unique_constraints}{check_constraints}){indexes}",
                     table = table,
-                    fields = fields.to_sql()
+                    fields = fields.to_sql(),
+                    primary_key_constraint = primary_key_constraint,
+                    foreign_key_constraints = foreign_key_constraints,
+                    unique_constraints = unique_constraints,
+                    check_constraints = check_constraints,
+                    indexes = indexes
                 )
             },
-            Query::Delete { ref filter, ref table } => {
+            Query::Delete { ref filter, ref returning, ref table } => {
                 let where_clause = filter_to_where_clause(filter);
-                replace_placeholder(format!("DELETE FROM {table}{where_clause}{filter}",
+                format!("DELETE FROM {table}{where_clause}{filter}{returning}",
                                             table = table,
                                             where_clause = where_clause,
-                                            filter = filter.to_sql()
+                                            filter = filter.to_sql(),
+                                            returning = returning.as_ref().map_or("".to_owned(), ToSql::to_sql)
                                            )
-                                   )
             },
-            Query::Drop { ref table } => {
-                format!("DROP TABLE {table}", table = table)
+            Query::Drop { ref junction_tables, ref table } => {
+                let junction_tables: String = junction_tables.iter()
+                    .map(|junction_table| ", ".to_owned() + &junction_table.name())
+                    .collect();
+                format!("DROP TABLE {table}{junction_tables}", table = table, junction_tables = junction_tables)
+            },
+            Query::Exists { ref filter, ref joins, ref table } => {
+                let where_clause = filter_to_where_clause(filter);
+                format!("SELECT EXISTS(SELECT 1 FROM {table}{joins}{where_clause}{filter})",
+                                            table = table,
+                                            joins = joins.to_sql(),
+                                            where_clause = where_clause,
+                                            filter = filter.to_qualified_sql(joined_table(table, joins))
+                                           )
             },
-            Query::Insert { ref assignments, ref table } => {
+            Query::Insert { ref assignments, ref on_conflict, ref table } => {
                 let fields: Vec<_> = assignments.iter().map(|assign| assign.identifier.to_sql()).collect();
                 let values: Vec<_> = assignments.iter().map(|assign| assign.value.to_sql()).collect();
+                let conflict_clause = on_conflict.as_ref()
+                    .map_or("".to_owned(), |on_conflict| on_conflict_to_sql(on_conflict, table));
                 // Add the SQL code to get the inserted primary key.
                 // TODO: what to do when there is no primary key?
                 let return_value = get_primary_key_field_by_table_name(table)
                     .map_or("".to_owned(), |primary_key| " RETURNING ".to_owned() + &primary_key);
-                replace_placeholder(format!("INSERT INTO {table}({fields}) VALUES({values}){return_value}",
+                format!("INSERT INTO {table}({fields}) VALUES({values}){conflict_clause}{return_value}",
                         table = table,
                         fields = fields.to_sql(),
                         values = values.to_sql(),
+                        conflict_clause = conflict_clause,
+                        return_value = return_value
+                    )
+            },
+            Query::InsertMany { ref assignments, ref table, .. } => {
+                let fields: Vec<_> = assignments.iter().map(|assign| assign.identifier.to_sql()).collect();
+                let return_value = get_primary_key_field_by_table_name(table)
+                    .map_or("".to_owned(), |primary_key| " RETURNING ".to_owned() + &primary_key);
+                // NOTE: The {} placeholder is replaced by the rows in the generated code because
+                // the number of rows is only known at runtime.
+                format!("INSERT INTO {table}({fields}) VALUES {{}}{return_value}",
+                        table = table,
+                        fields = fields.to_sql(),
                         return_value = return_value
-                    ))
+                    )
             },
-            Query::Select{ref fields, ref filter, ref joins, ref limit, ref order, ref table} => {
+            Query::Select{ref distinct, ref fields, ref filter, ref joins, ref limit, ref order, ref table, ..} => {
                 let where_clause = filter_to_where_clause(filter);
                 let order_clause =
                     if !order.is_empty() {
@@ -323,27 +611,27 @@ impl ToSql for Query {
                     else {
                         ""
                     };
-                replace_placeholder(format!("SELECT {fields} FROM {table}{joins}{where_clause}{filter}{order_clause}{order}{limit}",
+                format!("SELECT {distinct}{fields} FROM {table}{joins}{where_clause}{filter}{order_clause}{order}{limit}",
+                                            distinct = distinct.to_sql(),
                                             fields = fields.to_sql(),
                                             table = table,
                                             joins = joins.to_sql(),
                                             where_clause = where_clause,
-                                            filter = filter.to_sql(),
+                                            filter = filter.to_qualified_sql(joined_table(table, joins)),
                                             order_clause = order_clause,
-                                            order = order.to_sql(),
+                                            order = order.to_qualified_sql(joined_table(table, joins)),
                                             limit = limit.to_sql()
                                            )
-                                   )
             },
-            Query::Update { ref assignments, ref filter, ref table } => {
+            Query::Update { ref assignments, ref filter, ref returning, ref table } => {
                 let where_clause = filter_to_where_clause(filter);
-                replace_placeholder(format!("UPDATE {table} SET {assignments}{where_clause}{filter}",
+                format!("UPDATE {table} SET {assignments}{where_clause}{filter}{returning}",
                                             table = table,
                                             assignments = assignments.to_sql(),
                                             where_clause = where_clause,
-                                            filter = filter.to_sql()
+                                            filter = filter.to_sql(),
+                                            returning = returning.as_ref().map_or("".to_owned(), ToSql::to_sql)
                                            )
-                                   )
             },
         }
     }
@@ -362,6 +650,34 @@ impl ToSql for RelationalOperator {
     }
 }
 
+impl ToSql for Returning {
+    fn to_sql(&self) -> String {
+        match *self {
+            Returning::Fields(ref fields) | Returning::Table(ref fields) => " RETURNING ".to_owned() + &fields.to_sql(),
+        }
+    }
+}
+
+impl ToSql for TableIndex {
+    fn to_sql(&self) -> String {
+        let unique =
+            if self.unique {
+                "UNIQUE "
+            }
+            else {
+                ""
+            };
+        let method = self.method.as_ref().map_or("".to_owned(), |method| " USING ".to_owned() + method);
+        format!("CREATE {unique}INDEX {name} ON {table}{method} ({fields})",
+            unique = unique,
+            name = self.name,
+            table = self.table,
+            method = method,
+            fields = self.fields.join(", ")
+        )
+    }
+}
+
 impl ToSql for TypedField {
     fn to_sql(&self) -> String {
         self.identifier.to_sql() + " " + &self.typ
@@ -370,6 +686,70 @@ impl ToSql for TypedField {
 
 slice_to_sql!(TypedField, ", ");
 
+impl ToSql for Window {
+    fn to_sql(&self) -> String {
+        // NOTE: The fields are prefixed with the table name because the table can be joined.
+        let mut clauses = vec![];
+        if !self.partition.is_empty() {
+            let partition: Vec<_> = self.partition.iter()
+                .map(|field| qualified_field(field, Some(&self.table[..])))
+                .collect();
+            clauses.push("PARTITION BY ".to_owned() + &partition.join(", "));
+        }
+        if !self.order.is_empty() {
+            clauses.push("ORDER BY ".to_owned() + &self.order.to_qualified_sql(Some(&self.table[..])));
+        }
+        self.function.to_qualified_sql(Some(&self.table[..])) + " OVER (" + &clauses.join(" ") + ")"
+    }
+}
+
+/// Convert the `aggregates` to the SQL select list.
+/// The results are casted to the SQL type matching the Rust field type of the aggregate struct
+/// (for instance, the average of integers is a NUMERIC that is casted to a DOUBLE PRECISION).
+fn aggregates_to_sql(aggregates: &[Aggregate]) -> String {
+    aggregates.iter()
+        .map(|aggregate| "CAST(".to_owned() + &aggregate.to_sql() + " AS " + &type_to_sql_name(&aggregate.result_type) + ")")
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Convert an operand of an arithmetic operation in an assignment to SQL.
+/// The operand is parenthesized when it is itself an arithmetic operation.
+fn assignment_operand_to_sql(operand: &AssignmentValue) -> String {
+    if let AssignmentValue::Operation(..) = *operand {
+        "(".to_owned() + &operand.to_sql() + ")"
+    }
+    else {
+        operand.to_sql()
+    }
+}
+
+/// Convert an `Assignment` to SQL using `operand` as the left operand of the operation (e.g. +=).
+fn assignment_to_sql(assignment: &Assignment, operand: &str) -> String {
+    if let AssignementOperator::Equal = assignment.operator.node {
+        assignment.identifier.to_sql() +
+            &assignment.operator.node.to_sql() +
+            &assignment.value.to_sql()
+    }
+    else {
+        assignment.identifier.to_sql() +
+            &assignment.operator.node.to_sql().replace("{}", operand) +
+            &assignment_operand_to_sql(&assignment.value)
+    }
+}
+
+/// Convert an operand of a binary operation to SQL, prefixing its fields with the `table` name, if
+/// any.
+/// The operand is parenthesized when it is itself a binary operation.
+fn binary_operand_to_sql(operand: &AggregateOperand, table: Option<&str>) -> String {
+    if let AggregateOperand::Binary(..) = *operand {
+        "(".to_owned() + &operand.to_qualified_sql(table) + ")"
+    }
+    else {
+        operand.to_qualified_sql(table)
+    }
+}
+
 /// Convert a `FilterExpression` to either " WHERE " or the empty string if there are no filters.
 fn filter_to_where_clause(filter: &FilterExpression) -> &str {
     match *filter {
@@ -378,9 +758,65 @@ fn filter_to_where_clause(filter: &FilterExpression) -> &str {
     }
 }
 
+/// Get the table name prefixing the fields of the query on the `table`.
+/// The fields are prefixed when there are `joins` because they would be ambiguous otherwise (for
+/// instance, when the table is joined with itself).
+fn joined_table<'a>(table: &'a str, joins: &[Join]) -> Option<&'a str> {
+    if joins.is_empty() {
+        None
+    }
+    else {
+        Some(table)
+    }
+}
+
+/// Convert a `MethodCall` to SQL using `object_name` as the object of the method.
+fn method_call_to_sql(&MethodCall { ref arguments, ref template, .. }: &MethodCall, object_name: &str) -> String {
+    // In the template, $0 represents the object identifier and $1, $2, ... the arguments.
+    let mut sql = template.replace("$0", object_name);
+    let mut index = 1;
+    for argument in arguments {
+        sql = sql.replace(&format!("${}", index), &argument.to_sql());
+        index += 1;
+    }
+    sql
+}
+
+/// Convert an `OnConflict` to an SQL ON CONFLICT clause.
+/// The fields of the existing row are qualified with the `table` name to avoid ambiguity with the
+/// EXCLUDED row.
+fn on_conflict_to_sql(&OnConflict { ref action, ref fields }: &OnConflict, table: &str) -> String {
+    let target =
+        if fields.is_empty() {
+            "".to_owned()
+        }
+        else {
+            " (".to_owned() + &fields.to_sql() + ")"
+        };
+    let action =
+        match *action {
+            ConflictAction::Ignore => "DO NOTHING".to_owned(),
+            ConflictAction::Update(ref assignments) => {
+                let assignments: Vec<_> = assignments.iter()
+                    .map(|assignment| assignment_to_sql(assignment, &(table.to_owned() + "." + &assignment.identifier)))
+                    .collect();
+                "DO UPDATE SET ".to_owned() + &assignments.join(", ")
+            },
+        };
+    " ON CONFLICT".to_owned() + &target + " " + &action
+}
+
+/// Prefix the `field` with the `table` name, if any.
+fn qualified_field(field: &str, table: Option<&str>) -> String {
+    match table {
+        Some(table) => table.to_owned() + "." + field,
+        None => field.to_owned(),
+    }
+}
+
 // TODO: find a better way to write the symbols ($1, $2, …) in the query.
 /// Replace the placeholders `{}` by $# by # where # is the index of the placeholder.
-fn replace_placeholder(string: String) -> String {
+pub fn replace_placeholder(string: String) -> String {
     let mut result = "".to_owned();
     let mut in_string = false;
     let mut skip_next = false;
@@ -407,3 +843,12 @@ fn replace_placeholder(string: String) -> String {
     }
     result
 }
+
+/// Convert the `windows` to the SQL select list.
+/// The results are casted like the aggregates.
+fn windows_to_sql(windows: &[Window]) -> String {
+    windows.iter()
+        .map(|window| "CAST(".to_owned() + &window.to_sql() + " AS " + &type_to_sql_name(&window.function.result_type) + ")")
+        .collect::<Vec<_>>()
+        .join(", ")
+}
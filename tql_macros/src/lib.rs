@@ -30,8 +30,6 @@
 // referenced.
 // TODO: show PrimaryKey instead of i32 in error message.
 // TODO: create a module codegen and put the Rust code generation in this new module.
-// TODO: create a module for the predefined aggregate functions (instead of creating them in the
-// methods module).
 // TODO: check if we can use macros as arguments. If not, use cx.expand_expr() on the result of
 // parse_expr().
 // TODO: check if parse_expr() parse the whole expression. If not, check this with
@@ -39,8 +37,6 @@
 // TODO: use syntax::print::pprust() to print types or expression in error message.
 // TODO: support the character and i8 field type.
 // TODO: use named parameters in the format!() macro when needed.
-// TODO: support field index (in table create).
-// TODO: support field with unique key.
 // TODO: support more operators in the optimizer.
 // TODO: use more the Default trait.
 // TODO: allow setting a field to None in an update().
@@ -59,8 +55,6 @@
 // FIXME: remove useless clone().
 // FIXME: use closures instead of internal functions.
 // FIXME: use "use self" instead of two lines.
-// TODO: implement the Default trait on table structures to be able to create a default object and
-// only assign the fields that were fetched by the query (for only() and defer()).
 // TODO: check byte strings (for instance: b"\u{a66e}").
 // TODO: create different types for String (VARCHAR, CHAR(n), TEXT, …).
 // TODO: make the error messages similar to Rust ones.
@@ -69,19 +63,12 @@
 // TODO: support more database management systems.
 // TODO: support methods on Nullable<Generic> and Nullable<i32> and other?
 // TODO: support slices (for istance: Table.filter(field1[3..6] == "te")).
-// TODO: add the method in() (for instance: Table.filter(field1.in([3, 4, 5]) ou Table.filter(field1.len().in(3..6)))).
-// TODO: in aggregates, allow operations:
-// Table.aggregate(avg(field2 / field1))
-// TODO: check argument types in aggregations.
-// TODO: in aggregates, allow selecting other fields (grouped fields only?).
-// TODO: add the annotate() method for object aggregates.
 // TODO: in aggregate filters, allow aggregate function calls.
 // TODO: make more similar filters and aggregate filters to avoid code duplicate.
 // TODO: optimize the Rust code generation to avoid having to prepare a query multiple times.
 // TODO: create a macro to choose a DBMS. Give an optional parameter to this macro to choose the
 // name of the macro to create (to allow using many DBMS at the same time).
 // TODO: use a 2-pass compilation to detect used fields and joins (perhaps using a lint plugin).
-// TODO: support compound primary keys.
 // TODO: remove allow attributes that were added because of clippy bugs.
 
 #[macro_use]
@@ -90,11 +77,12 @@ extern crate rustc_plugin;
 extern crate syntax;
 extern crate syntax_ext;
 
+use std::collections::HashMap;
 use std::error::Error;
 
 use rustc::lint::{EarlyLintPassObject, LateLintPassObject};
 use rustc_plugin::Registry;
-use syntax::ast::{AngleBracketedParameters, AngleBracketedParameterData, Block, Field, Ident, MetaItem, Path, PathSegment, StructField_, StructFieldKind, TokenTree, Ty, Ty_, VariantData, Visibility};
+use syntax::ast::{Attribute, Block, Field, Ident, MetaItem, StructField_, StructFieldKind, TokenTree, Ty, VariantData, Visibility};
 use syntax::ast::Expr_::ExprLit;
 use syntax::ast::Item_::ItemStruct;
 use syntax::ast::MetaItem_::MetaWord;
@@ -103,12 +91,15 @@ use syntax::ext::base::{Annotatable, DummyResult, ExtCtxt, MacEager, MacResult};
 use syntax::ext::base::Annotatable::Item;
 use syntax::ext::base::SyntaxExtension::MultiDecorator;
 use syntax::ext::build::AstBuilder;
+use syntax::feature_gate::AttributeType;
 use syntax_ext::deriving::debug::expand_deriving_debug;
+use syntax::parse::new_parser_from_source_str;
 use syntax::parse::token::{InternedString, Token, intern, str_to_ident};
 use syntax::ptr::P;
 
 #[macro_use]
 pub mod hashmap;
+pub mod aggregates;
 pub mod analyzer;
 pub mod arguments;
 pub mod ast;
@@ -125,18 +116,18 @@ pub mod string;
 pub mod type_analyzer;
 pub mod types;
 
-pub type SqlQueryWithArgs = (String, QueryType, Args, Vec<Join>, Vec<Aggregate>);
+pub type SqlQueryWithArgs = (String, QueryType, Args, Vec<Join>, Vec<Aggregate>, FieldList, Vec<ManyToManyJoin>);
 
-use analyzer::{analyze, analyze_types, has_joins};
+use analyzer::{analyze, analyze_types, check_to_sql};
 use arguments::{Args, arguments};
-use ast::{Aggregate, Expression, Join, Query, QueryType, query_type};
-use attribute::fields_vec_to_hashmap;
+use ast::{Aggregate, Expression, FieldList, Identifier, Join, JoinType, JunctionTable, ManyToManyJoin, Prefetch, Query, QueryType, Returning, query_type};
+use attribute::{fields_vec_to_hashmap, get_checks, get_defaults, get_foreign_keys, get_indexes, get_primary_key, get_unique_keys};
 use error::{ErrorType, SqlError, SqlResult};
-use gen::ToSql;
+use gen::{ToSql, replace_placeholder};
 use optimizer::optimize;
 use parser::parse;
 use plugin::NODE_ID;
-use state::{SqlArg, SqlArgs, SqlFields, SqlTable, SqlTables, get_primary_key_field_by_table_name, lint_singleton, tables_singleton};
+use state::{SqlArg, SqlArgs, SqlFields, SqlTable, SqlTables, get_primary_key_field, get_primary_key_fields, get_primary_key_type, get_primary_key_type_by_table_name, lint_singleton, tables_singleton};
 use type_analyzer::{SqlAttrError, SqlErrorLint};
 use types::Type;
 
@@ -172,13 +163,77 @@ fn add_field(fields: &mut Vec<Field>, expr: Expression, name: &str, position: Sp
     });
 }
 
+/// Add the methods adding and removing the related objects of the `ManyToMany` fields of the
+/// struct (for instance, `add_tags()` and `remove_tags()`).
+fn add_many_to_many_impl(cx: &mut ExtCtxt, sp: Span, push: &mut FnMut(Annotatable), table_name: &str) {
+    let table_ident = str_to_ident(table_name);
+    // NOTE: At this stage, the table was just added, hence unwrap().
+    let sql_tables = tables_singleton();
+    let table = sql_tables.get(table_name).unwrap();
+    for (field, typ) in &table.fields {
+        if let Type::ManyToMany(ref related_table_name) = typ.node {
+            // NOTE: The junction table columns reference a single primary key field.
+            if table.primary_key.len() > 1 {
+                cx.span_err(typ.span, "a table with a compound primary key cannot have a ManyToMany field");
+                continue;
+            }
+            let junction_table = JunctionTable {
+                base_table: table_name.to_owned(),
+                field: field.clone(),
+                related_table: related_table_name.clone(),
+            };
+            let add_query = format!("INSERT INTO {table}({base_table}, {field}) VALUES($1, $2) ON CONFLICT DO NOTHING",
+                                    table = junction_table.name(),
+                                    base_table = table_name,
+                                    field = field
+                                   );
+            let add_query = cx.expr_str(sp, InternedString::new_from_name(intern(&add_query)));
+            let remove_query = format!("DELETE FROM {table} WHERE {base_table} = $1 AND {field} = $2",
+                                       table = junction_table.name(),
+                                       base_table = table_name,
+                                       field = field
+                                      );
+            let remove_query = cx.expr_str(sp, InternedString::new_from_name(intern(&remove_query)));
+            let add_ident = str_to_ident(&format!("add_{}", field));
+            let remove_ident = str_to_ident(&format!("remove_{}", field));
+            let related_table_ident = str_to_ident(related_table_name);
+            let implementation = quote_item!(cx,
+                impl $table_ident {
+                    pub fn $add_ident(&self, connection: &postgres::Connection, related_object: &$related_table_ident) -> postgres::Result<u64> {
+                        connection.execute($add_query, &[self as &postgres::types::ToSql, related_object as &postgres::types::ToSql])
+                    }
+
+                    pub fn $remove_ident(&self, connection: &postgres::Connection, related_object: &$related_table_ident) -> postgres::Result<u64> {
+                        connection.execute($remove_query, &[self as &postgres::types::ToSql, related_object as &postgres::types::ToSql])
+                    }
+                }
+            );
+            push(Annotatable::Item(implementation.unwrap()));
+        }
+    }
+}
+
 /// Add the postgres::types::ToSql implementation on the struct.
 /// Its SQL representation is the same as the primary key SQL representation.
+/// A struct with a compound primary key has no single SQL representation: the fields of a foreign
+/// key referencing it are assigned separately.
 fn add_tosql_impl(cx: &mut ExtCtxt, push: &mut FnMut(Annotatable), table_name: &str) {
-    match get_primary_key_field_by_table_name(table_name) {
-        Some(primary_key_field) => {
+    let table = tables_singleton().get(table_name);
+    let primary_key_field = table.and_then(|table| {
+        let mut primary_key_fields = get_primary_key_fields(table);
+        if primary_key_fields.len() == 1 {
+            primary_key_fields.pop()
+        }
+        else {
+            None
+        }
+    });
+    let primary_key_type = table.and_then(|table| get_primary_key_type(table));
+    match (primary_key_field, primary_key_type) {
+        (Some(primary_key_field), Some(primary_key_type)) => {
             let table_ident = str_to_ident(table_name);
             let primary_key_ident = str_to_ident(&primary_key_field);
+            let primary_key_ty = type_to_ty(cx, &primary_key_type);
             let implementation = quote_item!(cx,
                 impl postgres::types::ToSql for $table_ident {
                     fn to_sql<W: std::io::Write + ?Sized>(&self, ty: &postgres::types::Type, out: &mut W, ctx: &postgres::types::SessionInfo) -> postgres::Result<postgres::types::IsNull> {
@@ -186,10 +241,7 @@ fn add_tosql_impl(cx: &mut ExtCtxt, push: &mut FnMut(Annotatable), table_name: &
                     }
 
                     fn accepts(ty: &postgres::types::Type) -> bool {
-                        match *ty {
-                            postgres::types::Type::Int4 => true,
-                            _ => false,
-                        }
+                        <$primary_key_ty as postgres::types::ToSql>::accepts(ty)
                     }
 
                     fn to_sql_checked(&self, ty: &postgres::types::Type, out: &mut ::std::io::Write, ctx: &postgres::types::SessionInfo) -> postgres::Result<postgres::types::IsNull> {
@@ -202,32 +254,30 @@ fn add_tosql_impl(cx: &mut ExtCtxt, push: &mut FnMut(Annotatable), table_name: &
             );
             push(Annotatable::Item(implementation.unwrap()));
         },
-        None => (), // NOTE: Do not add the implementation when there is no primary key.
+        _ => (), // NOTE: Do not add the implementation when there is no single primary key.
+    }
+}
+
+/// Convert a `Type` to the Rust type of a query argument.
+/// The argument can be an unsized type so that, for instance, a `&str` is accepted for a `String`.
+fn argument_type_to_ty(cx: &mut ExtCtxt, typ: &Type) -> P<Ty> {
+    match *typ {
+        Type::Array(ref typ) => {
+            let ty = type_to_ty(cx, typ);
+            quote_ty!(cx, [$ty])
+        },
+        Type::String => quote_ty!(cx, str),
+        _ => type_to_ty(cx, typ),
     }
 }
 
 /// Create an aggregate field definition to be added to a struct definition.
-fn create_aggregate_field_def(field_name: &str, sp: Span) -> Spanned<StructField_> {
+fn create_aggregate_field_def(field_name: &str, ty: P<Ty>, sp: Span) -> Spanned<StructField_> {
     Spanned {
         node: StructField_ {
             kind: StructFieldKind::NamedField(str_to_ident(field_name), Visibility::Inherited),
             id: NODE_ID,
-            ty: P(Ty {
-                id: NODE_ID,
-                node: Ty_::TyPath(None, Path {
-                    span: sp,
-                    global: false,
-                    segments: vec![PathSegment {
-                        identifier: str_to_ident("i32"), // TODO: choose the type from the field?
-                        parameters: AngleBracketedParameters(AngleBracketedParameterData {
-                            bindings: P::empty(),
-                            lifetimes: vec![],
-                            types: P::empty(),
-                        }),
-                    }],
-                }),
-                span: sp,
-            }),
+            ty: ty,
             attrs: vec![],
         },
         span: sp,
@@ -271,12 +321,67 @@ fn expand_sql_table(cx: &mut ExtCtxt, sp: Span, meta_item: &MetaItem, annotatabl
             if !sql_tables.contains_key(&table_name) {
                 let fields = get_struct_fields(cx, struct_def);
 
+                let defaults =
+                    match get_defaults(struct_def.fields(), &fields) {
+                        Ok(defaults) => defaults,
+                        Err(errors) => {
+                            span_errors(errors, cx);
+                            HashMap::new()
+                        },
+                    };
+                let foreign_keys =
+                    match get_foreign_keys(struct_def.fields(), &table_name, &fields) {
+                        Ok(foreign_keys) => foreign_keys,
+                        Err(errors) => {
+                            span_errors(errors, cx);
+                            HashMap::new()
+                        },
+                    };
+                let indexes =
+                    match get_indexes(&item.attrs, struct_def.fields(), &table_name, &fields) {
+                        Ok(indexes) => indexes,
+                        Err(errors) => {
+                            span_errors(errors, cx);
+                            vec![]
+                        },
+                    };
+                let primary_key =
+                    match get_primary_key(&item.attrs, struct_def.fields(), &table_name, &fields) {
+                        Ok(primary_key) => primary_key,
+                        Err(errors) => {
+                            span_errors(errors, cx);
+                            vec![]
+                        },
+                    };
+                let unique_keys =
+                    match get_unique_keys(&item.attrs, struct_def.fields(), &table_name, &fields) {
+                        Ok(unique_keys) => unique_keys,
+                        Err(errors) => {
+                            span_errors(errors, cx);
+                            vec![]
+                        },
+                    };
+
                 sql_tables.insert(table_name.clone(), SqlTable {
+                    checks: vec![],
+                    defaults: defaults,
                     fields: fields,
+                    foreign_keys: foreign_keys,
+                    indexes: indexes,
                     name: table_name.clone(),
                     position: item.span,
+                    primary_key: primary_key,
+                    unique_keys: unique_keys,
                 });
 
+                // NOTE: The checks are analyzed after the table is added because they are analyzed
+                // like filters on this table.
+                let checks = get_table_checks(cx, &item.attrs, &table_name);
+                if let Some(table) = sql_tables.get_mut(&table_name) {
+                    table.checks = checks;
+                }
+
+                add_many_to_many_impl(cx, sp, push, &table_name);
                 add_tosql_impl(cx, push, &table_name);
             }
             else {
@@ -300,7 +405,7 @@ fn expand_sql_table(cx: &mut ExtCtxt, sp: Span, meta_item: &MetaItem, annotatabl
 fn expand_to_sql(cx: &mut ExtCtxt, sp: Span, args: &[TokenTree]) -> Box<MacResult + 'static> {
     let sql_result = to_sql(cx, args);
     match sql_result {
-        Ok((sql, _, _, _, _)) => {
+        Ok((sql, _, _, _, _, _, _)) => {
             let string_literal = intern(&sql);
             MacEager::expr(cx.expr_str(sp, InternedString::new_from_name(string_literal)))
         },
@@ -312,13 +417,32 @@ fn expand_to_sql(cx: &mut ExtCtxt, sp: Span, args: &[TokenTree]) -> Box<MacResul
 }
 
 /// Generate the aggregate struct and struct expression.
-fn gen_aggregate_struct(cx: &mut ExtCtxt, sp: Span, aggregates: &[Aggregate]) -> P<Block> {
+/// The struct contains the `groups` fields followed by the `aggregates`.
+/// The fields are fetched from the row starting at `first_index`.
+fn gen_aggregate_struct(cx: &mut ExtCtxt, sp: Span, table: &SqlTable, groups: &[Identifier], aggregates: &[Aggregate], first_index: usize) -> P<Block> {
     let mut aggregate_fields = vec![];
     let mut fields = vec![];
+    for (index, group) in groups.iter().enumerate() {
+        // NOTE: At this stage (code generation), the field exists, hence unwrap().
+        let typ =
+            match table.fields.get(group).unwrap().node {
+                // NOTE: The value of a foreign key column is the primary key of the related row.
+                Type::Custom(ref related_table_name) => get_primary_key_type_by_table_name(related_table_name).unwrap(),
+                Type::Nullable(box Type::Custom(ref related_table_name)) =>
+                    Type::Nullable(box get_primary_key_type_by_table_name(related_table_name).unwrap()),
+                ref typ => typ.clone(),
+            };
+        let ty = type_to_ty(cx, &typ);
+        let index = index + first_index;
+        add_field(&mut aggregate_fields, quote_expr!(cx, row.get($index)), group, sp);
+        fields.push(create_aggregate_field_def(group, ty, sp));
+    }
     for (index, aggregate) in aggregates.iter().enumerate() {
+        let index = index + first_index + groups.len();
         let field_name = aggregate.result_name.clone();
+        let ty = type_to_ty(cx, &aggregate.result_type);
         add_field(&mut aggregate_fields, quote_expr!(cx, row.get($index)), &field_name, sp);
-        fields.push(create_aggregate_field_def(&field_name, sp));
+        fields.push(create_aggregate_field_def(&field_name, ty, sp));
     }
     let struct_ident = str_to_ident("Aggregate");
     let aggregate_struct = cx.item_struct(sp, struct_ident, VariantData::Struct(fields, NODE_ID));
@@ -327,9 +451,133 @@ fn gen_aggregate_struct(cx: &mut ExtCtxt, sp: Span, aggregates: &[Aggregate]) ->
     cx.block(sp, vec![aggregate_stmt], Some(instance))
 }
 
+/// Generate the Rust code inserting the `rows` by chunks using the `postgres` library.
+/// The `arguments` are evaluated for each row.
+/// The inserted primary keys of type `typ` are returned.
+fn gen_insert_many_expr(cx: &mut ExtCtxt, sp: Span, ident: Ident, sql_query: Expression, rows: Expression, arguments: Args, typ: &Type) -> Expression {
+    let ty = type_to_ty(cx, typ);
+    let column_count = arguments.len();
+    // NOTE: PostgreSQL does not support more than 65535 parameters in a query.
+    let chunk_size = 65535 / column_count;
+    let mut argument_stmts = vec![];
+    for arg in arguments {
+        let argument_ty = argument_type_to_ty(cx, &arg.typ);
+        let expression = arg.expression;
+        // NOTE: The value is bound to a typed variable so that the compiler checks its type.
+        let argument_expr = quote_expr!(cx, {
+            let _: &$argument_ty = &$expression;
+            arguments.push(&$expression);
+        });
+        argument_stmts.push(cx.stmt_expr(argument_expr));
+    }
+    let args_expr = cx.expr_block(cx.block(sp, argument_stmts, None));
+    quote_expr!(cx, {
+        let rows: Vec<_> = ::std::iter::IntoIterator::into_iter($rows).collect();
+        let mut ids = vec![];
+        let mut result = Ok(());
+        for chunk in rows.chunks($chunk_size) {
+            let mut values = vec![];
+            let mut arguments: Vec<&postgres::types::ToSql> = vec![];
+            for row in chunk {
+                let index = arguments.len();
+                let placeholders: Vec<_> = (index + 1 .. index + 1 + $column_count).map(|index| format!("${}", index)).collect();
+                values.push(format!("({})", placeholders.join(", ")));
+                $args_expr
+            }
+            let query = $sql_query.replace("{}", &values.join(", "));
+            let chunk_result = $ident.prepare(&query)
+                .and_then(|result| {
+                    let rows = try!(result.query(&arguments));
+                    let chunk_ids: Vec<$ty> = rows.iter().map(|row| row.get(0)).collect();
+                    Ok(chunk_ids)
+                });
+            match chunk_result {
+                Ok(mut chunk_ids) => ids.append(&mut chunk_ids),
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                },
+            }
+        }
+        result.map(|()| ids)
+    })
+}
+
+/// Generate the Rust code creating a `HashMap` of the related objects fetched by the `sql` query,
+/// grouped by the primary key of the selected objects.
+/// The primary key of the selected object, of type `primary_key_type`, is the last field of each row.
+fn gen_related_objects_expr(cx: &mut ExtCtxt, sp: Span, ident: Ident, sql: &str, related_table_name: &str, fields: &[Identifier], primary_key_type: &Type) -> Expression {
+    let primary_key_ty = type_to_ty(cx, primary_key_type);
+    let sql_tables = tables_singleton();
+    // NOTE: At this stage (code generation), the related table exists, hence unwrap().
+    let related_table = sql_tables.get(related_table_name).unwrap();
+    let related_fields = get_query_fields(cx, sp, related_table, sql_tables, vec![], fields);
+    let related_struct = cx.expr_struct(sp, cx.path_ident(sp, str_to_ident(related_table_name)), related_fields);
+    let string_literal = intern(sql);
+    let sql_query = cx.expr_str(sp, InternedString::new_from_name(string_literal));
+    let primary_key_index = fields.len();
+    quote_expr!(cx, {
+        let result = $ident.prepare($sql_query).unwrap();
+        let mut related_objects = ::std::collections::HashMap::new();
+        for row in result.query(&[&primary_keys]).unwrap().iter() {
+            let primary_key: $primary_key_ty = row.get($primary_key_index);
+            related_objects.entry(primary_key).or_insert_with(Vec::new).push($related_struct);
+        }
+        related_objects
+    })
+}
+
+/// Generate the Rust code fetching the selected objects with their related objects.
+/// The related objects are fetched with a second query for each relation (the `many_to_many`
+/// fields and the `prefetch`ed related table).
+fn gen_select_related_expr(cx: &mut ExtCtxt, sp: Span, ident: Ident, sql_query: Expression, args_expr: Expression, struct_expr: Expression, table: &SqlTable, many_to_many: Vec<ManyToManyJoin>, prefetch: Option<Prefetch>, one: bool) -> Expression {
+    // NOTE: At this stage (code generation), the table has a primary key, hence unwrap().
+    let primary_key = str_to_ident(&get_primary_key_field(table).unwrap());
+    let primary_key_type = get_primary_key_type(table).unwrap();
+    let primary_key_ty = type_to_ty(cx, &primary_key_type);
+    let mut objects = quote_expr!(cx, objects);
+    for join in many_to_many {
+        let related_objects = gen_related_objects_expr(cx, sp, ident, &join.to_sql(), &join.junction_table.related_table, &join.fields, &primary_key_type);
+        let field = str_to_ident(&join.junction_table.field);
+        objects = quote_expr!(cx, {
+            let mut objects = $objects;
+            let mut related_objects = $related_objects;
+            for object in &mut objects {
+                object.$field = related_objects.remove(&object.$primary_key).unwrap_or(vec![]);
+            }
+            objects
+        });
+    }
+    if let Some(prefetch) = prefetch {
+        let related_objects = gen_related_objects_expr(cx, sp, ident, &prefetch.to_sql(), &prefetch.table, &prefetch.fields, &primary_key_type);
+        objects = quote_expr!(cx, {
+            let mut related_objects = $related_objects;
+            $objects.into_iter().map(|object| {
+                let related_objects = related_objects.remove(&object.$primary_key).unwrap_or(vec![]);
+                (object, related_objects)
+            }).collect::<Vec<_>>()
+        });
+    }
+    let objects = quote_expr!(cx, {
+        let result = $ident.prepare($sql_query).unwrap();
+        let objects = result.query(&$args_expr).unwrap().iter().map(|row| {
+            $struct_expr
+        }).collect::<Vec<_>>();
+        let primary_keys: Vec<$primary_key_ty> = objects.iter().map(|object| object.$primary_key.clone()).collect();
+        // TODO: return an iterator instead of a vector.
+        $objects
+    });
+    if one {
+        quote_expr!(cx, $objects.into_iter().next())
+    }
+    else {
+        objects
+    }
+}
+
 /// Generate the Rust code from the SQL query.
 fn gen_query(cx: &mut ExtCtxt, sp: Span, table_ident: Ident, sql_query_with_args: SqlQueryWithArgs) -> Box<MacResult + 'static> {
-    let (sql, query_type, arguments, joins, aggregates) = sql_query_with_args;
+    let (sql, query_type, arguments, joins, aggregates, query_fields, many_to_many) = sql_query_with_args;
     let string_literal = intern(&sql);
     let sql_query = cx.expr_str(sp, InternedString::new_from_name(string_literal));
     let ident = Ident::new(intern("connection"), table_ident.ctxt);
@@ -337,11 +585,40 @@ fn gen_query(cx: &mut ExtCtxt, sp: Span, table_ident: Ident, sql_query_with_args
     let table_name = table_ident.to_string();
     match sql_tables.get(&table_name) {
         Some(table) => {
-            let fields = get_query_fields(cx, sp, &table.fields, sql_tables, joins);
+            let fields = get_query_fields(cx, sp, table, sql_tables, joins, &query_fields);
             let struct_expr = cx.expr_struct(sp, cx.path_ident(sp, table_ident), fields);
-            let aggregate_struct = gen_aggregate_struct(cx, sp, &aggregates);
-            let args_expr = get_query_arguments(cx, sp, table_name, arguments);
-            let expr = gen_query_expr(cx, ident, sql_query, args_expr, struct_expr, aggregate_struct, query_type);
+            let aggregate_struct =
+                match query_type {
+                    QueryType::AggregateMulti | QueryType::AggregateOne =>
+                        gen_aggregate_struct(cx, sp, table, &query_fields, &aggregates, 0),
+                    // NOTE: The aggregates follow the fields of the annotated objects.
+                    QueryType::Annotate => gen_aggregate_struct(cx, sp, table, &[], &aggregates, query_fields.len()),
+                    _ => gen_aggregate_struct(cx, sp, table, &[], &[], 0),
+                };
+            let expr =
+                match query_type {
+                    QueryType::InsertMany(rows, typ) => gen_insert_many_expr(cx, sp, ident, sql_query, rows, arguments, &typ),
+                    QueryType::SelectMulti if !many_to_many.is_empty() => {
+                        let args_expr = get_query_arguments(cx, sp, table_name, arguments);
+                        gen_select_related_expr(cx, sp, ident, sql_query, args_expr, struct_expr, table, many_to_many, None, false)
+                    },
+                    QueryType::SelectOne if !many_to_many.is_empty() => {
+                        let args_expr = get_query_arguments(cx, sp, table_name, arguments);
+                        gen_select_related_expr(cx, sp, ident, sql_query, args_expr, struct_expr, table, many_to_many, None, true)
+                    },
+                    QueryType::SelectPrefetchMulti(prefetch) => {
+                        let args_expr = get_query_arguments(cx, sp, table_name, arguments);
+                        gen_select_related_expr(cx, sp, ident, sql_query, args_expr, struct_expr, table, many_to_many, Some(prefetch), false)
+                    },
+                    QueryType::SelectPrefetchOne(prefetch) => {
+                        let args_expr = get_query_arguments(cx, sp, table_name, arguments);
+                        gen_select_related_expr(cx, sp, ident, sql_query, args_expr, struct_expr, table, many_to_many, Some(prefetch), true)
+                    },
+                    query_type => {
+                        let args_expr = get_query_arguments(cx, sp, table_name, arguments);
+                        gen_query_expr(cx, ident, sql_query, args_expr, struct_expr, aggregate_struct, query_type)
+                    },
+                };
             MacEager::expr(expr)
         },
         None => DummyResult::any(sp),
@@ -368,7 +645,34 @@ fn gen_query_expr(cx: &mut ExtCtxt, ident: Ident, sql_query: Expression, args_ex
                 })
             })
         },
-        QueryType::InsertOne => {
+        QueryType::Annotate => {
+            quote_expr!(cx, {
+                let result = $ident.prepare($sql_query).unwrap();
+                // TODO: return an iterator instead of a vector.
+                result.query(&$args_expr).unwrap().iter().map(|row| {
+                    ($struct_expr, $aggregate_struct)
+                }).collect::<Vec<_>>()
+            })
+        },
+        QueryType::Count => {
+            quote_expr!(cx, {
+                let result = $ident.prepare($sql_query).unwrap();
+                // NOTE: There is always one result (the number of rows), hence unwrap().
+                let count: i64 = result.query(&$args_expr).unwrap().iter().next().unwrap().get(0);
+                count
+            })
+        },
+        QueryType::Exists => {
+            quote_expr!(cx, {
+                let result = $ident.prepare($sql_query).unwrap();
+                // NOTE: There is always one result (whether a row exists), hence unwrap().
+                let exists: bool = result.query(&$args_expr).unwrap().iter().next().unwrap().get(0);
+                exists
+            })
+        },
+        QueryType::InsertMany(..) => unreachable!(), // NOTE: This query type is generated by gen_insert_many_expr().
+        QueryType::InsertOne(typ) => {
+            let ty = type_to_ty(cx, &typ);
             quote_expr!(cx, {
                 $ident.prepare($sql_query)
                     .and_then(|result| {
@@ -376,8 +680,46 @@ fn gen_query_expr(cx: &mut ExtCtxt, ident: Ident, sql_query: Expression, args_ex
                         let rows = result.query(&$args_expr).unwrap();
                         // NOTE: There is always one result (the inserted id), hence unwrap().
                         let row = rows.iter().next().unwrap();
-                        let count: i32 = row.get(0);
-                        Ok(count)
+                        let id: $ty = row.get(0);
+                        Ok(id)
+                    })
+            })
+        },
+        QueryType::InsertOptional(typ) => {
+            let ty = type_to_ty(cx, &typ);
+            quote_expr!(cx, {
+                $ident.prepare($sql_query)
+                    .and_then(|result| {
+                        // NOTE: The query is not supposed to fail, hence unwrap().
+                        let rows = result.query(&$args_expr).unwrap();
+                        // NOTE: There is no result when the insertion was ignored.
+                        let id: Option<$ty> = rows.iter().next().map(|row| row.get(0));
+                        Ok(id)
+                    })
+            })
+        },
+        QueryType::ReturningFields(types) => {
+            let fields_expr = gen_returning_fields_expr(cx, struct_expr.span, &types);
+            quote_expr!(cx, {
+                $ident.prepare($sql_query)
+                    .and_then(|result| {
+                        let rows = try!(result.query(&$args_expr));
+                        // TODO: return an iterator instead of a vector.
+                        Ok(rows.iter().map(|row| {
+                            $fields_expr
+                        }).collect::<Vec<_>>())
+                    })
+            })
+        },
+        QueryType::ReturningTable => {
+            quote_expr!(cx, {
+                $ident.prepare($sql_query)
+                    .and_then(|result| {
+                        let rows = try!(result.query(&$args_expr));
+                        // TODO: return an iterator instead of a vector.
+                        Ok(rows.iter().map(|row| {
+                            $struct_expr
+                        }).collect::<Vec<_>>())
                     })
             })
         },
@@ -398,12 +740,35 @@ fn gen_query_expr(cx: &mut ExtCtxt, ident: Ident, sql_query: Expression, args_ex
                 })
             })
         },
+        // NOTE: These query types are generated by gen_select_related_expr().
+        QueryType::SelectPrefetchMulti(_) | QueryType::SelectPrefetchOne(_) => unreachable!(),
         QueryType::Exec => {
             quote_expr!(cx, {
                 $ident.prepare($sql_query)
                     .and_then(|result| result.execute(&$args_expr))
             })
         },
+        QueryType::ExecBatch => {
+            quote_expr!(cx, {
+                $ident.batch_execute($sql_query)
+            })
+        },
+    }
+}
+
+/// Generate the expression getting the returned fields of the specified `types` from a row.
+/// It is a tuple when there are many fields.
+fn gen_returning_fields_expr(cx: &mut ExtCtxt, sp: Span, types: &[Type]) -> Expression {
+    let mut fields = vec![];
+    for (index, typ) in types.iter().enumerate() {
+        let ty = type_to_ty(cx, typ);
+        fields.push(quote_expr!(cx, row.get::<_, $ty>($index)));
+    }
+    if fields.len() == 1 {
+        fields.remove(0)
+    }
+    else {
+        cx.expr_tuple(sp, fields)
     }
 }
 
@@ -447,28 +812,89 @@ fn get_query_arguments(cx: &mut ExtCtxt, sp: Span, table_name: String, arguments
 }
 
 /// Get the fully qualified field names for the struct expression needed by the generated code.
-fn get_query_fields(cx: &mut ExtCtxt, sp: Span, table: &SqlFields, sql_tables: &SqlTables, joins: Vec<Join>) -> Vec<Field> {
-    let mut fields = vec![];
+/// The fields of the `table` that are not in the `query_fields` are not fetched.
+fn get_query_fields(cx: &mut ExtCtxt, sp: Span, table: &SqlTable, sql_tables: &SqlTables, joins: Vec<Join>, query_fields: &[Identifier]) -> Vec<Field> {
     let mut index = 0usize;
-    for (name, typ) in table {
+    get_table_fields(cx, sp, table, &table.name, &[], sql_tables, &joins, query_fields, &mut index)
+}
+
+/// Convert the `#[check]` attributes of the table struct to the SQL expressions of the CHECK
+/// constraints.
+fn get_table_checks(cx: &mut ExtCtxt, attributes: &[Attribute], table_name: &str) -> Vec<String> {
+    let checks =
+        match get_checks(attributes) {
+            Ok(checks) => checks,
+            Err(errors) => {
+                span_errors(errors, cx);
+                vec![]
+            },
+        };
+    let mut sql_checks = vec![];
+    for Spanned { node: check, span: position } in checks {
+        let mut parser = new_parser_from_source_str(cx.parse_sess, cx.cfg(), "check".to_owned(), check);
+        let sql = parser.parse_expr()
+            .map_err(|error| vec![SqlError::new(error.description(), position)])
+            .and_then(|expression| {
+                // NOTE: At this stage, the table was added, hence unwrap().
+                let table = tables_singleton().get(table_name).unwrap();
+                check_to_sql(&expression, position, table)
+            });
+        match sql {
+            Ok(sql) => sql_checks.push(sql),
+            Err(errors) => span_errors(errors, cx),
+        }
+    }
+    sql_checks
+}
+
+/// Get the fields for the struct expression of the `table` referred to by `alias` in the query.
+/// The `path` contains the foreign key fields leading from the main table to this `table`.
+fn get_table_fields(cx: &mut ExtCtxt, sp: Span, table: &SqlTable, alias: &str, path: &[Identifier], sql_tables: &SqlTables, joins: &[Join], query_fields: &[Identifier], index: &mut usize) -> Vec<Field> {
+    let mut fields = vec![];
+    for (name, typ) in &table.fields {
         match typ.node {
-            Type::Custom(ref foreign_table) => {
+            Type::Custom(ref foreign_table) | Type::Nullable(box Type::Custom(ref foreign_table)) => {
                 let table_name = foreign_table;
                 if let Some(foreign_table) = sql_tables.get(foreign_table) {
-                    if has_joins(&joins, name) {
-                        // If there is a join, fetch the joined fields.
-                        let mut foreign_fields = vec![];
-                        for (field, typ) in &foreign_table.fields {
-                            match typ.node {
-                                Type::Custom(_) | Type::UnsupportedType(_) => (), // Do not add foreign key recursively.
-                                _ => {
-                                    add_field(&mut foreign_fields, quote_expr!(cx, row.get($index)), &field, sp);
-                                    index += 1;
-                                },
-                            }
+                    let mut field_path = path.to_vec();
+                    field_path.push(name.clone());
+                    if let Some(join) = joins.iter().find(|join| join.path == field_path) {
+                        // NOTE: A primary key which is a foreign key is fetched before the
+                        // other fields of the joined table (see add_table_fields()).
+                        if let Some(&Type::Custom(_)) = foreign_table.fields.get(&join.joined_field).map(|typ| &typ.node) {
+                            *index += 1;
                         }
+                        // If there is a join, fetch the joined fields (and the fields of the
+                        // nested joins).
+                        let foreign_fields = get_table_fields(cx, sp, foreign_table, &join.alias, &field_path, sql_tables, joins, query_fields, index);
+                        let primary_key_field = join.alias.clone() + "." + &join.joined_field;
+                        let primary_key_index = query_fields.iter()
+                            .position(|field| *field == primary_key_field)
+                            .expect("the primary key of a joined table is always fetched by the analyzer");
+                        let primary_key_ty = type_to_ty(cx, &get_primary_key_type(foreign_table).unwrap());
                         let related_struct = cx.expr_struct(sp, cx.path_ident(sp, str_to_ident(table_name)), foreign_fields);
-                        add_field(&mut fields, quote_expr!(cx, Some($related_struct)), name, sp);
+                        // NOTE: The conversion boxes the related object when the field is a
+                        // ForeignKey<Box<T>> (for a table referring to itself).
+                        let related_struct = quote_expr!(cx, ::std::convert::From::from($related_struct));
+                        let related_struct =
+                            if let Type::Nullable(_) = typ.node {
+                                quote_expr!(cx, Some($related_struct))
+                            }
+                            else {
+                                related_struct
+                            };
+                        let related_object =
+                            match join.typ {
+                                JoinType::Inner => quote_expr!(cx, Some($related_struct)),
+                                // NOTE: The related fields are all NULL when there is no related
+                                // row: this is checked with the primary key which cannot be NULL
+                                // otherwise.
+                                JoinType::Left => quote_expr!(cx, {
+                                    let primary_key: Option<$primary_key_ty> = row.get($primary_key_index);
+                                    primary_key.map(|_| $related_struct)
+                                }),
+                            };
+                        add_field(&mut fields, related_object, name, sp);
                     }
                     else {
                         // Since a `ForeignKey` is an `Option`, we output `None` when the field
@@ -478,10 +904,20 @@ fn get_query_fields(cx: &mut ExtCtxt, sp: Span, table: &SqlFields, sql_tables: &
                 }
                 // NOTE: if the field type is not an SQL table, an error is thrown by the linter.
             },
+            // NOTE: The related objects of a many-to-many field are fetched with a second query.
+            Type::ManyToMany(_) => add_field(&mut fields, quote_expr!(cx, vec![]), name, sp),
             Type::UnsupportedType(_) => (), // TODO: should panic.
             _ => {
-                add_field(&mut fields, quote_expr!(cx, row.get($index)), name, sp);
-                index += 1;
+                if query_fields.contains(&(alias.to_owned() + "." + name)) {
+                    let field_index = *index;
+                    add_field(&mut fields, quote_expr!(cx, row.get($field_index)), name, sp);
+                    *index += 1;
+                }
+                else {
+                    // NOTE: The fields that were not fetched (only() and defer()) get their
+                    // default value.
+                    add_field(&mut fields, quote_expr!(cx, ::std::default::Default::default()), name, sp);
+                }
             },
         }
     }
@@ -539,7 +975,7 @@ fn to_sql(cx: &mut ExtCtxt, args: &[TokenTree]) -> SqlResult<SqlQueryWithArgs> {
     let mut query = try!(analyze(method_calls, sql_tables));
     optimize(&mut query);
     query = try!(analyze_types(query));
-    let sql = query.to_sql();
+    let sql = replace_placeholder(query.to_sql());
     let joins =
         match query {
             Query::Select { ref joins, .. } => joins.clone(),
@@ -547,14 +983,85 @@ fn to_sql(cx: &mut ExtCtxt, args: &[TokenTree]) -> SqlResult<SqlQueryWithArgs> {
         };
     let aggrs: Vec<Aggregate> =
         match query {
-            Query::Aggregate { ref aggregates, .. } => aggregates.clone(),
+            // NOTE: The results of the window functions follow the aggregates.
+            Query::Aggregate { ref aggregates, ref windows, .. } =>
+                aggregates.iter()
+                    .cloned()
+                    .chain(windows.iter().map(|window| window.function.clone()))
+                    .collect(),
+            _ => vec![],
+        };
+    let fields =
+        match query {
+            Query::Aggregate { ref fields, ref groups, .. } =>
+                if fields.is_empty() {
+                    groups.clone()
+                }
+                else {
+                    fields.clone()
+                },
+            Query::Delete { returning: Some(Returning::Table(ref fields)), .. } |
+                Query::Select { ref fields, .. } |
+                Query::Update { returning: Some(Returning::Table(ref fields)), .. } => fields.clone(),
+            _ => vec![],
+        };
+    let many_to_many =
+        match query {
+            Query::Select { ref many_to_many, .. } => many_to_many.clone(),
             _ => vec![],
         };
-    Ok((sql, query_type(&query), arguments(cx, query), joins, aggrs))
+    Ok((sql, query_type(&query), arguments(cx, query), joins, aggrs, fields, many_to_many))
+}
+
+/// Convert a `Type` to the corresponding Rust type.
+fn type_to_ty(cx: &mut ExtCtxt, typ: &Type) -> P<Ty> {
+    match *typ {
+        Type::Array(ref typ) => {
+            let ty = type_to_ty(cx, typ);
+            quote_ty!(cx, Vec<$ty>)
+        },
+        Type::Bool => quote_ty!(cx, bool),
+        Type::ByteString => quote_ty!(cx, Vec<u8>),
+        Type::Char => quote_ty!(cx, char),
+        Type::Custom(ref table_name) => {
+            let ident = str_to_ident(table_name);
+            quote_ty!(cx, $ident)
+        },
+        Type::F32 => quote_ty!(cx, f32),
+        Type::F64 => quote_ty!(cx, f64),
+        // NOTE: The unsupported types are reported by the analyzer: the type placeholder avoids
+        // generating an invalid path.
+        Type::Generic | Type::UnsupportedType(_) => quote_ty!(cx, _),
+        Type::I8 => quote_ty!(cx, i8),
+        Type::I16 => quote_ty!(cx, i16),
+        Type::I32 | Type::Serial => quote_ty!(cx, i32),
+        Type::I64 => quote_ty!(cx, i64),
+        Type::LocalDateTime => quote_ty!(cx, ::chrono::datetime::DateTime<::chrono::offset::local::Local>),
+        Type::ManyToMany(ref table_name) => {
+            let ident = str_to_ident(table_name);
+            quote_ty!(cx, Vec<$ident>)
+        },
+        Type::NaiveDate => quote_ty!(cx, ::chrono::naive::date::NaiveDate),
+        Type::NaiveDateTime => quote_ty!(cx, ::chrono::naive::datetime::NaiveDateTime),
+        Type::NaiveTime => quote_ty!(cx, ::chrono::naive::time::NaiveTime),
+        Type::Nullable(ref typ) => {
+            let ty = type_to_ty(cx, typ);
+            quote_ty!(cx, Option<$ty>)
+        },
+        Type::String => quote_ty!(cx, String),
+        Type::UTCDateTime => quote_ty!(cx, ::chrono::datetime::DateTime<::chrono::offset::utc::UTC>),
+        Type::Uuid => quote_ty!(cx, ::uuid::Uuid),
+    }
 }
 
 #[plugin_registrar]
 pub fn plugin_registrar(reg: &mut Registry) {
+    reg.register_attribute("check".to_owned(), AttributeType::Whitelisted);
+    reg.register_attribute("default".to_owned(), AttributeType::Whitelisted);
+    reg.register_attribute("foreign_key".to_owned(), AttributeType::Whitelisted);
+    reg.register_attribute("index".to_owned(), AttributeType::Whitelisted);
+    reg.register_attribute("primary_key".to_owned(), AttributeType::Whitelisted);
+    reg.register_attribute("unique".to_owned(), AttributeType::Whitelisted);
     reg.register_macro("to_sql", expand_to_sql);
     reg.register_macro("sql", expand_sql);
     reg.register_syntax_extension(intern("SqlTable"), MultiDecorator(box expand_sql_table));
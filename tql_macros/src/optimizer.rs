@@ -23,7 +23,7 @@ use syntax::ast::BinOp_::{BiAdd, BiSub};
 use syntax::ast::Expr_::{ExprBinary, ExprLit};
 use syntax::ast::Lit_::LitInt;
 
-use ast::{Expression, Limit, Query};
+use ast::{Expression, FilterExpression, FilterValue, Limit, Membership, MembershipValues, Query};
 use ast::Limit::{EndRange, Index, LimitOffset, Range, StartRange};
 use plugin::number_literal;
 
@@ -63,15 +63,73 @@ fn evaluate(expression: &Expression) -> u64 {
 /// Optimize the query.
 pub fn optimize(query: &mut Query) {
     match *query {
-        Query::Aggregate { .. } => (), // TODO
-        Query::CreateTable { .. } => (), // Nothing to optimize.
-        Query::Delete { .. } => (), // TODO
+        Query::Aggregate { ref mut filter, .. } => {
+            optimize_filter(filter);
+        },
+        Query::Count { ref mut filter, .. } => {
+            optimize_filter(filter);
+        },
+        Query::CreateJunctionTables { .. } | Query::CreateTable { .. } => (), // Nothing to optimize.
+        Query::Delete { ref mut filter, .. } => {
+            optimize_filter(filter);
+        },
         Query::Drop { .. } => (), // Nothing to optimize.
+        Query::Exists { ref mut filter, .. } => {
+            optimize_filter(filter);
+        },
         Query::Insert { .. } => (), // TODO
-        Query::Select { ref mut limit, .. } => {
+        Query::InsertMany { .. } => (), // Nothing to optimize.
+        Query::Select { ref mut filter, ref mut limit, .. } => {
+            optimize_filter(filter);
             *limit = optimize_limit(limit);
         },
-        Query::Update { .. } => (), // TODO
+        Query::Update { ref mut filter, .. } => {
+            optimize_filter(filter);
+        },
+    }
+}
+
+/// Optimize the filter by converting the ranges having a literal end to inclusive ranges.
+fn optimize_filter(filter: &mut FilterExpression) {
+    match *filter {
+        FilterExpression::Filter(ref mut filter) => optimize_filter_value(&mut filter.operand1),
+        FilterExpression::Filters(ref mut filters) => {
+            optimize_filter(&mut filters.operand1);
+            optimize_filter(&mut filters.operand2);
+        },
+        FilterExpression::NegFilter(ref mut filter) => optimize_filter(filter),
+        FilterExpression::NoFilters => (),
+        FilterExpression::ParenFilter(ref mut filter) => optimize_filter(filter),
+        FilterExpression::FilterValue(ref mut filter_value) => optimize_filter_value(&mut filter_value.node),
+    }
+}
+
+/// Convert the range of a `FilterValue::In` to an inclusive range if its end is a literal.
+/// Also optimize the subqueries.
+fn optimize_filter_value(filter_value: &mut FilterValue) {
+    if let FilterValue::Exists(ref mut query) = *filter_value {
+        optimize(query);
+    }
+    if let FilterValue::In(Membership { ref mut values, .. }) = *filter_value {
+        let new_values =
+            if let MembershipValues::Query(ref mut query) = *values {
+                optimize(query);
+                None
+            }
+            else if let MembershipValues::Range(ref start, ref end) = *values {
+                if all_integer_literal(end) && evaluate(end) > 0 {
+                    Some(MembershipValues::InclusiveRange(try_simplify(start), number_literal(evaluate(end) - 1)))
+                }
+                else {
+                    None
+                }
+            }
+            else {
+                None
+            };
+        if let Some(new_values) = new_values {
+            *values = new_values;
+        }
     }
 }
 
@@ -17,18 +17,28 @@
 
 //! Rust compiler plugin functions.
 
-use syntax::ast::{Expr, Ident, Path};
-use syntax::ast::Expr_::{ExprField, ExprLit};
-use syntax::ast::Lit_::LitInt;
-use syntax::ast::LitIntType::SignedIntLit;
+use syntax::ast::{Expr, Ident, Lit_, Path, PathParameters, PathSegment, UnOp};
+use syntax::ast::Expr_::{ExprField, ExprLit, ExprPath, ExprTupField, ExprUnary};
+use syntax::ast::Lit_::{LitFloat, LitFloatUnsuffixed, LitInt};
+use syntax::ast::LitIntType::{SignedIntLit, UnsuffixedIntLit};
 use syntax::ast::IntTy::TyI64;
 use syntax::ast::Sign;
 use syntax::codemap::{Span, Spanned, DUMMY_SP};
-use syntax::parse::token::intern;
+use syntax::parse::token::{intern, intern_and_get_ident, str_to_ident};
 use syntax::ptr::P;
 
 pub static NODE_ID: u32 = 4294967295;
 
+/// Create the `ExprUnary` expression *`expr` (dereference).
+pub fn deref(expr: P<Expr>, position: Span) -> P<Expr> {
+    P(Expr {
+        attrs: None,
+        id: NODE_ID,
+        node: ExprUnary(UnOp::UnDeref, expr),
+        span: position,
+    })
+}
+
 /// Create the `ExprField` expression `expr`.`field_name` (struct field access).
 pub fn field_access(expr: P<Expr>, path: &Path, position: Span, field_name: String) -> P<Expr> {
     let syntax_context = path.segments[0].identifier.ctxt;
@@ -44,6 +54,47 @@ pub fn field_access(expr: P<Expr>, path: &Path, position: Span, field_name: Stri
     })
 }
 
+/// Create the `ExprField` expression `expr`.`field_name` (struct field access) without a syntax
+/// context.
+pub fn field_access_by_name(expr: P<Expr>, position: Span, field_name: &str) -> P<Expr> {
+    P(Expr {
+        attrs: None,
+        id: NODE_ID,
+        node: ExprField(expr, Spanned {
+            node: str_to_ident(field_name),
+            span: position,
+        }),
+        span: position,
+    })
+}
+
+/// Create the `ExprLit` expression of the `literal`.
+pub fn literal_expr(literal: Lit_, position: Span) -> P<Expr> {
+    P(Expr {
+        attrs: None,
+        id: NODE_ID,
+        node: ExprLit(P(Spanned {
+            node: literal,
+            span: position,
+        })),
+        span: position,
+    })
+}
+
+/// Create the `ExprLit` expression of the negation of the number `literal` (for instance, `-100`).
+/// Returns `None` if the `literal` is not a positive number.
+pub fn negative_literal(literal: &Lit_, position: Span) -> Option<P<Expr>> {
+    let literal =
+        match *literal {
+            LitFloat(ref float, float_type) => LitFloat(intern_and_get_ident(&format!("-{}", float)), float_type),
+            LitFloatUnsuffixed(ref float) => LitFloatUnsuffixed(intern_and_get_ident(&format!("-{}", float))),
+            LitInt(number, SignedIntLit(int_type, Sign::Plus)) => LitInt(number, SignedIntLit(int_type, Sign::Minus)),
+            LitInt(number, UnsuffixedIntLit(Sign::Plus)) => LitInt(number, UnsuffixedIntLit(Sign::Minus)),
+            _ => return None,
+        };
+    Some(literal_expr(literal, position))
+}
+
 /// Converts a number to an `P<Expr>`.
 pub fn number_literal(number: u64) -> P<Expr> {
     P(Expr {
@@ -56,3 +107,33 @@ pub fn number_literal(number: u64) -> P<Expr> {
         span: DUMMY_SP,
     })
 }
+
+/// Create the `ExprTupField` expression `expr`.`index` (tuple field access).
+pub fn tuple_field_access(expr: P<Expr>, position: Span, index: usize) -> P<Expr> {
+    P(Expr {
+        attrs: None,
+        id: NODE_ID,
+        node: ExprTupField(expr, Spanned {
+            node: index,
+            span: position,
+        }),
+        span: position,
+    })
+}
+
+/// Create the `ExprPath` expression `name` (variable access).
+pub fn variable(name: &str, position: Span) -> P<Expr> {
+    P(Expr {
+        attrs: None,
+        id: NODE_ID,
+        node: ExprPath(None, Path {
+            span: position,
+            global: false,
+            segments: vec![PathSegment {
+                identifier: str_to_ident(name),
+                parameters: PathParameters::none(),
+            }],
+        }),
+        span: position,
+    })
+}
@@ -17,7 +17,7 @@
 
 //! Global mutable states handling.
 //!
-//! There are four global states:
+//! There are five global states:
 //!
 //! The aggregates global state contains the existing aggregate functions.
 //!
@@ -29,6 +29,8 @@
 //! The tables global state contains the SQL tables gathered by the `SqlTable` attribute with their
 //! fields.
 //! A field is an identifier and a type.
+//!
+//! The window functions global state contains the existing window functions.
 
 use std::collections::BTreeMap;
 use std::collections::HashMap;
@@ -36,11 +38,24 @@ use std::mem;
 
 use syntax::codemap::{Span, Spanned};
 
-use methods::{add_initial_aggregates, add_initial_methods};
+use aggregates::{add_initial_aggregates, add_initial_window_functions};
+use ast::FieldList;
+use methods::add_initial_methods;
 use types::Type;
 
+/// A tql aggregate function with its overloads (by number of arguments).
+pub type SqlAggregate = Vec<SqlAggregateTypes>;
+
 /// A collection of tql aggregate functions.
-pub type SqlAggregates = HashMap<String, String>;
+pub type SqlAggregates = HashMap<String, SqlAggregate>;
+
+/// Tql aggregate function argument types, result type and template.
+/// The first argument is the aggregated field.
+pub struct SqlAggregateTypes {
+    pub argument_types: Vec<Type>,
+    pub result_type: fn(&Type) -> Option<Type>,
+    pub template: String,
+}
 
 /// An SQL query argument type.
 #[derive(Debug)]
@@ -79,17 +94,50 @@ pub struct SqlMethodTypes {
     pub template: String,
 }
 
-/// An `SqlTable` has a name, a position and some `SqlFields`.
+/// An index of an `SqlTable` (from an `#[index]` attribute).
+#[derive(Clone, Debug, Default)]
+pub struct SqlIndex {
+    pub fields: Vec<String>,
+    /// The index method (for instance, `gin`). The default method is used when it is `None`.
+    pub method: Option<String>,
+    pub name: String,
+    pub unique: bool,
+}
+
+/// An `SqlTable` has a name, a position, some `SqlFields`, some indexes and some unique keys.
 pub struct SqlTable {
+    /// The SQL expressions of the CHECK constraints (from the `#[check]` attributes).
+    pub checks: Vec<String>,
+    /// The default values of the fields (from the `#[default]` attributes).
+    pub defaults: HashMap<String, String>,
     pub fields: SqlFields,
+    /// The fields of the foreign keys referencing a compound primary key, by foreign key field
+    /// (from the `#[foreign_key]` attributes).
+    pub foreign_keys: HashMap<String, FieldList>,
+    pub indexes: Vec<SqlIndex>,
     pub name: String,
     pub position: Span,
+    /// The fields of the compound primary key (from the `#[primary_key]` attribute).
+    /// It is empty when the primary key is the `PrimaryKey` field.
+    pub primary_key: Vec<String>,
+    /// The fields of each unique key (from the `#[unique]` attributes).
+    pub unique_keys: Vec<FieldList>,
 }
 
 /// A collection of SQL tables.
 /// A map from table name to `SqlTable`.
 pub type SqlTables = HashMap<String, SqlTable>;
 
+/// Get the aggregate types by function name and number of arguments.
+/// The window functions are also searched.
+pub fn get_aggregate_types<'a>(function: &str, argument_count: usize) -> Option<&'a SqlAggregateTypes> {
+    let aggregates = aggregates_singleton();
+    let window_functions = window_functions_singleton();
+    aggregates.get(function)
+        .or_else(|| window_functions.get(function))
+        .and_then(|overloads| overloads.iter().find(|types| types.argument_types.len() == argument_count))
+}
+
 /// Get the type of the field if it exists.
 pub fn get_field_type<'a, 'b>(table_name: &'a str, identifier: &'b str) -> Option<&'a Type> {
     let tables = tables_singleton();
@@ -110,8 +158,47 @@ pub fn get_method_types<'a>(table_name: &str, field_name: &str, method_name: &st
         )
 }
 
-/// Get the name of the primary key field.
+/// Get the (field, related field) pairs of the foreign key `field` of the `table` referencing the
+/// `related_table`.
+/// A foreign key referencing a compound primary key contains the fields of its `#[foreign_key]`
+/// attribute, the `field` itself referencing the last field of this key.
+/// Returns an empty `Vec` when the fields of the foreign key cannot be found.
+pub fn get_foreign_key_fields(table: &SqlTable, field: &str, related_table: &SqlTable) -> Vec<(String, String)> {
+    let mut primary_key_fields = get_primary_key_fields(related_table);
+    if primary_key_fields.len() > 1 {
+        match table.foreign_keys.get(field) {
+            Some(fields) if fields.len() == primary_key_fields.len() =>
+                fields.iter().cloned()
+                    .zip(primary_key_fields)
+                    .collect(),
+            // NOTE: The missing or invalid #[foreign_key] attribute is reported by the lint.
+            _ => vec![],
+        }
+    }
+    else {
+        primary_key_fields.pop()
+            .map(|primary_key_field| vec![(field.to_owned(), primary_key_field)])
+            .unwrap_or_else(Vec::new)
+    }
+}
+
+/// Get the name of the generated primary key field (a SERIAL or a BIGSERIAL), i.e. the primary key
+/// which is not specified in an insert().
+pub fn get_generated_primary_key_field(table: &SqlTable) -> Option<String> {
+    get_primary_key_field(table).and_then(|field| {
+        match table.fields.get(&field).map(|typ| &typ.node) {
+            Some(&Type::I32) | Some(&Type::I64) | Some(&Type::Serial) => Some(field),
+            _ => None,
+        }
+    })
+}
+
+/// Get the name of the primary key field (the field with the `#[primary_key]` attribute or the
+/// `PrimaryKey` field).
 pub fn get_primary_key_field(table: &SqlTable) -> Option<String> {
+    if table.primary_key.len() == 1 {
+        return Some(table.primary_key[0].clone());
+    }
     table.fields.iter()
         .find(|&(_, typ)| typ.node == Type::Serial)
         .map(|(field, _)| field.clone())
@@ -123,6 +210,52 @@ pub fn get_primary_key_field_by_table_name(table_name: &str) -> Option<String> {
     tables.get(table_name).and_then(|table| get_primary_key_field(table))
 }
 
+/// Get the type of the values of the primary key of the `table`, which is also the type of the
+/// foreign keys referencing this table.
+/// For a compound primary key, it is the type of its last field.
+pub fn get_primary_key_type(table: &SqlTable) -> Option<Type> {
+    get_primary_key_fields(table).pop()
+        .and_then(|field| table.fields.get(&field))
+        .and_then(|typ| {
+            match typ.node {
+                Type::Custom(ref related_table_name) => get_primary_key_type_by_table_name(related_table_name),
+                Type::Serial => Some(Type::I32),
+                ref typ => Some(typ.clone()),
+            }
+        })
+}
+
+/// Get the type of the values of the primary key by table name.
+pub fn get_primary_key_type_by_table_name(table_name: &str) -> Option<Type> {
+    let tables = tables_singleton();
+    tables.get(table_name).and_then(|table| get_primary_key_type(table))
+}
+
+/// Get the names of the primary key fields (many fields for a compound primary key).
+pub fn get_primary_key_fields(table: &SqlTable) -> Vec<String> {
+    if !table.primary_key.is_empty() {
+        table.primary_key.clone()
+    }
+    else {
+        get_primary_key_field(table).into_iter().collect()
+    }
+}
+
+/// Get the fields of the compound primary key, of the unique keys and of the unique indexes of the
+/// `table`.
+pub fn get_unique_field_lists(table: &SqlTable) -> Vec<&[String]> {
+    let unique_indexes = table.indexes.iter()
+        .filter(|index| index.unique)
+        .map(|index| &index.fields[..]);
+    let mut unique_field_lists = vec![];
+    if !table.primary_key.is_empty() {
+        unique_field_lists.push(&table.primary_key[..]);
+    }
+    unique_field_lists.extend(table.unique_keys.iter().map(|unique_key| &unique_key[..]));
+    unique_field_lists.extend(unique_indexes);
+    unique_field_lists
+}
+
 /// Returns the global aggregate state.
 pub fn aggregates_singleton() -> &'static mut SqlAggregates {
     // FIXME: make this thread safe.
@@ -180,3 +313,18 @@ pub fn tables_singleton() -> &'static mut SqlTables {
         &mut *hash_map
     }
 }
+
+/// Returns the global window function state.
+pub fn window_functions_singleton() -> &'static mut SqlAggregates {
+    // FIXME: make this thread safe.
+    static mut hash_map: *mut SqlAggregates = 0 as *mut SqlAggregates;
+
+    let map: SqlAggregates = HashMap::new();
+    unsafe {
+        if hash_map == 0 as *mut SqlAggregates {
+            hash_map = mem::transmute(Box::new(map));
+            add_initial_window_functions();
+        }
+        &mut *hash_map
+    }
+}
@@ -28,7 +28,7 @@ use syntax::codemap::{NO_EXPANSION, BytePos, Span};
 
 use analyzer::unknown_table_error;
 use error::{SqlError, ErrorType, SqlResult, res};
-use state::{SqlTable, SqlTables, lint_singleton, tables_singleton};
+use state::{SqlTable, SqlTables, get_primary_key_fields, lint_singleton, tables_singleton};
 use types::Type;
 
 declare_lint!(SQL_LINT, Forbid, "Err about SQL type errors");
@@ -53,28 +53,49 @@ impl LintPass for SqlAttrError {
 fn analyze_table_types(table: &SqlTable, sql_tables: &SqlTables) -> SqlResult<()> {
     let mut errors = vec![];
     let mut primary_key_count = 0u32;
-    for field in table.fields.values() {
+    for (field_name, field) in &table.fields {
         match field.node {
-            Type::Custom(ref related_table_name) =>
-                if let None = sql_tables.get(related_table_name) {
-                    unknown_table_error(related_table_name, field.span, sql_tables, &mut errors);
+            Type::Custom(ref related_table_name) | Type::Nullable(box Type::Custom(ref related_table_name)) =>
+                match sql_tables.get(related_table_name) {
+                    Some(related_table) => check_foreign_key_fields(table, field_name, related_table, field.span, &mut errors),
+                    None => unknown_table_error(related_table_name, field.span, sql_tables, &mut errors),
                 },
+            Type::ManyToMany(ref related_table_name) => {
+                // NOTE: The junction table columns reference a single primary key field.
+                match sql_tables.get(related_table_name) {
+                    Some(related_table) =>
+                        if related_table.primary_key.len() > 1 {
+                            errors.push(SqlError::new(
+                                &format!("a ManyToMany field cannot reference the table `{}` which has a compound primary key", related_table_name),
+                                field.span,
+                            ));
+                        },
+                    None => unknown_table_error(related_table_name, field.span, sql_tables, &mut errors),
+                }
+            },
             Type::Serial => {
                 primary_key_count += 1;
             }
             _ => (),
         }
     }
-    match primary_key_count {
-        0 => errors.insert(0, SqlError::new_warning("No primary key found", table.position)),
-        1 => (), // One primary key is OK.
-        _ => errors.insert(0, SqlError::new_warning("More than one primary key is currently not supported", table.position)),
+    // NOTE: A compound primary key can contain PrimaryKey fields.
+    if table.primary_key.is_empty() {
+        match primary_key_count {
+            0 => errors.insert(0, SqlError::new_warning("No primary key found", table.position)),
+            1 => (), // One primary key is OK.
+            _ => {
+                errors.insert(0, SqlError::new_warning("More than one primary key is currently not supported", table.position));
+                errors.insert(1, SqlError::new_help("use the #[primary_key(field1, field2)] attribute for a compound primary key", table.position));
+            },
+        }
     }
     res((), errors)
 }
 
 /// Get the types of the elements in a `Vec`.
-fn argument_types<'a>(cx: &'a LateContext, arguments: &'a Expr_) -> Vec<Ty<'a>> {
+/// Returns `None` if the `arguments` are not a reference to a slice literal.
+fn argument_types<'a>(cx: &'a LateContext, arguments: &'a Expr_) -> Option<Vec<Ty<'a>>> {
     let mut types = vec![];
     if let ExprAddrOf(_, ref argument) = *arguments {
         if let ExprVec(ref vector) = argument.node {
@@ -86,15 +107,34 @@ fn argument_types<'a>(cx: &'a LateContext, arguments: &'a Expr_) -> Vec<Ty<'a>>
                     panic!("Argument should be a `&_`");
                 }
             }
-        }
-        else {
-            panic!("Arguments should be a `&Vec<_>`");
+            return Some(types);
         }
     }
-    else {
-        panic!("Arguments should be a `&Vec<_>`");
+    None
+}
+
+/// Check the types of the `arguments` generated by the sql!() macro at `position`.
+fn check_arguments(cx: &LateContext, arguments: &Expr, position: Span) {
+    let calls = lint_singleton();
+    let BytePos(low) = position.lo;
+    match calls.get(&low) {
+        Some(fields) => {
+            // NOTE: The insert_many() query sends a `Vec` built row by row instead of a `&[_]`:
+            // its row arguments are type-checked by the compiler.
+            if let Some(types) = argument_types(cx, &arguments.node) {
+                for (i, typ) in types.iter().enumerate() {
+                    let field = &fields.arguments[i];
+                    let field_position = Span {
+                        lo: BytePos(field.low),
+                        hi: BytePos(field.high),
+                        expn_id: NO_EXPANSION,
+                    };
+                    check_type(&field.typ, typ, field_position, position, cx);
+                }
+            }
+        },
+        None => (), // TODO
     }
-    types
 }
 
 impl EarlyLintPass for SqlAttrError {
@@ -123,25 +163,104 @@ impl LateLintPass for SqlErrorLint {
         if let ExprMethodCall(name, _, ref arguments) = expr.node {
             let method_name = name.node.to_string();
             if method_name == "query" || method_name == "execute" {
-                let types = argument_types(cx, &arguments[1].node);
-                let calls = lint_singleton();
-                let BytePos(low) = expr.span.lo;
-                match calls.get(&low) {
-                    Some(fields) => {
-                        for (i, typ) in types.iter().enumerate() {
-                            let field = &fields.arguments[i];
-                            let position = Span {
-                                lo: BytePos(field.low),
-                                hi: BytePos(field.high),
-                                expn_id: NO_EXPANSION,
-                            };
-                            check_type(&field.typ, typ, position, expr.span, cx);
+                // NOTE: Only get the argument types of the calls generated by the sql!()
+                // macro since other calls could send a `Vec` variable instead of a `&[_]`.
+                check_arguments(cx, &arguments[1], expr.span);
+            }
+        }
+    }
+
+}
+
+/// Check that the foreign key `field` of the `table` maps each field of the compound primary key of
+/// the `related_table` with a `#[foreign_key]` attribute.
+fn check_foreign_key_fields(table: &SqlTable, field: &str, related_table: &SqlTable, position: Span, errors: &mut Vec<SqlError>) {
+    let primary_key_fields = get_primary_key_fields(related_table);
+    // NOTE: A nullable related object cannot be split into the fields of the foreign key.
+    if let Some(&Type::Nullable(_)) = table.fields.get(field).map(|typ| &typ.node) {
+        if primary_key_fields.len() > 1 {
+            errors.push(SqlError::new(
+                &format!("a nullable foreign key cannot reference the table `{}` which has a compound primary key", related_table.name),
+                position,
+            ));
+            errors.push(SqlError::new_help(
+                &format!("use the type ForeignKey<{}>", related_table.name),
+                position,
+            ));
+        }
+    }
+    match table.foreign_keys.get(field) {
+        Some(_) if primary_key_fields.len() <= 1 => {
+            errors.push(SqlError::new(
+                &format!("the #[foreign_key] attribute of `{field}` is not needed because `{related_table}` does not have a compound primary key",
+                    field = field,
+                    related_table = related_table.name
+                ),
+                position,
+            ));
+            errors.push(SqlError::new_help(
+                "remove the #[foreign_key] attribute",
+                position,
+            ));
+        },
+        Some(fields) => {
+            if fields.len() != primary_key_fields.len() {
+                errors.push(SqlError::new(
+                    &format!("the foreign key `{field}` contains {count} fields, but the primary key ({primary_key}) of `{related_table}` contains {primary_key_count} fields",
+                        field = field,
+                        count = fields.len(),
+                        primary_key = primary_key_fields.join(", "),
+                        related_table = related_table.name,
+                        primary_key_count = primary_key_fields.len()
+                    ),
+                    position,
+                ));
+            }
+            else {
+                // NOTE: The foreign key field itself has the type of the related table.
+                for (foreign_key_field, primary_key_field) in fields.iter().zip(&primary_key_fields).filter(|&(foreign_key_field, _)| foreign_key_field != field) {
+                    let foreign_key_type = table.fields.get(foreign_key_field).map(|typ| typ.node.clone());
+                    let primary_key_type =
+                        match related_table.fields.get(primary_key_field).map(|typ| typ.node.clone()) {
+                            Some(Type::Serial) => Some(Type::I32),
+                            typ => typ,
+                        };
+                    if let (Some(foreign_key_type), Some(primary_key_type)) = (foreign_key_type, primary_key_type) {
+                        if foreign_key_type != primary_key_type {
+                            errors.push(SqlError::new_with_code(
+                                &format!("mismatched types:\n expected `{expected_type}`,\n    found `{actual_type}`\n(the field `{foreign_key_field}` references `{related_table}.{primary_key_field}`)",
+                                    expected_type = primary_key_type,
+                                    actual_type = foreign_key_type,
+                                    foreign_key_field = foreign_key_field,
+                                    related_table = related_table.name,
+                                    primary_key_field = primary_key_field
+                                ),
+                                position,
+                                "E0308",
+                            ));
                         }
-                    },
-                    None => (), // TODO
+                    }
                 }
             }
-        }
+        },
+        None if primary_key_fields.len() > 1 => {
+            errors.push(SqlError::new(
+                &format!("the foreign key `{field}` references the compound primary key ({primary_key}) of `{related_table}`, but has no #[foreign_key] attribute",
+                    field = field,
+                    primary_key = primary_key_fields.join(", "),
+                    related_table = related_table.name
+                ),
+                position,
+            ));
+            errors.push(SqlError::new_help(
+                &format!("add a #[foreign_key] attribute with a field of `{table}` for each field of this key, like #[foreign_key(field1, {field})]",
+                    table = table.name,
+                    field = field
+                ),
+                position,
+            ));
+        },
+        None => (),
     }
 }
 
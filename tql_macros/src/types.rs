@@ -19,7 +19,7 @@
 
 use std::fmt::{self, Display, Formatter};
 
-use rustc::middle::ty::{TypeAndMut, TyS, TypeVariants};
+use rustc::middle::ty::{Ty, TypeAndMut, TyS, TypeVariants};
 use syntax::ast::{AngleBracketedParameterData, FloatTy, IntTy, Path, PathParameters};
 use syntax::ast::Expr_::ExprLit;
 use syntax::ast::LitIntType::{SignedIntLit, UnsignedIntLit, UnsuffixedIntLit};
@@ -29,11 +29,12 @@ use syntax::ast::Ty_::TyPath;
 
 use ast::Expression;
 use gen::ToSql;
-use state::{get_primary_key_field, tables_singleton};
+use state::{get_primary_key_field, get_primary_key_type, tables_singleton};
 
 /// A field type.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Type {
+    Array(Box<Type>),
     Bool,
     ByteString,
     Char,
@@ -46,6 +47,9 @@ pub enum Type {
     I32,
     I64,
     LocalDateTime,
+    /// The related table name of a `ManyToMany` field (it is not a column, the relation is
+    /// stored in a junction table).
+    ManyToMany(String),
     NaiveDate,
     NaiveDateTime,
     NaiveTime,
@@ -54,12 +58,20 @@ pub enum Type {
     String,
     UnsupportedType(String),
     UTCDateTime,
+    Uuid,
+}
+
+impl Default for Type {
+    fn default() -> Type {
+        Type::Generic
+    }
 }
 
 impl Display for Type {
     /// Get a string representation of the SQL `Type` for display in error messages.
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         let typ = match *self {
+            Type::Array(ref typ) => "Vec<".to_owned() + &typ.to_string() + ">",
             Type::Bool => "bool".to_owned(),
             Type::ByteString => "Vec<u8>".to_owned(),
             Type::Char => "char".to_owned(),
@@ -72,14 +84,16 @@ impl Display for Type {
             Type::I32 => "i32".to_owned(),
             Type::I64 => "i64".to_owned(),
             Type::LocalDateTime => "chrono::datetime::DateTime<chrono::offset::local::Local>".to_owned(),
-            Type::NaiveDate => "chrono::naive::datetime::NaiveDate".to_owned(),
+            Type::ManyToMany(ref typ) => "Vec<".to_owned() + typ + ">",
+            Type::NaiveDate => "chrono::naive::date::NaiveDate".to_owned(),
             Type::NaiveDateTime => "chrono::naive::datetime::NaiveDateTime".to_owned(),
-            Type::NaiveTime => "chrono::naive::datetime::NaiveTime".to_owned(),
+            Type::NaiveTime => "chrono::naive::time::NaiveTime".to_owned(),
             Type::Nullable(ref typ) => "Option<".to_owned() + &typ.to_string() + ">",
             Type::Serial => "i32".to_owned(),
             Type::String => "String".to_owned(),
             Type::UnsupportedType(_) => "".to_owned(),
             Type::UTCDateTime => "chrono::datetime::DateTime<chrono::offset::utc::UTC>".to_owned(),
+            Type::Uuid => "uuid::Uuid".to_owned(),
         };
         write!(f, "{}", typ)
     }
@@ -108,10 +122,20 @@ impl<'a> From<&'a Path> for Type {
                 "i16" => Type::I16,
                 "i32" => Type::I32,
                 "i64" => Type::I64,
-                "ForeignKey" => match get_type_parameter(&segments[0].parameters) {
-                    Some(ty) => Type::Custom(ty),
+                "ForeignKey" => match get_type_parameter_as_path(&segments[0].parameters) {
+                    // NOTE: A table referring to itself uses a ForeignKey<Box<T>>.
+                    Some(path) if path.segments[0].identifier.to_string() == "Box" =>
+                        match get_type_parameter(&path.segments[0].parameters) {
+                            Some(ty) => Type::Custom(ty),
+                            None => Type::UnsupportedType("ForeignKey<Box>".to_owned()),
+                        },
+                    Some(path) => Type::Custom(path.segments[0].identifier.to_string()),
                     None => Type::UnsupportedType("ForeignKey".to_owned()),
                 },
+                "ManyToMany" => match get_type_parameter(&segments[0].parameters) {
+                    Some(ty) => Type::ManyToMany(ty),
+                    None => Type::UnsupportedType("ManyToMany".to_owned()),
+                },
                 "NaiveDate" => Type::NaiveDate,
                 "NaiveDateTime" => Type::NaiveDateTime,
                 "NaiveTime" => Type::NaiveTime,
@@ -136,6 +160,7 @@ impl<'a> From<&'a Path> for Type {
                 "String" => {
                     Type::String
                 },
+                "Uuid" => Type::Uuid,
                 "Vec" => match get_type_parameter(&segments[0].parameters) {
                     Some(ty) => match ty.as_ref() {
                         "u8" => Type::ByteString,
@@ -229,6 +254,15 @@ impl<'tcx> PartialEq<TyS<'tcx>> for Type {
                     TypeVariants::TyStr => {
                         *typ == Type::String
                     },
+                    TypeVariants::TySlice(element_type) => {
+                        match *typ {
+                            Type::Array(ref typ) => **typ == *element_type,
+                            _ => false,
+                        }
+                    },
+                    TypeVariants::TyStruct(def, sub) if def.struct_variant().name.to_string() == "Vec" => {
+                        is_array_of(typ, sub.types.iter().next())
+                    },
                     _ => false,
                 }
             },
@@ -254,6 +288,8 @@ impl<'tcx> PartialEq<TyS<'tcx>> for Type {
                     "NaiveDateTime" => *typ == Type::NaiveDateTime,
                     "NaiveTime" => *typ == Type::NaiveTime,
                     "String" => *typ == Type::String,
+                    "Uuid" => *typ == Type::Uuid,
+                    "Vec" => is_array_of(typ, sub.types.iter().next()),
                     struct_type => *typ == Type::Custom(struct_type.to_owned()),
                 }
             },
@@ -296,18 +332,63 @@ fn get_type_parameter_from_type(typ: &TypeVariants) -> Option<String> {
     }
 }
 
+/// Get the name of the related table if `typ` is a foreign key (which can be nullable).
+pub fn get_related_table_name(typ: &Type) -> Option<&String> {
+    match *typ {
+        Type::Custom(ref table_name) | Type::Nullable(box Type::Custom(ref table_name)) => Some(table_name),
+        _ => None,
+    }
+}
+
+/// Check if the Rust type corresponding to `typ` implements the `Default` trait.
+pub fn has_default(typ: &Type) -> bool {
+    match *typ {
+        Type::Array(_) | Type::Bool | Type::ByteString | Type::Char | Type::Custom(_) | Type::F32 | Type::F64 |
+            Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::ManyToMany(_) | Type::Nullable(_) | Type::Serial |
+            Type::String => true,
+        Type::Generic | Type::LocalDateTime | Type::NaiveDate | Type::NaiveDateTime | Type::NaiveTime |
+            Type::UnsupportedType(_) | Type::UTCDateTime | Type::Uuid => false,
+    }
+}
+
+/// Check if `typ` is an array whose elements are of type `element_type`.
+fn is_array_of(typ: &Type, element_type: Option<&Ty>) -> bool {
+    match (typ, element_type) {
+        (&Type::Array(ref typ), Some(element_type)) => **typ == **element_type,
+        _ => false,
+    }
+}
+
+/// Check if `typ` is a number type (that can be used in arithmetic operations).
+pub fn is_number(typ: &Type) -> bool {
+    match *typ {
+        Type::F32 | Type::F64 | Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::Serial => true,
+        _ => false,
+    }
+}
+
 /// Convert a `Type` to its SQL representation.
 fn type_to_sql(typ: &Type, mut nullable: bool) -> String {
     let sql_type =
         match *typ {
+            Type::Array(ref typ) => type_to_sql(&*typ, true) + "[]",
             Type::Bool => "BOOLEAN".to_owned(),
             Type::ByteString => "BYTEA".to_owned(),
             Type::I8 | Type::Char => "CHARACTER(1)".to_owned(),
             Type::Custom(ref related_table_name) => {
                 let tables = tables_singleton();
                 if let Some(table) = tables.get(related_table_name) {
-                    let primary_key_field = get_primary_key_field(table).unwrap();
-                    "INTEGER REFERENCES ".to_owned() + &related_table_name + "(" + &primary_key_field + ")"
+                    // NOTE: The column has the type of the primary key of the related table.
+                    let column_type = get_primary_key_type(table).map_or("".to_owned(), |typ| type_to_sql_name(&typ));
+                    if table.primary_key.len() > 1 {
+                        // NOTE: The FOREIGN KEY constraint referencing a compound primary key is
+                        // added to the table.
+                        column_type
+                    }
+                    else {
+                        let primary_key_field = get_primary_key_field(table).unwrap();
+                        column_type + " REFERENCES " + &related_table_name + "(" + &primary_key_field + ")"
+                    }
                 }
                 else {
                     "".to_owned()
@@ -321,6 +402,7 @@ fn type_to_sql(typ: &Type, mut nullable: bool) -> String {
             Type::I32 => "INTEGER".to_owned(),
             Type::I64 => "BIGINT".to_owned(),
             Type::LocalDateTime => "TIMESTAMP WITH TIME ZONE".to_owned(),
+            Type::ManyToMany(_) => "".to_owned(), // NOTE: This is a junction table, not a column.
             Type::NaiveDate => "DATE".to_owned(),
             Type::NaiveDateTime => "TIMESTAMP".to_owned(),
             Type::NaiveTime => "TIME".to_owned(),
@@ -332,6 +414,7 @@ fn type_to_sql(typ: &Type, mut nullable: bool) -> String {
             Type::String => "CHARACTER VARYING".to_owned(),
             Type::UnsupportedType(_) => "".to_owned(), // TODO: should panic.
             Type::UTCDateTime => "TIMESTAMP WITH TIME ZONE".to_owned(),
+            Type::Uuid => "UUID".to_owned(),
         };
 
     if nullable {
@@ -342,6 +425,22 @@ fn type_to_sql(typ: &Type, mut nullable: bool) -> String {
     }
 }
 
+/// Convert the `Type` of a primary key field to its SQL representation.
+/// An integer primary key is generated by the database.
+pub fn primary_key_type_to_sql(typ: &Type) -> String {
+    match *typ {
+        Type::I32 | Type::Serial => "SERIAL PRIMARY KEY NOT NULL".to_owned(),
+        Type::I64 => "BIGSERIAL PRIMARY KEY NOT NULL".to_owned(),
+        ref typ => type_to_sql_name(typ) + " PRIMARY KEY NOT NULL",
+    }
+}
+
+/// Get the SQL name of the `Type` without the NOT NULL constraint (for instance, to use it in a
+/// CAST).
+pub fn type_to_sql_name(typ: &Type) -> String {
+    type_to_sql(typ, true)
+}
+
 impl ToSql for Type {
     fn to_sql(&self) -> String {
         type_to_sql(self, false)